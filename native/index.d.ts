@@ -1,42 +1,262 @@
 /* auto-generated by NAPI-RS */
 /* eslint-disable */
-/** RocksDB-based build cache */
+/**
+ * RocksDB-based build cache.
+ * Clones share the same database, so async methods run on a clone.
+ */
 export declare class BuildCache {
-  /** Create a new build cache at the specified path */
-  constructor(cachePath: string)
-  /** Get a value from the cache */
+  /**
+   * Create a new build cache at the specified path.
+   * Entries are kept in the backend named by `NEXXO_CACHE_BACKEND`, RocksDB by default.
+   * Entries written under another schema or producer version are dropped.
+   */
+  constructor(cachePath: string, options?: BuildCacheOptions | undefined | null)
+  /**
+   * Get a value from the local cache. This runs on the JS thread, so it
+   * never reads through to the remote cache; use `getAsync` for that.
+   */
   get(key: string): string | null
-  /** Set a value in the cache */
+  /**
+   * Get many values from the local cache at once, in the order of `keys`.
+   * On RocksDB, lookups go through a single multi-get.
+   */
+  multiGet(keys: Array<string>): Array<string | undefined | null>
+  /**
+   * Async version of `get` that runs on the blocking thread pool and reads
+   * through to the remote cache on a local miss
+   */
+  getAsync(key: string): Promise<string | null>
+  /**
+   * Async version of `multi_get` that runs on the blocking thread pool and
+   * reads through to the remote cache on local misses
+   */
+  multiGetAsync(keys: Array<string>): Promise<Array<string | undefined | null>>
+  /**
+   * Take a consistent, read-only view of the cache. Writes made after this
+   * call, by this or any other build, are not visible through the snapshot.
+   * Needs the RocksDB backend.
+   */
+  snapshot(): CacheSnapshot
+  /** Set a value in the cache and queue it for upload to the remote cache */
   set(key: string, value: string): void
+  /** Async version of `set` that runs on the blocking thread pool */
+  setAsync(key: string, value: string): Promise<void>
   /** Delete a value from the cache */
   delete(key: string): void
   /** Check if a key exists in the cache */
   has(key: string): boolean
+  /** Get the metadata stored with an entry, without counting a hit or refreshing its access time */
+  getEntryMetadata(key: string): EntryMetadata | null
+  /**
+   * Number of entries dropped when the cache was opened because they were
+   * written under another schema or producer version
+   */
+  get invalidatedEntries(): number
+  /** How the cache recovered from a database it could not open, if it had to */
+  get recovery(): CacheRecovery | null
+  /** Turn lookup tracing on or off. Lookups already traced are kept. */
+  setTracing(enabled: boolean): void
+  /** Whether lookups are being traced */
+  get tracing(): boolean
+  /**
+   * Traced lookups with an explanation for each miss, optionally only
+   * those for `key`. Empty unless tracing is on.
+   */
+  explain(key?: string | undefined | null): ExplainReport
+  /** Forget all traced lookups */
+  clearTrace(): void
   /** Batch set multiple key-value pairs */
   batchSet(entries: Record<string, string>): void
+  /** Async version of `batch_set` that runs on the blocking thread pool */
+  batchSetAsync(entries: Record<string, string>): Promise<void>
+  /**
+   * Start staging writes for a build. Nothing is visible to the cache
+   * until the transaction is committed.
+   */
+  beginTransaction(): CacheTransaction
   /** Clear all entries for a specific target (dev/prod/lib) */
   clearTarget(target: string): number
   /** Clear all cache entries */
   clearAll(): void
-  /** Get cache statistics */
+  /**
+   * Async version of `clear_all` that runs on the blocking thread pool.
+   * Resolves to the number of entries removed.
+   */
+  clearAllAsync(): Promise<number>
+  /**
+   * Get cache statistics. On RocksDB, entry counts and sizes are estimates,
+   * so this never scans the database; other backends count every entry.
+   */
   getStats(): CacheStats
-  /** Compact the database to reclaim space */
+  /**
+   * Wait for queued remote uploads to finish.
+   * Returns false if uploads were still pending when the timeout elapsed.
+   * Uploads still queued when the cache is dropped carry on in the background
+   * only as long as the process lives, so call this before exiting.
+   */
+  flushRemote(timeoutMs?: number | undefined | null): boolean
+  /** Get remote cache statistics, if a remote cache is configured */
+  getRemoteStats(): RemoteCacheStats | null
+  /** Export the selected namespaces (all when omitted) to a portable archive */
+  export(path: string, namespaces?: Array<string> | undefined | null): ExportReport
+  /**
+   * Merge an archive into the cache. Entries that differ from the local
+   * value are reported as conflicts and only replaced when `overwrite` is set.
+   * Entries written under another schema or producer version are skipped.
+   */
+  import(path: string, overwrite?: boolean | undefined | null): ImportReport
+  /**
+   * Check every entry against its checksum. Corrupt entries are deleted so
+   * they get rebuilt; namespaces whose files cannot be read are dropped.
+   * Needs the RocksDB backend.
+   */
+  verify(): VerifyReport
+  /**
+   * Take a checkpoint of the cache in `dir`, which must not exist yet.
+   * A relative `dir` is placed under `{cachePath}.checkpoints`. SST files
+   * are hard-linked where the filesystem allows, so this is cheap.
+   * Needs the RocksDB backend.
+   */
+  checkpoint(dir: string): CheckpointInfo
+  /** Checkpoints under `{cachePath}.checkpoints`, oldest first */
+  listCheckpoints(): Array<CheckpointInfo>
+  /**
+   * Replace the contents of the cache with a checkpoint taken by `checkpoint`.
+   * Fails while snapshots, open transactions or async calls are using the
+   * cache. Entries from another schema or producer version are dropped, as
+   * when opening the cache. If the previous cache cannot be reopened after
+   * a failed restore, the cache serves it read-only, or nothing if even that
+   * fails, and `accessMode` becomes ReadOnly.
+   */
+  restore(dir: string): void
+  /** Mode the cache was opened in. `Auto` resolves to Primary or Secondary. */
+  get accessMode(): CacheAccessMode
+  /**
+   * Catch up with the primary's writes. Only meaningful for Secondary
+   * instances; a Primary is always current.
+   */
+  catchUp(): void
+  /**
+   * Compact the database to reclaim space. Every file is rewritten, so
+   * existing entries pick up the current compression settings.
+   * Other backends have nothing to compact.
+   */
   compact(): void
+  /**
+   * Async version of `compact` that runs on the blocking thread pool,
+   * calling `on_progress` as each namespace finishes
+   */
+  compactAsync(onProgress?: ((arg: CompactProgress) => void) | undefined | null): Promise<void>
   /** Close the cache (cleanup) */
   close(): void
 }
 
 /** Tokio-based build orchestrator */
 export declare class BuildOrchestrator {
-  /** Create a new orchestrator with specified parallelism */
-  constructor(parallelism?: number | undefined | null)
-  /** Log a build event */
-  logEvent(stage: string, message: string, durationMs?: number | undefined | null): Promise<void>
-  /** Get all logged events */
-  getEvents(): Promise<Array<BuildEvent>>
+  /**
+   * Create a new orchestrator with specified parallelism, keeping the
+   * last `event_history` events (default: 1000)
+   */
+  constructor(parallelism?: number | undefined | null, eventHistory?: number | undefined | null)
+  /**
+   * Log a build event (default level: Info). `stage` is a `BuildStage`, or
+   * its name in any case, such as `"execute"` or `"determinism-check"`.
+   */
+  logEvent(stage: BuildStage | string, message: string, durationMs?: number | undefined | null, level?: EventLevel | undefined | null): Promise<void>
+  /** Get the stored events, oldest first, optionally only those matching `filter` */
+  getEvents(filter?: EventFilter | undefined | null): Promise<Array<BuildEvent>>
+  /**
+   * Call `callback` with every event matching `filter` as it is logged.
+   * Returns an id for `unsubscribe`.
+   */
+  subscribe(callback: (event: BuildEvent) => void, filter?: EventFilter | undefined | null): number
+  /** Stop a subscription. Returns false if there was none with this id. */
+  unsubscribe(id: number): boolean
   /** Clear all events */
   clearEvents(): Promise<void>
-  /** Execute tasks in parallel */
+  /**
+   * Run a graph of native and JS tasks on the orchestrator's runtime. Each
+   * task starts as soon as its dependencies have succeeded; tasks depending
+   * on a failed task are skipped. Fails only if the graph itself is invalid;
+   * a build stopped by `cancel` resolves with status Cancelled, one whose
+   * stage hook throws with status Failed, and one whose outputs differ
+   * between runs under `checkDeterminism` with status Nondeterministic.
+   */
+  run(tasks: Array<TaskSpec>, options?: RunOptions | undefined | null): Promise<BuildReport>
+  /**
+   * A fresh id of the form `build-{n}`, never handed out before. Pass it as
+   * `RunOptions.buildId` to be able to `cancel` or `prioritize` the build
+   * before `run` resolves.
+   */
+  nextBuildId(): string
+  /**
+   * Cancel a running build: no new tasks start, running native tasks are
+   * told to stop, tasks waiting to retry are cancelled, and its `run` call
+   * resolves with status Cancelled once the tasks already running have
+   * returned. A build whose tasks had all finished completes as usual.
+   * Returns false if no build with this id is running.
+   */
+  cancel(buildId: string): boolean
+  /**
+   * Raise a task of a running build, and every task it depends on, to at
+   * least `priority` so it starts ahead of other queued work. Tasks that
+   * already started are unaffected, as is a determinism rehearsal.
+   * Returns false if no build with this id is running.
+   */
+  prioritize(buildId: string, taskId: string, priority: number): boolean
+  /**
+   * Call `hook` whenever a build enters a stage. Hooks run in the order
+   * they were registered and the build waits for each. If one throws or
+   * rejects, no more tasks start and the build goes straight to Emit, then
+   * resolves with status Failed and the hook's error in `error`.
+   */
+  onStageEnter(hook: (event: StageEvent) => void | Promise<void>): void
+  /**
+   * Call `hook` whenever a build leaves a stage, with the time spent in
+   * it. Like `onStageEnter`, the build waits for each hook.
+   */
+  onStageLeave(hook: (event: StageEvent) => void | Promise<void>): void
+  /** Remove all stage hooks. Builds already running keep theirs. */
+  clearStageHooks(): void
+  /** Start or stop recording spans for `exportTrace` */
+  setTracing(enabled: boolean): void
+  /** Whether spans are being recorded */
+  get tracing(): boolean
+  /**
+   * Record a cache lookup made for a build, e.g. by a JS task, that took
+   * `duration_ms` and just finished. Ignored while tracing is off.
+   */
+  traceCacheLookup(key: string, outcome: LookupOutcome, durationMs?: number | undefined | null): void
+  /**
+   * Draw every lookup through `cache` (and its clones) on this timeline
+   * while tracing is on, replacing any orchestrator it was drawn on
+   * before. Pass `enabled: false` to stop.
+   */
+  traceCache(cache: BuildCache, enabled?: boolean | undefined | null): void
+  /**
+   * Recorded spans as Chrome Trace Event JSON, for chrome://tracing or
+   * Perfetto. Also written to `path` if given.
+   */
+  exportTrace(path?: string | undefined | null): string
+  /** Drop all recorded spans */
+  clearTrace(): void
+  /**
+   * Send every finished build to an OpenTelemetry collector over OTLP/HTTP,
+   * or stop sending with `null`. Builds are sent in the background, so
+   * `run` does not wait for the collector; export failures are logged as
+   * Warn events. Builds already queued are still sent after a change.
+   */
+  setOtlpExporter(options?: OtlpExporterOptions | undefined | null): void
+  /**
+   * Wait until the current OTLP exporter has sent every finished build,
+   * resolving to false if some were still queued after `timeout_ms`
+   * (default: 30000). Resolves to true when no exporter is set.
+   */
+  flushOtlp(timeoutMs?: number | undefined | null): Promise<boolean>
+  /**
+   * Benchmark the runtime with `task_count` independent 10ms tasks.
+   * Use `run` for real work.
+   */
   executeParallel(taskCount: number): Promise<OrchestratorStats>
   /** Process items in parallel using Rayon */
   processParallelSync(items: Array<string>): Array<string>
@@ -52,6 +272,64 @@ export declare class BuildOrchestrator {
   shutdown(): void
 }
 
+/** Builds unambiguous cache keys that fold in config, environment and Nexxo version */
+export declare class CacheKeyBuilder {
+  constructor(options?: CacheKeyOptions | undefined | null)
+  /** Key for a source file's transformed output */
+  input(filePath: string, contentHash: string): string
+  /** Key for a module graph */
+  graph(graphHash: string): string
+  /** Key for a build plan */
+  plan(planHash: string, target: string): string
+  /** Key for an emitted artifact */
+  artifact(artifactId: string, target: string): string
+  /** Key in any other namespace. `plan` and `artifact` keys need a target. */
+  key(namespace: string, components: Array<string>, target?: string | undefined | null): string
+  /** Fingerprint of the selected environment values */
+  get envFingerprint(): string
+}
+
+/** A frozen, read-only view of a `BuildCache` */
+export declare class CacheSnapshot {
+  /** Get a value as it was when the snapshot was taken */
+  get(key: string): string | null
+  /** Get many values as they were when the snapshot was taken, in the order of `keys` */
+  multiGet(keys: Array<string>): Array<string | undefined | null>
+  /** When the snapshot was taken (ms since epoch) */
+  get createdAt(): number
+  /**
+   * Release the snapshot so RocksDB can drop the data it pins.
+   * Later reads fail; releasing twice is a no-op.
+   */
+  release(): void
+}
+
+/** Pending writes for one build, committed atomically or discarded */
+export declare class CacheTransaction {
+  /** Stage a value to be written on commit */
+  set(key: string, value: string): void
+  /** Stage many values to be written on commit */
+  batchSet(entries: Record<string, string>): void
+  /** Stage a delete to be applied on commit */
+  delete(key: string): void
+  /**
+   * Read a value as this build sees it: staged writes first, then the cache.
+   * Does not count as a cache hit or miss.
+   */
+  get(key: string): string | null
+  /** Number of staged writes and deletes */
+  get pending(): number
+  /**
+   * Write every staged change in one atomic batch and queue the new
+   * entries for upload. Returns the number of changes applied.
+   */
+  commit(): number
+  /** Discard every staged change. Returns the number of changes dropped. */
+  rollback(): number
+  /** Whether the transaction can still stage writes */
+  get isOpen(): boolean
+}
+
 /**
  * High-performance graph analyzer using Integer-based Adjacency List
  * Uses string interning to map Strings <-> usize IDs for O(1) lookups and cache efficiency
@@ -119,15 +397,59 @@ export declare function benchmarkParallelism(itemCount: number): Record<string,
 /** Benchmark function to compare performance */
 export declare function benchmarkTransform(code: string, iterations: number): number
 
+/** Build cache configuration */
+export interface BuildCacheOptions {
+  /** Remote cache to read through and write behind to */
+  remote?: RemoteCacheOptions
+  /**
+   * Version of the tool producing entries, e.g. Nexxo plus transformer version.
+   * Entries written by a different producer are invalidated.
+   */
+  producerVersion?: string
+  /** How to open the cache directory (default: Primary) */
+  accessMode?: CacheAccessMode
+  /** Block compression (default: LZ4) */
+  compression?: CompressionOptions
+  /** Record every lookup for `explain()` (default: false) */
+  trace?: boolean
+}
+
 /** Build event for structured logging */
 export interface BuildEvent {
-  stage: string
+  stage: BuildStage
+  level: EventLevel
   message: string
   timestamp: number
   durationMs?: number
   metadata?: string
 }
 
+/** Results of a build, with tasks in the order they were submitted */
+export interface BuildReport {
+  buildId: string
+  status: BuildStatus
+  durationMs: number
+  succeeded: number
+  failed: number
+  skipped: number
+  cancelled: number
+  /** Sum of `queuedMs` over all tasks */
+  totalQueuedMs: number
+  maxQueuedMs: number
+  /** Failed tasks, in the order they were submitted */
+  failures: Array<TaskFailure>
+  /**
+   * Why the build failed other than through a task, e.g. a stage hook
+   * that threw
+   */
+  error?: string
+  /** Stages the build went through, in order */
+  stages: Array<StageTiming>
+  /** Only set if `checkDeterminism` was on and the check ran */
+  determinism?: DeterminismReport
+  tasks: Array<TaskResult>
+}
+
 /** Build stage for orchestration */
 export declare const enum BuildStage {
   Init = 'Init',
@@ -138,13 +460,99 @@ export declare const enum BuildStage {
   Emit = 'Emit'
 }
 
+/** Outcome of a whole build */
+export declare const enum BuildStatus {
+  Succeeded = 'Succeeded',
+  /** At least one task failed */
+  Failed = 'Failed',
+  /** Stopped by `cancel` before every task had run */
+  Cancelled = 'Cancelled',
+  /**
+   * Every task succeeded, but the determinism check found tasks whose
+   * output differed between runs
+   */
+  Nondeterministic = 'Nondeterministic'
+}
+
+/** How a `BuildCache` opens its directory */
+export declare const enum CacheAccessMode {
+  /** Read-write; fails if another process holds the cache */
+  Primary = 'Primary',
+  /** Read-only view of the cache as it was when opened */
+  ReadOnly = 'ReadOnly',
+  /** Read-only instance that sees the primary's writes after `catchUp()` */
+  Secondary = 'Secondary',
+  /** Primary if the lock is free, otherwise Secondary */
+  Auto = 'Auto'
+}
+
+/** Block compression algorithm */
+export declare const enum CacheCompression {
+  None = 'None',
+  Lz4 = 'Lz4',
+  /** zstd, with a dictionary trained for each namespace */
+  Zstd = 'Zstd'
+}
+
+/** Inputs folded into every key a builder produces */
+export interface CacheKeyOptions {
+  /** Fingerprint of the resolved config, see `fingerprintConfig` */
+  configFingerprint?: string
+  /** Environment variables whose values should invalidate keys, read once when the builder is created */
+  env?: Array<string>
+  /** Nexxo version (default: the native crate version) */
+  nexxoVersion?: string
+}
+
+/** One traced lookup */
+export interface CacheLookup {
+  key: string
+  outcome: LookupOutcome
+  timestamp: number
+  /** Present for misses */
+  miss?: MissExplanation
+}
+
+/** How the cache recovered from a database it could not open */
+export declare const enum CacheRecovery {
+  /** RocksDB repaired the database in place; some entries may be gone */
+  Repaired = 'Repaired',
+  /** The database was moved aside and a fresh one created */
+  Rebuilt = 'Rebuilt'
+}
+
 /** Cache statistics */
 export interface CacheStats {
+  /** RocksDB estimate, summed over namespaces */
   totalEntries: number
   hits: number
   misses: number
   hitRate: number
   sizeBytes: number
+  /**
+   * Uncompressed over on-disk size of flushed data, over all namespaces.
+   * Entries compressed with a namespace dictionary count at their compressed size.
+   */
+  compressionRatio: number
+  bytesRead: number
+  bytesWritten: number
+  /** Entries that failed their checksum on read and were treated as misses */
+  corruptedEntries: number
+  namespaces: Array<NamespaceStats>
+  targets: Array<TargetStats>
+}
+
+/** A checkpoint of a `BuildCache` */
+export interface CheckpointInfo {
+  /** Directory name of the checkpoint */
+  name: string
+  path: string
+  createdAt: number
+  schemaVersion: number
+  producerVersion: string
+  /** RocksDB estimate at the time the checkpoint was taken */
+  estimatedEntries: number
+  sizeBytes: number
 }
 
 export interface CircularDependency {
@@ -152,24 +560,142 @@ export interface CircularDependency {
   entryPoint: string
 }
 
+/** Progress of a `compactAsync` call, reported once per namespace */
+export interface CompactProgress {
+  /** Namespace that just finished compacting */
+  namespace: string
+  completed: number
+  total: number
+}
+
+/** Compression settings for `BuildCacheOptions.compression` */
+export interface CompressionOptions {
+  /** Algorithm (default: Lz4) */
+  algorithm?: CacheCompression
+  /** zstd level from 1 to 22 (default: 3). Only valid with Zstd. */
+  level?: number
+  /**
+   * Maximum size of each namespace's zstd dictionary (default: 16 KiB).
+   * 0 compresses every entry on its own.
+   */
+  dictionaryBytes?: number
+}
+
 /** Create cache key for artifact */
 export declare function createArtifactKey(artifactId: string, target: string): string
 
 /** Create cache key for graph hash */
 export declare function createGraphKey(graphHash: string): string
 
-/** Create cache key for input fingerprint */
+/**
+ * Create cache key for input fingerprint.
+ * Prefer `CacheKeyBuilder`, whose keys stay unambiguous when paths contain `:`.
+ */
 export declare function createInputKey(filePath: string, contentHash: string): string
 
 /** Create cache key for plan hash */
 export declare function createPlanKey(planHash: string, target: string): string
 
+/** Split a cache key into its namespace, target, components and context */
+export declare function decodeCacheKey(key: string): DecodedCacheKey
+
+/** A key split back into its parts */
+export interface DecodedCacheKey {
+  namespace: string
+  target?: string
+  components: Array<string>
+  /** Context section; absent for keys not made by `CacheKeyBuilder` */
+  nexxoVersion?: string
+  configFingerprint?: string
+  envFingerprint?: string
+}
+
+/** A task whose output differed between the two runs */
+export interface DeterminismMismatch {
+  taskId: string
+  /** `TaskSpec.module`, if the task has one */
+  module?: string
+  stage: BuildStage
+  /** Stable id of the output of the earlier run; absent if it failed */
+  first?: string
+  /** Stable id of the output of the later run; absent if it failed */
+  second?: string
+}
+
+/** Outcome of a build's determinism check */
+export interface DeterminismReport {
+  /** Whether every task run twice produced the same output both times */
+  deterministic: boolean
+  /** Tasks run in both the rehearsal and the build itself */
+  checked: number
+  /** The earliest mismatch in dependency order, where the runs diverged */
+  firstDivergence?: DeterminismMismatch
+  /** Every mismatch, in dependency order */
+  mismatches: Array<DeterminismMismatch>
+}
+
+/** Metadata stored alongside every cache entry */
+export interface EntryMetadata {
+  createdAt: number
+  lastAccess: number
+  size: number
+  producerVersion: string
+  schemaVersion: number
+}
+
+/** Which events to receive or return */
+export interface EventFilter {
+  /** Only events of these stages (default: all) */
+  stages?: Array<BuildStage>
+  /** Only events of this level or above (default: Debug) */
+  minLevel?: EventLevel
+}
+
+/** Severity of a build event, least severe first */
+export declare const enum EventLevel {
+  Debug = 'Debug',
+  Info = 'Info',
+  Warn = 'Warn',
+  Error = 'Error'
+}
+
+/** Traced lookups, as returned by `explain` */
+export interface ExplainReport {
+  hits: number
+  misses: number
+  /** Older lookups dropped to keep the trace bounded */
+  discarded: number
+  lookups: Array<CacheLookup>
+}
+
+/** Result of exporting the cache to an archive */
+export interface ExportReport {
+  entries: number
+  namespaces: Array<string>
+  uncompressedBytes: number
+  archiveBytes: number
+}
+
+/** Whether a build goes on after a task fails */
+export declare const enum FailureMode {
+  /** Start no new tasks after the first failure */
+  FailFast = 'FailFast',
+  /** Run every task that does not depend on a failed one */
+  ContinueOnError = 'ContinueOnError'
+}
+
 /**
  * Fast content hashing for cache keys
  * Uses XXH3 (ultra-fast non-cryptographic hash)
  */
 export declare function fastHash(content: string): string
 
+/**
+ * Stable fingerprint of a JSON config: object keys are sorted before hashing,
+ * so formatting and key order do not change it
+ */
+export declare function fingerprintConfig(configJson: string): string
+
 /** Helper function to get optimal parallelism */
 export declare function getOptimalParallelism(): number
 
@@ -199,6 +725,78 @@ export interface GraphNode {
 /** Simple function to test native bindings */
 export declare function helloRust(): string
 
+/** Result of importing an archive into the cache */
+export interface ImportReport {
+  imported: number
+  unchanged: number
+  conflicts: number
+  /** Entries skipped because they were written under another schema or producer version */
+  incompatible: number
+  /** Keys whose archived value differed from the local value */
+  conflictingKeys: Array<string>
+  namespaces: Array<string>
+}
+
+/** One key part that differs between a missed key and its nearest neighbour */
+export interface KeyDifference {
+  /** Part name, e.g. `contentHash`, `target` or `config` */
+  component: string
+  /** Value in the key that was looked up; empty if it has no such part */
+  expected: string
+  /** Value in the nearest existing key; empty if it has no such part */
+  found: string
+}
+
+/** How a traced lookup ended */
+export declare const enum LookupOutcome {
+  LocalHit = 'LocalHit',
+  /** Fetched from the remote cache and stored locally */
+  RemoteHit = 'RemoteHit',
+  Miss = 'Miss'
+}
+
+/** Explanation of a missed lookup */
+export interface MissExplanation {
+  reason: MissReason
+  /** Closest existing key with the same structured prefix */
+  nearestKey?: string
+  differences: Array<KeyDifference>
+}
+
+/** Why a lookup missed */
+export declare const enum MissReason {
+  /** No entry under this key */
+  NotFound = 'NotFound',
+  /** An entry existed but was written by another schema or producer version */
+  Incompatible = 'Incompatible',
+  /** An entry existed but failed its checksum */
+  Corrupt = 'Corrupt'
+}
+
+/** Per-namespace cache statistics */
+export interface NamespaceStats {
+  namespace: string
+  /** RocksDB estimate, may be off for recently deleted keys */
+  estimatedEntries: number
+  sizeBytes: number
+  /** Uncompressed over on-disk size of flushed data; 1 until anything is flushed */
+  compressionRatio: number
+  hits: number
+  misses: number
+  bytesRead: number
+  bytesWritten: number
+}
+
+/** Built-in work a task can run */
+export declare const enum NativeTaskKind {
+  /** Does nothing; groups dependencies under one name */
+  Noop = 'Noop',
+  /** Hash `input` together with the outputs of the dependencies */
+  Hash = 'Hash',
+  /** Hash the contents of the file at `input` together with the outputs of the dependencies */
+  HashFile = 'HashFile'
+}
+
 /** Fast string operations for module resolution */
 export declare function normalizePath(path: string): string
 
@@ -208,11 +806,231 @@ export interface OrchestratorStats {
   completedTasks: number
   failedTasks: number
   totalDurationMs: number
+  /** Time tasks spent ready but waiting for a free slot */
+  totalQueuedMs: number
   parallelism: number
 }
 
+/** Where and how to send build spans */
+export interface OtlpExporterOptions {
+  /**
+   * Base URL of the collector's OTLP/HTTP receiver, e.g.
+   * `http://localhost:4318`; `/v1/traces` is added unless already there
+   */
+  endpoint: string
+  /** Extra headers sent with every request, e.g. for authentication */
+  headers?: Record<string, string>
+  /** `service.name` resource attribute (default: `nexxo`) */
+  serviceName?: string
+  /** `nexxo.project` resource attribute */
+  project?: string
+  /** `nexxo.target` resource attribute, e.g. `dev` or `prod` */
+  target?: string
+  /** `vcs.ref.head.revision` resource attribute */
+  gitSha?: string
+  /** Per-request timeout in milliseconds (default: 10000) */
+  timeoutMs?: number
+}
+
+/** Remote cache configuration */
+export interface RemoteCacheOptions {
+  /** Base URL of the cache server, e.g. `http://cache.internal:8080` */
+  url: string
+  /** Protocol spoken by the server (default: Bazel) */
+  protocol?: RemoteCacheProtocol
+  /** Bearer token sent as the `Authorization` header */
+  token?: string
+  /** Extra headers sent with every request */
+  headers?: Record<string, string>
+  /** Turborepo team id (`teamId` query parameter) */
+  teamId?: string
+  /** Turborepo team slug (`slug` query parameter) */
+  teamSlug?: string
+  /** Maximum number of uploads in flight (default: 4) */
+  maxConcurrentUploads?: number
+  /** Maximum number of downloads in flight (default: 8) */
+  maxConcurrentDownloads?: number
+  /** Per-request timeout in milliseconds (default: 10000) */
+  timeoutMs?: number
+  /** Only read from the remote cache, never upload */
+  readOnly?: boolean
+}
+
+/** Wire protocol spoken by the remote cache server */
+export declare const enum RemoteCacheProtocol {
+  Bazel = 'Bazel',
+  Turborepo = 'Turborepo'
+}
+
+/** Remote cache statistics */
+export interface RemoteCacheStats {
+  hits: number
+  misses: number
+  uploads: number
+  uploadFailures: number
+  downloadFailures: number
+  droppedUploads: number
+  pendingUploads: number
+}
+
+/** How often, and how soon, to run a failed task again */
+export interface RetryPolicy {
+  /** Runs after the first failed one (default: 0) */
+  retries?: number
+  /** Delay before the first retry (default: 100) */
+  backoffMs?: number
+  /** Each further delay is this many times the previous one (default: 2) */
+  backoffFactor?: number
+  /** Upper bound for the delay (default: 30000) */
+  maxBackoffMs?: number
+}
+
+/** Options for one `run` call */
+export interface RunOptions {
+  /**
+   * Id reported back in the build report and used by `cancel` and
+   * `prioritize` (default: a fresh `nextBuildId()`)
+   */
+  buildId?: string
+  /** Maximum number of tasks running at once (default: the orchestrator's parallelism) */
+  maxConcurrency?: number
+  /**
+   * Maximum number of JS callback tasks running at once, within
+   * `maxConcurrency` (default: `maxConcurrency`)
+   */
+  maxJsConcurrency?: number
+  /** Whether to go on after a task fails (default: ContinueOnError) */
+  failureMode?: FailureMode
+  /** Retries for tasks that have no policy of their own (default: none) */
+  retry?: RetryPolicy
+  /**
+   * Run the Plan and Execute stages twice, in a different order, and
+   * compare their outputs (default: false). `prioritize` does not affect
+   * the rehearsal, and rehearsed tasks record no spans for `exportTrace`.
+   */
+  checkDeterminism?: boolean
+}
+
 /**
  * Natively scan for imports/requires (Phase 4.2 Hot Path)
  * This is significantly faster than JS-based regex or full AST parsing
  */
 export declare function scanImports(code: string): Array<string>
+
+/** Argument passed to stage hooks */
+export interface StageEvent {
+  buildId: string
+  stage: BuildStage
+  /** Time spent in the stage; only set when leaving it */
+  durationMs?: number
+}
+
+/** When a stage of a build started and how long it took */
+export interface StageTiming {
+  stage: BuildStage
+  /** In ms since the build started */
+  startMs: number
+  durationMs: number
+}
+
+/** Per-target (dev/prod/lib) cache statistics */
+export interface TargetStats {
+  target: string
+  hits: number
+  misses: number
+  bytesRead: number
+  bytesWritten: number
+}
+
+/** A failed task and the tasks that were not run because of it */
+export interface TaskFailure {
+  id: string
+  /** Error of each attempt, first to last */
+  errors: Array<string>
+  /**
+   * Tasks skipped because they depend on this one, directly or through
+   * other skipped tasks, or because the build stopped at this failure
+   */
+  skipped: Array<string>
+}
+
+/** Argument passed to a task callback */
+export interface TaskInvocation {
+  id: string
+  /** Outputs of the task's dependencies, by task id */
+  inputs: Record<string, string>
+  /** Value of `TaskSpec.input` */
+  input?: string
+  /** 1 for the first run, 2 for the first retry and so on */
+  attempt: number
+}
+
+/** Result and timing of one task */
+export interface TaskResult {
+  id: string
+  stage: BuildStage
+  status: TaskStatus
+  output?: string
+  error?: string
+  /** When the task started, in ms since the build started; absent if skipped */
+  startMs?: number
+  /** Time between its dependencies finishing and the task starting */
+  queuedMs?: number
+  /** Including retries, and the backoff between them */
+  durationMs: number
+  /** Number of times the task was run; 0 if it never started */
+  attempts: number
+}
+
+/** A task submitted to `BuildOrchestrator.run` */
+export interface TaskSpec {
+  /** Unique within a build */
+  id: string
+  /** Ids of tasks that must succeed before this one starts */
+  dependencies?: Array<string>
+  /**
+   * Stage the task runs in (default: Execute). Dependencies must be in
+   * the same stage or an earlier one.
+   */
+  stage?: BuildStage
+  /** Work to run (default: Noop). Not allowed together with `callback`. */
+  kind?: NativeTaskKind
+  /** Input for the work, e.g. the text to hash or the path of the file */
+  input?: string
+  /**
+   * Module the task's output belongs to, e.g. `src/App.tsx`. Names the
+   * task's output in determinism reports and stable ids.
+   */
+  module?: string
+  /** Higher starts first when more tasks are ready than can run (default: 0) */
+  priority?: number
+  /** Retries if the task fails (default: `RunOptions.retry`) */
+  retry?: RetryPolicy
+  /**
+   * JS function to run instead of native work. It may return the task's
+   * output or a Promise of it; throwing or rejecting fails the task.
+   */
+  callback?: (invocation: TaskInvocation) => string | void | null | Promise<string | void | null>
+}
+
+/** How a task ended */
+export declare const enum TaskStatus {
+  Succeeded = 'Succeeded',
+  Failed = 'Failed',
+  /** Not run because a dependency did not succeed */
+  Skipped = 'Skipped',
+  /** Not run, or stopped early, because the build was cancelled */
+  Cancelled = 'Cancelled'
+}
+
+/** Result of a full integrity scan */
+export interface VerifyReport {
+  scanned: number
+  /** Entries that failed their checksum or could not be decoded */
+  corrupted: number
+  /** Corrupted entries deleted so they get rebuilt */
+  removed: number
+  corruptedKeys: Array<string>
+  /** Namespaces whose files could not be read and were dropped wholesale */
+  rebuiltNamespaces: Array<string>
+}
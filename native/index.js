@@ -574,6 +574,9 @@ if (!nativeBinding) {
 module.exports = nativeBinding
 module.exports.BuildCache = nativeBinding.BuildCache
 module.exports.BuildOrchestrator = nativeBinding.BuildOrchestrator
+module.exports.CacheKeyBuilder = nativeBinding.CacheKeyBuilder
+module.exports.CacheSnapshot = nativeBinding.CacheSnapshot
+module.exports.CacheTransaction = nativeBinding.CacheTransaction
 module.exports.GraphAnalyzer = nativeBinding.GraphAnalyzer
 module.exports.NativeWorker = nativeBinding.NativeWorker
 module.exports.PluginRuntime = nativeBinding.PluginRuntime
@@ -582,12 +585,25 @@ module.exports.benchmarkGraphAnalysis = nativeBinding.benchmarkGraphAnalysis
 module.exports.benchmarkParallelism = nativeBinding.benchmarkParallelism
 module.exports.benchmarkTransform = nativeBinding.benchmarkTransform
 module.exports.BuildStage = nativeBinding.BuildStage
+module.exports.BuildStatus = nativeBinding.BuildStatus
+module.exports.CacheAccessMode = nativeBinding.CacheAccessMode
+module.exports.CacheCompression = nativeBinding.CacheCompression
+module.exports.CacheRecovery = nativeBinding.CacheRecovery
 module.exports.createArtifactKey = nativeBinding.createArtifactKey
 module.exports.createGraphKey = nativeBinding.createGraphKey
 module.exports.createInputKey = nativeBinding.createInputKey
 module.exports.createPlanKey = nativeBinding.createPlanKey
+module.exports.decodeCacheKey = nativeBinding.decodeCacheKey
+module.exports.EventLevel = nativeBinding.EventLevel
+module.exports.FailureMode = nativeBinding.FailureMode
 module.exports.fastHash = nativeBinding.fastHash
+module.exports.fingerprintConfig = nativeBinding.fingerprintConfig
 module.exports.getOptimalParallelism = nativeBinding.getOptimalParallelism
 module.exports.helloRust = nativeBinding.helloRust
+module.exports.LookupOutcome = nativeBinding.LookupOutcome
+module.exports.MissReason = nativeBinding.MissReason
+module.exports.NativeTaskKind = nativeBinding.NativeTaskKind
 module.exports.normalizePath = nativeBinding.normalizePath
+module.exports.RemoteCacheProtocol = nativeBinding.RemoteCacheProtocol
 module.exports.scanImports = nativeBinding.scanImports
+module.exports.TaskStatus = nativeBinding.TaskStatus
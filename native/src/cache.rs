@@ -5,14 +5,107 @@
 // - LSM tree architecture for efficient writes
 // - Automatic compaction and cleanup
 // - Multi-target support (dev/prod/lib)
+// - Collision-safe keys carrying config, env and version (see `key`)
+// - Read-only and secondary instances for other processes (see `access`)
 // - Cache warming and invalidation
+// - Optional remote HTTP tier (see `remote`)
+// - LZ4 or zstd compression, with a zstd dictionary trained per namespace (see `compression`)
+// - Portable export/import archives (see `archive`)
+// - Hard-linked checkpoints and restore (see `checkpoint`)
+// - Per-entry metadata, checksums and schema versioning (see `envelope`)
+// - Corruption recovery and integrity scans (see `integrity`)
+// - One column family per key namespace, with per-namespace statistics (see `stats`)
+// - Atomic per-build commits and rollback (see `transaction`)
+// - Consistent point-in-time reads (see `snapshot`)
+// - Opt-in lookup tracing with miss explanations (see `trace`)
+// - Memory, directory or RocksDB storage, picked by `NEXXO_CACHE_BACKEND` (see `storage`)
+
+mod access;
+mod archive;
+mod checkpoint;
+mod compression;
+mod envelope;
+mod integrity;
+mod key;
+mod remote;
+mod snapshot;
+mod stats;
+mod storage;
+mod trace;
+mod transaction;
 
 use napi::bindgen_prelude::*;
+use napi::threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode};
 use napi_derive::napi;
-use rocksdb::{DB, Options, WriteBatch, IteratorMode};
+use nexxo_storage::{BackendKind, BatchOp};
+use rocksdb::{BottommostLevelCompaction, ColumnFamilyDescriptor, CompactOptions, Options, DB};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub use access::CacheAccessMode;
+pub use archive::{ExportReport, ImportReport};
+pub use checkpoint::CheckpointInfo;
+pub use compression::{CacheCompression, CompressionOptions};
+pub use envelope::EntryMetadata;
+pub use integrity::{CacheRecovery, VerifyReport};
+pub use key::{decode_cache_key, fingerprint_config, CacheKeyBuilder, CacheKeyOptions, DecodedCacheKey};
+pub use remote::{RemoteCacheOptions, RemoteCacheProtocol, RemoteCacheStats};
+pub use snapshot::CacheSnapshot;
+pub use stats::{NamespaceStats, TargetStats};
+pub use trace::{CacheLookup, ExplainReport, KeyDifference, LookupOutcome, MissExplanation, MissReason};
+pub use transaction::CacheTransaction;
+use access::OpenError;
+use compression::Compression;
+use envelope::{Envelope, ACCESS_RESOLUTION_MS, DEFAULT_PRODUCER_VERSION, SCHEMA_VERSION};
+use remote::RemoteCache;
+use stats::CacheCounters;
+use storage::Backend;
+use trace::CacheTracer;
+
+/// Column family per key namespace. Keys with any other prefix live in `default`.
+pub(crate) const NAMESPACES: [&str; 5] = ["default", "input", "graph", "plan", "artifact"];
+
+/// Column family for bookkeeping that is not a cache entry
+pub(crate) const META_CF: &str = "meta";
+
+/// Key in `META_CF` holding the schema and producer version of the whole cache
+const META_KEY: &str = "schema";
+
+/// Column families of the cache, all opened with `opts`
+fn cf_descriptors(opts: &Options) -> Vec<ColumnFamilyDescriptor> {
+    NAMESPACES
+        .iter()
+        .chain(std::iter::once(&META_CF))
+        .map(|name| ColumnFamilyDescriptor::new(*name, opts.clone()))
+        .collect()
+}
+
+/// Index into `NAMESPACES` of the column family holding a key
+pub(crate) fn namespace_index(key: &[u8]) -> usize {
+    let prefix = key.split(|b| *b == b':').next().unwrap_or(key);
+    NAMESPACES
+        .iter()
+        .position(|namespace| namespace.as_bytes() == prefix)
+        .unwrap_or(0)
+}
+
+/// Column family holding the namespace at `index`
+pub(crate) fn namespace_cf(db: &DB, index: usize) -> &rocksdb::ColumnFamily {
+    db.cf_handle(NAMESPACES[index])
+        .expect("namespace column families are created when the cache is opened")
+}
+
+/// Build target of a key, for namespaces whose keys carry one
+/// (`plan:{target}:...` and `artifact:{target}:...`)
+pub(crate) fn target_of(key: &str) -> Option<&str> {
+    let mut parts = key.splitn(3, ':');
+    match parts.next()? {
+        "plan" | "artifact" => parts.next(),
+        _ => None,
+    }
+}
 
 // Unused CacheEntry struct removed
 // pub struct CacheEntry { ... }
@@ -21,31 +114,103 @@ use std::sync::Arc;
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[napi(object)]
 pub struct CacheStats {
-    pub total_entries: u32,
-    pub hits: u32,
-    pub misses: u32,
+    /// RocksDB estimate, summed over namespaces
+    pub total_entries: i64,
+    pub hits: i64,
+    pub misses: i64,
     pub hit_rate: f64,
     pub size_bytes: f64,
+    /// Uncompressed over on-disk size of flushed data, over all namespaces.
+    /// Entries compressed with a namespace dictionary count at their compressed size.
+    pub compression_ratio: f64,
+    pub bytes_read: i64,
+    pub bytes_written: i64,
+    /// Entries that failed their checksum on read and were treated as misses
+    pub corrupted_entries: i64,
+    pub namespaces: Vec<NamespaceStats>,
+    pub targets: Vec<TargetStats>,
+}
+
+/// Build cache configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[napi(object)]
+pub struct BuildCacheOptions {
+    /// Remote cache to read through and write behind to
+    pub remote: Option<RemoteCacheOptions>,
+    /// Version of the tool producing entries, e.g. Nexxo plus transformer version.
+    /// Entries written by a different producer are invalidated.
+    pub producer_version: Option<String>,
+    /// How to open the cache directory (default: Primary)
+    pub access_mode: Option<CacheAccessMode>,
+    /// Block compression (default: LZ4)
+    pub compression: Option<CompressionOptions>,
+    /// Record every lookup for `explain()` (default: false)
+    pub trace: Option<bool>,
+}
+
+/// Schema and producer recorded for the cache as a whole
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct CacheMeta {
+    schema_version: u32,
+    producer_version: String,
 }
 
-/// RocksDB-based build cache
+/// Progress of a `compactAsync` call, reported once per namespace
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[napi(object)]
+pub struct CompactProgress {
+    /// Namespace that just finished compacting
+    pub namespace: String,
+    pub completed: u32,
+    pub total: u32,
+}
+
+/// RocksDB-based build cache.
+/// Clones share the same database, so async methods run on a clone.
 #[napi]
+#[derive(Clone)]
 pub struct BuildCache {
-    db: Arc<DB>,
-    hits: Arc<std::sync::atomic::AtomicU32>,
-    misses: Arc<std::sync::atomic::AtomicU32>,
+    backend: Arc<Backend>,
+    path: PathBuf,
+    /// RocksDB options, for reopening the database after a restore
+    db_options: Arc<Options>,
+    compression: Compression,
+    counters: Arc<CacheCounters>,
+    tracer: Arc<CacheTracer>,
+    remote: Option<Arc<RemoteCache>>,
+    producer_version: String,
+    invalidated_entries: u32,
+    recovery: Option<CacheRecovery>,
+    /// Resolved mode, never `Auto`
+    access_mode: CacheAccessMode,
 }
 
 #[napi]
 impl BuildCache {
-    /// Create a new build cache at the specified path
+    /// Create a new build cache at the specified path.
+    /// Entries are kept in the backend named by `NEXXO_CACHE_BACKEND`, RocksDB by default.
+    /// Entries written under another schema or producer version are dropped.
     #[napi(constructor)]
-    pub fn new(cache_path: String) -> Result<Self> {
+    pub fn new(cache_path: String, options: Option<BuildCacheOptions>) -> Result<Self> {
         let path = PathBuf::from(cache_path);
+        let options = options.unwrap_or(BuildCacheOptions {
+            remote: None,
+            producer_version: None,
+            access_mode: None,
+            compression: None,
+            trace: None,
+        });
+        
+        let producer_version = options.producer_version
+            .unwrap_or_else(|| DEFAULT_PRODUCER_VERSION.to_string());
+        if producer_version.len() > u8::MAX as usize {
+            return Err(Error::from_reason("Producer version must be at most 255 bytes"));
+        }
         
         // Configure RocksDB options for optimal performance
         let mut opts = Options::default();
         opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
         opts.set_max_open_files(1000);
         opts.set_use_fsync(false); // Faster writes, acceptable for cache
         opts.set_bytes_per_sync(1048576); // 1MB
@@ -54,83 +219,414 @@ impl BuildCache {
         opts.set_target_file_size_base(64 * 1024 * 1024); // 64MB
         
         // Enable compression
-        opts.set_compression_type(rocksdb::DBCompressionType::Lz4);
+        let compression = Compression::from_options(options.compression.as_ref())?;
+        compression.apply(&mut opts);
         
-        let db = DB::open(&opts, path)
-            .map_err(|e| Error::from_reason(format!("Failed to open RocksDB: {}", e)))?;
+        let kind = nexxo_storage::kind_from_env(BackendKind::RocksDb)
+            .map_err(|e| Error::from_reason(e.to_string()))?;
+        let requested_mode = options.access_mode.unwrap_or(CacheAccessMode::Primary);
+        let (backend, recovery, access_mode) = match kind {
+            BackendKind::RocksDb => open_rocks(&path, &opts, &compression, requested_mode)?,
+            kind => {
+                // Only RocksDB can share a directory between processes
+                if !matches!(requested_mode, CacheAccessMode::Primary | CacheAccessMode::Auto) {
+                    return Err(Error::from_reason(format!(
+                        "accessMode {:?} needs the RocksDB cache backend",
+                        requested_mode
+                    )));
+                }
+                let backend = Backend::open(kind, &path)
+                    .map_err(|e| Error::from_reason(format!("Failed to open cache: {}", e)))?;
+                (backend, None, CacheAccessMode::Primary)
+            }
+        };
         
-        Ok(Self {
-            db: Arc::new(db),
-            hits: Arc::new(std::sync::atomic::AtomicU32::new(0)),
-            misses: Arc::new(std::sync::atomic::AtomicU32::new(0)),
-        })
+        let remote = options.remote
+            .map(|options| RemoteCache::new(&options))
+            .transpose()
+            .map_err(|e| Error::from_reason(format!("Failed to configure remote cache: {}", e)))?;
+        
+        let mut cache = Self {
+            backend: Arc::new(backend),
+            db_options: Arc::new(opts.clone()),
+            compression,
+            path,
+            counters: Arc::new(CacheCounters::new(NAMESPACES.len())),
+            tracer: Arc::new(CacheTracer::default()),
+            remote: remote.map(Arc::new),
+            producer_version,
+            invalidated_entries: 0,
+            recovery,
+            access_mode,
+        };
+        cache.invalidated_entries = cache.check_schema()?;
+        cache.train_dictionaries()?;
+        cache.tracer.set_enabled(options.trace.unwrap_or(false));
+        
+        Ok(cache)
     }
     
-    /// Get a value from the cache
-    #[napi]
-    pub fn get(&self, key: String) -> Option<String> {
-        match self.db.get(key.as_bytes()) {
-            Ok(Some(value)) => {
-                self.hits.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-                String::from_utf8(value).ok()
+    /// Wipe the cache if it was written under another schema or producer version.
+    /// Returns the number of entries dropped.
+    fn check_schema(&self) -> Result<u32> {
+        let current = CacheMeta {
+            schema_version: SCHEMA_VERSION,
+            producer_version: self.producer_version.clone(),
+        };
+        
+        let stored = self.backend.meta.get(META_KEY.as_bytes())
+            .map_err(|e| Error::from_reason(format!("Failed to read cache metadata: {}", e)))?
+            .and_then(|bytes| serde_json::from_slice::<CacheMeta>(&bytes).ok());
+        if stored.as_ref() == Some(&current) || self.ensure_writable().is_err() {
+            // Other processes leave the primary's entries alone; incompatible
+            // ones simply read as misses
+            return Ok(0);
+        }
+        
+        // Either the versions changed or the cache predates envelopes
+        let invalidated = self.clear_entries()?;
+        let meta = serde_json::to_vec(&current)
+            .map_err(|e| Error::from_reason(format!("Failed to encode cache metadata: {}", e)))?;
+        self.backend.meta.put(META_KEY.as_bytes(), &meta)
+            .map_err(|e| Error::from_reason(format!("Failed to write cache metadata: {}", e)))?;
+        
+        Ok(invalidated)
+    }
+    
+    /// Train a zstd dictionary for each namespace that has enough entries but
+    /// none yet. Only a primary with zstd dictionaries enabled trains them.
+    fn train_dictionaries(&self) -> Result<()> {
+        let (Some(db), Some(max_bytes)) = (&self.backend.db, self.compression.dictionary_bytes()) else {
+            return Ok(());
+        };
+        if self.ensure_writable().is_err() {
+            return Ok(());
+        }
+        self.backend.dictionaries.train(db, &*self.backend.meta, max_bytes)
+            .map_err(|e| Error::from_reason(format!("Failed to train compression dictionaries: {}", e)))?;
+        Ok(())
+    }
+    
+    /// Fail unless this instance owns the cache directory
+    fn ensure_writable(&self) -> Result<()> {
+        if self.access_mode == CacheAccessMode::Primary {
+            Ok(())
+        } else {
+            Err(Error::from_reason(format!(
+                "Cache is open in {:?} mode and cannot be written",
+                self.access_mode
+            )))
+        }
+    }
+    
+    /// Whether an entry was written by this schema and producer
+    fn is_compatible(&self, envelope: &Envelope) -> bool {
+        envelope.is_current(&self.producer_version)
+    }
+    
+    /// Read a compatible entry from the local database, refreshing its last-access time
+    fn load_local(&self, key: &str) -> Option<Vec<u8>> {
+        let bytes = self.backend.entries.get(key.as_bytes()).ok()??;
+        self.open_local(key, bytes)
+    }
+    
+    /// Unwrap an entry read from the local database, dropping it if incompatible
+    /// and refreshing its last-access time if stale
+    fn open_local(&self, key: &str, mut bytes: Vec<u8>) -> Option<Vec<u8>> {
+        let now = envelope::now_ms();
+        
+        let (payload, stale) = match Envelope::decode(&bytes) {
+            Some(entry) if self.is_compatible(&entry) => (
+                entry.payload.to_vec(),
+                now - entry.last_access >= ACCESS_RESOLUTION_MS,
+            ),
+            decoded => {
+                // Corrupt, or written by another schema or producer: drop it so it gets rebuilt
+                let reason = if decoded.is_none() {
+                    self.counters.corrupt();
+                    MissReason::Corrupt
+                } else {
+                    MissReason::Incompatible
+                };
+                self.tracer.dropped(key, reason);
+                let _ = self.backend.entries.delete(key.as_bytes());
+                return None;
+            }
+        };
+        
+        if stale {
+            envelope::touch(&mut bytes, now);
+            let _ = self.backend.entries.put(key.as_bytes(), &bytes);
+        }
+        Some(payload)
+    }
+    
+    /// Fetch an entry from the remote cache and store it locally
+    fn load_remote(&self, key: &str) -> Option<Vec<u8>> {
+        let mut bytes = self.remote.as_ref()?.fetch(key)?;
+        let payload = match Envelope::decode(&bytes) {
+            Some(entry) if self.is_compatible(&entry) => entry.payload.to_vec(),
+            Some(_) => return None,
+            None => {
+                self.counters.corrupt();
+                return None;
+            }
+        };
+        
+        // Populate the local cache so the next lookup stays on this machine
+        envelope::touch(&mut bytes, envelope::now_ms());
+        let _ = self.backend.entries.put(key.as_bytes(), &bytes);
+        Some(payload)
+    }
+    
+    /// Finish a lookup given the local payload, reading through to the remote
+    /// cache on a local miss if `read_through`, and counting the hit or miss
+    /// of the lookup that started at `start`
+    fn resolve(&self, key: &str, local: Option<Vec<u8>>, read_through: bool, start: Instant) -> Option<String> {
+        let namespace = namespace_index(key.as_bytes());
+        let target = target_of(key);
+        
+        let outcome = if local.is_some() { LookupOutcome::LocalHit } else { LookupOutcome::RemoteHit };
+        let payload = match local {
+            None if read_through => self.load_remote(key),
+            local => local,
+        };
+        match payload.and_then(|payload| String::from_utf8(payload).ok()) {
+            Some(value) => {
+                self.counters.hit(namespace, target, value.len());
+                self.tracer.record(&*self.backend.entries, key, outcome, start);
+                Some(value)
             }
-            _ => {
-                self.misses.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            None => {
+                self.counters.miss(namespace, target);
+                self.tracer.record(&*self.backend.entries, key, LookupOutcome::Miss, start);
                 None
             }
         }
     }
     
-    /// Set a value in the cache
+    fn lookup(&self, key: &str, read_through: bool) -> Option<String> {
+        let start = Instant::now();
+        let local = self.load_local(key);
+        self.resolve(key, local, read_through, start)
+    }
+    
+    fn lookup_many(&self, keys: &[String], read_through: bool) -> Vec<Option<String>> {
+        let lookups: Vec<&[u8]> = keys.iter().map(|key| key.as_bytes()).collect();
+        let results = self.backend.entries.multi_get(&lookups)
+            .unwrap_or_else(|_| vec![None; keys.len()]);
+        
+        keys.iter()
+            .zip(results)
+            .map(|(key, result)| {
+                let start = Instant::now();
+                let local = result.and_then(|bytes| self.open_local(key, bytes));
+                self.resolve(key, local, read_through, start)
+            })
+            .collect()
+    }
+    
+    /// Get a value from the local cache. This runs on the JS thread, so it
+    /// never reads through to the remote cache; use `getAsync` for that.
+    #[napi]
+    pub fn get(&self, key: String) -> Option<String> {
+        self.lookup(&key, false)
+    }
+    
+    /// Get many values from the local cache at once, in the order of `keys`.
+    /// On RocksDB, lookups go through a single multi-get.
+    #[napi]
+    pub fn multi_get(&self, keys: Vec<String>) -> Vec<Option<String>> {
+        self.lookup_many(&keys, false)
+    }
+    
+    /// Run `f` against this cache on the blocking thread pool
+    async fn run_blocking<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(BuildCache) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let cache = self.clone();
+        tokio::task::spawn_blocking(move || f(cache))
+            .await
+            .map_err(|e| Error::from_reason(format!("Cache task failed: {}", e)))?
+    }
+    
+    /// Async version of `get` that runs on the blocking thread pool and reads
+    /// through to the remote cache on a local miss
+    #[napi]
+    pub async fn get_async(&self, key: String) -> Result<Option<String>> {
+        self.run_blocking(move |cache| Ok(cache.lookup(&key, true))).await
+    }
+    
+    /// Async version of `multi_get` that runs on the blocking thread pool and
+    /// reads through to the remote cache on local misses
+    #[napi]
+    pub async fn multi_get_async(&self, keys: Vec<String>) -> Result<Vec<Option<String>>> {
+        self.run_blocking(move |cache| Ok(cache.lookup_many(&keys, true))).await
+    }
+    
+    /// Take a consistent, read-only view of the cache. Writes made after this
+    /// call, by this or any other build, are not visible through the snapshot.
+    /// Needs the RocksDB backend.
+    #[napi]
+    pub fn snapshot(&self) -> Result<CacheSnapshot> {
+        let db = self.backend.db("Snapshots").map_err(Error::from_reason)?.clone();
+        Ok(CacheSnapshot::new(db, self.backend.clone(), self.counters.clone(), self.producer_version.clone()))
+    }
+    
+    /// Set a value in the cache and queue it for upload to the remote cache
     #[napi]
     pub fn set(&self, key: String, value: String) -> Result<()> {
-        self.db.put(key.as_bytes(), value.as_bytes())
-            .map_err(|e| Error::from_reason(format!("Failed to set cache: {}", e)))
+        self.put_payload(&key, value.as_bytes())
+    }
+    
+    /// Wrap a payload in an envelope, store it and queue it for upload
+    fn put_payload(&self, key: &str, payload: &[u8]) -> Result<()> {
+        self.ensure_writable()?;
+        let bytes = envelope::encode(payload, &self.producer_version, envelope::now_ms());
+        self.backend.entries.put(key.as_bytes(), &bytes)
+            .map_err(|e| Error::from_reason(format!("Failed to set cache: {}", e)))?;
+        self.counters.write(namespace_index(key.as_bytes()), target_of(key), bytes.len());
+        if let Some(remote) = &self.remote {
+            remote.enqueue(key, &bytes);
+        }
+        Ok(())
+    }
+    
+    /// Async version of `set` that runs on the blocking thread pool
+    #[napi]
+    pub async fn set_async(&self, key: String, value: String) -> Result<()> {
+        self.run_blocking(move |cache| cache.set(key, value)).await
     }
     
     /// Delete a value from the cache
     #[napi]
     pub fn delete(&self, key: String) -> Result<()> {
-        self.db.delete(key.as_bytes())
+        self.ensure_writable()?;
+        self.backend.entries.delete(key.as_bytes())
             .map_err(|e| Error::from_reason(format!("Failed to delete cache: {}", e)))
     }
     
     /// Check if a key exists in the cache
     #[napi]
     pub fn has(&self, key: String) -> bool {
-        self.db.get(key.as_bytes()).ok().flatten().is_some()
+        self.get_entry_metadata(key).is_some()
+    }
+    
+    /// Get the metadata stored with an entry, without counting a hit or refreshing its access time
+    #[napi]
+    pub fn get_entry_metadata(&self, key: String) -> Option<EntryMetadata> {
+        let bytes = self.backend.entries.get(key.as_bytes()).ok()??;
+        Envelope::decode(&bytes)
+            .filter(|entry| self.is_compatible(entry))
+            .map(|entry| entry.metadata())
+    }
+    
+    /// Number of entries dropped when the cache was opened because they were
+    /// written under another schema or producer version
+    #[napi(getter)]
+    pub fn get_invalidated_entries(&self) -> u32 {
+        self.invalidated_entries
+    }
+    
+    /// How the cache recovered from a database it could not open, if it had to
+    #[napi(getter)]
+    pub fn get_recovery(&self) -> Option<CacheRecovery> {
+        self.recovery
+    }
+    
+    /// Turn lookup tracing on or off. Lookups already traced are kept.
+    #[napi]
+    pub fn set_tracing(&self, enabled: bool) {
+        self.tracer.set_enabled(enabled);
+    }
+    
+    /// Whether lookups are being traced
+    #[napi(getter)]
+    pub fn get_tracing(&self) -> bool {
+        self.tracer.is_enabled()
+    }
+    
+    /// Traced lookups with an explanation for each miss, optionally only
+    /// those for `key`. Empty unless tracing is on.
+    #[napi]
+    pub fn explain(&self, key: Option<String>) -> ExplainReport {
+        self.tracer.report(key.as_deref())
+    }
+    
+    /// Forget all traced lookups
+    #[napi]
+    pub fn clear_trace(&self) {
+        self.tracer.clear();
+    }
+    
+    /// Draw every lookup on an orchestrator's timeline, or stop with `None`
+    pub(crate) fn set_timeline(&self, timeline: Option<Arc<crate::orchestrator::timeline::Timeline>>) {
+        self.tracer.set_timeline(timeline);
     }
     
     /// Batch set multiple key-value pairs
     #[napi]
     pub fn batch_set(&self, entries: std::collections::HashMap<String, String>) -> Result<()> {
-        let mut batch = WriteBatch::default();
-        for (key, value) in entries {
-            batch.put(key.as_bytes(), value.as_bytes());
-        }
-        self.db.write(batch)
-            .map_err(|e| Error::from_reason(format!("Failed to batch set: {}", e)))
+        self.ensure_writable()?;
+        let now = envelope::now_ms();
+        let encoded: Vec<(String, Vec<u8>)> = entries
+            .into_iter()
+            .map(|(key, value)| {
+                let bytes = envelope::encode(value.as_bytes(), &self.producer_version, now);
+                (key, bytes)
+            })
+            .collect();
+        
+        let batch = encoded
+            .iter()
+            .map(|(key, bytes)| BatchOp::Put(key.as_bytes().to_vec(), bytes.clone()))
+            .collect();
+        self.backend.entries.write(batch)
+            .map_err(|e| Error::from_reason(format!("Failed to batch set: {}", e)))?;
+        publish_writes(&self.counters, self.remote.as_deref(), &encoded);
+        Ok(())
+    }
+    
+    /// Async version of `batch_set` that runs on the blocking thread pool
+    #[napi]
+    pub async fn batch_set_async(&self, entries: std::collections::HashMap<String, String>) -> Result<()> {
+        self.run_blocking(move |cache| cache.batch_set(entries)).await
+    }
+    
+    /// Start staging writes for a build. Nothing is visible to the cache
+    /// until the transaction is committed.
+    #[napi]
+    pub fn begin_transaction(&self) -> Result<CacheTransaction> {
+        self.ensure_writable()?;
+        Ok(CacheTransaction::new(
+            self.backend.clone(),
+            self.counters.clone(),
+            self.remote.clone(),
+            self.producer_version.clone(),
+        ))
     }
     
     /// Clear all entries for a specific target (dev/prod/lib)
     #[napi]
     pub fn clear_target(&self, target: String) -> Result<u32> {
-        let mut count = 0u32;
-        let mut batch = WriteBatch::default();
-        
-        // Iterate through all keys and delete those matching the target
-        let iter = self.db.iterator(IteratorMode::Start);
-        for item in iter {
-            if let Ok((key, _)) = item {
-                let key_str = String::from_utf8_lossy(&key);
-                if key_str.starts_with(&format!("{}:", target)) {
-                    batch.delete(&key);
-                    count += 1;
+        self.ensure_writable()?;
+        let mut batch = Vec::new();
+        let prefix = format!("{}:", target);
+        
+        // Iterate through all keys and delete those built for the target
+        self.backend.entries
+            .scan_prefix(b"", &mut |key, _| {
+                let key_str = String::from_utf8_lossy(key);
+                if target_of(&key_str) == Some(target.as_str()) || key_str.starts_with(&prefix) {
+                    batch.push(BatchOp::Delete(key.to_vec()));
                 }
-            }
-        }
+                true
+            })
+            .map_err(|e| Error::from_reason(format!("Failed to clear target: {}", e)))?;
         
-        self.db.write(batch)
+        let count = batch.len() as u32;
+        self.backend.entries.write(batch)
             .map_err(|e| Error::from_reason(format!("Failed to clear target: {}", e)))?;
         
         Ok(count)
@@ -139,24 +635,36 @@ impl BuildCache {
     /// Clear all cache entries
     #[napi]
     pub fn clear_all(&self) -> Result<()> {
-        let mut batch = WriteBatch::default();
-        
-        let iter = self.db.iterator(IteratorMode::Start);
-        for item in iter {
-            if let Ok((key, _)) = item {
-                batch.delete(&key);
-            }
-        }
+        self.clear_entries().map(|_| ())
+    }
+    
+    /// Async version of `clear_all` that runs on the blocking thread pool.
+    /// Resolves to the number of entries removed.
+    #[napi]
+    pub async fn clear_all_async(&self) -> Result<u32> {
+        self.run_blocking(|cache| cache.clear_entries()).await
+    }
+    
+    /// Delete every entry in every namespace, returning how many were removed
+    fn clear_entries(&self) -> Result<u32> {
+        self.ensure_writable()?;
+        let mut count = 0u32;
+        self.backend.entries
+            .scan_prefix(b"", &mut |_, _| {
+                count += 1;
+                true
+            })
+            .and_then(|()| self.backend.entries.clear())
+            .map_err(|e| Error::from_reason(format!("Failed to clear all: {}", e)))?;
         
-        self.db.write(batch)
-            .map_err(|e| Error::from_reason(format!("Failed to clear all: {}", e)))
+        Ok(count)
     }
     
-    /// Get cache statistics
+    /// Get cache statistics. On RocksDB, entry counts and sizes are estimates,
+    /// so this never scans the database; other backends count every entry.
     #[napi]
     pub fn get_stats(&self) -> Result<CacheStats> {
-        let hits = self.hits.load(std::sync::atomic::Ordering::Relaxed);
-        let misses = self.misses.load(std::sync::atomic::Ordering::Relaxed);
+        let (hits, misses, bytes_read, bytes_written) = self.counters.total.load();
         let total_requests = hits + misses;
         
         let hit_rate = if total_requests > 0 {
@@ -165,44 +673,370 @@ impl BuildCache {
             0.0
         };
         
-        // Count total entries
-        let mut total_entries = 0u32;
-        let iter = self.db.iterator(IteratorMode::Start);
-        for _ in iter {
-            total_entries += 1;
+        let mut total_entries = 0u64;
+        let mut size_bytes = 0u64;
+        let (mut raw_block_bytes, mut stored_block_bytes) = (0u64, 0u64);
+        let mut namespaces = Vec::with_capacity(NAMESPACES.len());
+        let sizes = self.backend.namespace_sizes()
+            .map_err(|e| Error::from_reason(format!("Failed to read cache stats: {}", e)))?;
+        for (index, (name, sizes)) in NAMESPACES.iter().zip(sizes).enumerate() {
+            let storage::NamespaceSizes { entries: estimated_entries, size, raw, stored } = sizes;
+            total_entries += estimated_entries;
+            size_bytes += size;
+            raw_block_bytes += raw;
+            stored_block_bytes += stored;
+            
+            let (hits, misses, bytes_read, bytes_written) = self.counters.namespaces[index].load();
+            namespaces.push(NamespaceStats {
+                namespace: name.to_string(),
+                estimated_entries: estimated_entries as i64,
+                size_bytes: size as f64,
+                compression_ratio: compression::ratio(raw, stored),
+                hits: hits as i64,
+                misses: misses as i64,
+                bytes_read: bytes_read as i64,
+                bytes_written: bytes_written as i64,
+            });
         }
         
-        // Estimate size (approximate)
-        let size_bytes = self.db.property_int_value("rocksdb.total-sst-files-size")
-            .ok()
-            .flatten()
-            .unwrap_or(0);
-        
         Ok(CacheStats {
-            total_entries,
-            hits,
-            misses,
+            total_entries: total_entries as i64,
+            hits: hits as i64,
+            misses: misses as i64,
             hit_rate,
             size_bytes: size_bytes as f64,
+            compression_ratio: compression::ratio(raw_block_bytes, stored_block_bytes),
+            bytes_read: bytes_read as i64,
+            bytes_written: bytes_written as i64,
+            corrupted_entries: self.counters.corrupted.load(std::sync::atomic::Ordering::Relaxed) as i64,
+            namespaces,
+            targets: self.counters.target_stats(),
         })
     }
     
-    /// Compact the database to reclaim space
+    /// Wait for queued remote uploads to finish.
+    /// Returns false if uploads were still pending when the timeout elapsed.
+    /// Uploads still queued when the cache is dropped carry on in the background
+    /// only as long as the process lives, so call this before exiting.
+    #[napi]
+    pub fn flush_remote(&self, timeout_ms: Option<u32>) -> bool {
+        match &self.remote {
+            Some(remote) => remote.flush(Duration::from_millis(timeout_ms.unwrap_or(30_000) as u64)),
+            None => true,
+        }
+    }
+    
+    /// Get remote cache statistics, if a remote cache is configured
+    #[napi]
+    pub fn get_remote_stats(&self) -> Option<RemoteCacheStats> {
+        self.remote.as_ref().map(|remote| remote.stats())
+    }
+    
+    /// Export the selected namespaces (all when omitted) to a portable archive
+    #[napi]
+    pub fn export(&self, path: String, namespaces: Option<Vec<String>>) -> Result<ExportReport> {
+        archive::export(&*self.backend.entries, &PathBuf::from(&path), namespaces)
+            .map_err(|e| Error::from_reason(format!("Failed to export cache to {}: {}", path, e)))
+    }
+    
+    /// Merge an archive into the cache. Entries that differ from the local
+    /// value are reported as conflicts and only replaced when `overwrite` is set.
+    /// Entries written under another schema or producer version are skipped.
+    #[napi]
+    pub fn import(&self, path: String, overwrite: Option<bool>) -> Result<ImportReport> {
+        self.ensure_writable()?;
+        let is_compatible = |bytes: &[u8]| {
+            Envelope::decode(bytes).is_some_and(|entry| self.is_compatible(&entry))
+        };
+        archive::import(&*self.backend.entries, &PathBuf::from(&path), overwrite.unwrap_or(false), &is_compatible)
+            .map_err(|e| Error::from_reason(format!("Failed to import cache from {}: {}", path, e)))
+    }
+    
+    /// Check every entry against its checksum. Corrupt entries are deleted so
+    /// they get rebuilt; namespaces whose files cannot be read are dropped.
+    /// Needs the RocksDB backend.
+    #[napi]
+    pub fn verify(&self) -> Result<VerifyReport> {
+        self.ensure_writable()?;
+        let db = self.backend.db("Verifying the cache").map_err(Error::from_reason)?;
+        integrity::verify(db, &self.backend.dictionaries)
+            .map_err(|e| Error::from_reason(format!("Failed to verify cache: {}", e)))
+    }
+    
+    /// Take a checkpoint of the cache in `dir`, which must not exist yet.
+    /// A relative `dir` is placed under `{cachePath}.checkpoints`. SST files
+    /// are hard-linked where the filesystem allows, so this is cheap.
+    /// Needs the RocksDB backend.
+    #[napi]
+    pub fn checkpoint(&self, dir: String) -> Result<CheckpointInfo> {
+        let db = self.backend.db("Checkpoints").map_err(Error::from_reason)?;
+        let stats = self.get_stats()?;
+        let manifest = checkpoint::Manifest {
+            created_at: envelope::now_ms(),
+            schema_version: SCHEMA_VERSION,
+            producer_version: self.producer_version.clone(),
+            estimated_entries: stats.total_entries,
+            size_bytes: stats.size_bytes,
+        };
+        let target = checkpoint::resolve(&self.path, &dir);
+        checkpoint::create(db, &target, manifest)
+            .map_err(|e| Error::from_reason(format!("Failed to create checkpoint {}: {}", target.display(), e)))
+    }
+    
+    /// Checkpoints under `{cachePath}.checkpoints`, oldest first
+    #[napi]
+    pub fn list_checkpoints(&self) -> Vec<CheckpointInfo> {
+        checkpoint::list(&checkpoint::checkpoints_root(&self.path))
+    }
+    
+    /// Replace the contents of the cache with a checkpoint taken by `checkpoint`.
+    /// Fails while snapshots, open transactions or async calls are using the
+    /// cache. Entries from another schema or producer version are dropped, as
+    /// when opening the cache. If the previous cache cannot be reopened after
+    /// a failed restore, the cache serves it read-only, or nothing if even that
+    /// fails, and `accessMode` becomes ReadOnly.
+    #[napi]
+    pub fn restore(&mut self, dir: String) -> Result<()> {
+        self.ensure_writable()?;
+        self.backend.db("Checkpoints").map_err(Error::from_reason)?;
+        let source = checkpoint::resolve(&self.path, &dir);
+        let fail = |e: String| Error::from_reason(format!("Failed to restore checkpoint {}: {}", source.display(), e));
+        if checkpoint::read(&source).is_none() {
+            return Err(fail("not a cache checkpoint".to_string()));
+        }
+        // Clones made for async calls, snapshots and transactions share the database
+        let users = Arc::strong_count(&self.backend) - 1;
+        if users > 0 {
+            return Err(fail(format!(
+                "{} snapshot(s), transaction(s) or async call(s) still use the cache",
+                users
+            )));
+        }
+        
+        let staging = checkpoint::sibling(&self.path, ".restoring");
+        let backup = checkpoint::sibling(&self.path, ".pre-restore");
+        checkpoint::stage(&source, &staging).map_err(|e| fail(e.to_string()))?;
+        
+        // The checkpoint stands in for the live database while the files are
+        // swapped, since the old one has to be closed before its directory moves
+        let descriptors = cf_descriptors(&self.db_options);
+        let stand_in = DB::open_cf_descriptors_read_only(&self.db_options, &source, descriptors, false)
+            .map_err(|e| e.to_string())
+            .and_then(|db| Backend::rocks(db, access::Lease::ReadOnly, &self.compression).map_err(|e| e.to_string()))
+            .map_err(fail)?;
+        drop(std::mem::replace(&mut self.backend, Arc::new(stand_in)));
+        
+        let open = |path: &std::path::Path| {
+            DB::open_cf_descriptors(&self.db_options, path, cf_descriptors(&self.db_options))
+                .map_err(|e| e.to_string())
+                .and_then(|db| Backend::rocks(db, access::Lease::Primary(path.to_path_buf()), &self.compression).map_err(|e| e.to_string()))
+        };
+        let swapped = std::fs::rename(&self.path, &backup)
+            .and_then(|_| std::fs::rename(&staging, &self.path).inspect_err(|_| {
+                let _ = std::fs::rename(&backup, &self.path);
+            }))
+            .map_err(|e| e.to_string())
+            .and_then(|_| open(&self.path).inspect_err(|_| {
+                // Put the previous cache back rather than leave a broken one
+                let _ = std::fs::remove_dir_all(&self.path);
+                let _ = std::fs::rename(&backup, &self.path);
+            }));
+        let backend = match swapped {
+            Ok(backend) => backend,
+            Err(e) => {
+                let _ = std::fs::remove_dir_all(&staging);
+                match open(&self.path) {
+                    Ok(reopened) => {
+                        self.backend = Arc::new(reopened);
+                        access::record_owner(&self.path);
+                        return Err(fail(e));
+                    }
+                    Err(reopen) => {
+                        // Never keep serving the checkpoint that was not restored
+                        let read_only = DB::open_cf_descriptors_read_only(
+                            &self.db_options,
+                            &self.path,
+                            cf_descriptors(&self.db_options),
+                            false,
+                        );
+                        let fallback = read_only
+                            .map_err(|e| e.to_string())
+                            .and_then(|db| Backend::rocks(db, access::Lease::ReadOnly, &self.compression).map_err(|e| e.to_string()))
+                            .unwrap_or_else(|_| Backend::empty());
+                        self.backend = Arc::new(fallback);
+                        self.access_mode = CacheAccessMode::ReadOnly;
+                        return Err(fail(format!(
+                            "{}; reopening the previous cache also failed, so it is now read-only: {}",
+                            e, reopen
+                        )));
+                    }
+                }
+            }
+        };
+        
+        self.backend = Arc::new(backend);
+        let _ = std::fs::remove_dir_all(&backup);
+        access::record_owner(&self.path);
+        self.recovery = None;
+        self.invalidated_entries = self.check_schema()?;
+        self.train_dictionaries()
+    }
+    
+    /// Mode the cache was opened in. `Auto` resolves to Primary or Secondary.
+    #[napi(getter)]
+    pub fn get_access_mode(&self) -> CacheAccessMode {
+        self.access_mode
+    }
+    
+    /// Catch up with the primary's writes. Only meaningful for Secondary
+    /// instances; a Primary is always current.
+    #[napi]
+    pub fn catch_up(&self) -> Result<()> {
+        match self.access_mode {
+            CacheAccessMode::Secondary => {
+                self.backend.db("Catching up").map_err(Error::from_reason)?
+                    .try_catch_up_with_primary()
+                    .map_err(|e| Error::from_reason(format!("Failed to catch up with primary: {}", e)))?;
+                // The primary may have trained dictionaries since
+                self.backend.dictionaries.load(&*self.backend.meta)
+                    .map_err(|e| Error::from_reason(format!("Failed to load compression dictionaries: {}", e)))
+            }
+            CacheAccessMode::ReadOnly => Err(Error::from_reason(
+                "Read-only caches are fixed when opened; use Secondary mode to follow the primary",
+            )),
+            _ => Ok(()),
+        }
+    }
+    
+    /// Compact the database to reclaim space. Every file is rewritten, so
+    /// existing entries pick up the current compression settings.
+    /// Other backends have nothing to compact.
     #[napi]
     pub fn compact(&self) -> Result<()> {
-        self.db.compact_range::<&[u8], &[u8]>(None, None);
+        self.ensure_writable()?;
+        self.compact_namespaces(|_| {});
         Ok(())
     }
     
+    /// Async version of `compact` that runs on the blocking thread pool,
+    /// calling `on_progress` as each namespace finishes
+    #[napi]
+    pub async fn compact_async(
+        &self,
+        on_progress: Option<ThreadsafeFunction<CompactProgress, (), CompactProgress, Status, false>>,
+    ) -> Result<()> {
+        self.ensure_writable()?;
+        self.run_blocking(move |cache| {
+            cache.compact_namespaces(|progress| {
+                if let Some(on_progress) = &on_progress {
+                    on_progress.call(progress, ThreadsafeFunctionCallMode::NonBlocking);
+                }
+            });
+            Ok(())
+        })
+        .await
+    }
+    
+    /// Compact each namespace in turn, reporting after each one
+    fn compact_namespaces(&self, on_progress: impl Fn(CompactProgress)) {
+        let Some(db) = &self.backend.db else {
+            return;
+        };
+        // Without forcing it, files already at the bottom level are left as they are
+        let mut compact_opts = CompactOptions::default();
+        compact_opts.set_bottommost_level_compaction(BottommostLevelCompaction::Force);
+        for (index, name) in NAMESPACES.iter().enumerate() {
+            db.compact_range_cf_opt::<&[u8], &[u8]>(
+                namespace_cf(db, index),
+                None,
+                None,
+                &compact_opts,
+            );
+            on_progress(CompactProgress {
+                namespace: name.to_string(),
+                completed: index as u32 + 1,
+                total: NAMESPACES.len() as u32,
+            });
+        }
+    }
+    
     /// Close the cache (cleanup)
     #[napi]
     pub fn close(&self) -> Result<()> {
-        // RocksDB will be closed when the Arc is dropped
+        // The storage is closed when the Arc is dropped
         Ok(())
     }
 }
 
-/// Create cache key for input fingerprint
+/// Open the cache database at `path` in the requested access mode
+fn open_rocks(
+    path: &std::path::Path,
+    opts: &Options,
+    compression: &Compression,
+    requested_mode: CacheAccessMode,
+) -> Result<(Backend, Option<CacheRecovery>, CacheAccessMode)> {
+    let descriptors = || cf_descriptors(opts);
+    let secondary_path = access::secondary_path(path);
+    let open_secondary = || {
+        let mut opts = opts.clone();
+        opts.set_max_open_files(-1); // Required for secondary instances
+        DB::open_cf_descriptors_as_secondary(&opts, path, &secondary_path, descriptors())
+            .map_err(OpenError::new)
+    };
+    let open_primary = || {
+        let open = |path: &std::path::Path| DB::open_cf_descriptors(opts, path, descriptors());
+        integrity::open_or_recover(path, opts, open)
+    };
+    
+    let opened = match requested_mode {
+        CacheAccessMode::Primary => open_primary()
+            .map(|(db, recovery)| (db, recovery, CacheAccessMode::Primary)),
+        CacheAccessMode::Auto => match open_primary() {
+            Err(OpenError::Locked) => open_secondary().map(|db| (db, None, CacheAccessMode::Secondary)),
+            opened => opened.map(|(db, recovery)| (db, recovery, CacheAccessMode::Primary)),
+        },
+        CacheAccessMode::ReadOnly => DB::open_cf_descriptors_read_only(opts, path, descriptors(), false)
+            .map(|db| (db, None, CacheAccessMode::ReadOnly))
+            .map_err(OpenError::new),
+        CacheAccessMode::Secondary => open_secondary().map(|db| (db, None, CacheAccessMode::Secondary)),
+    };
+    let (db, recovery, access_mode) = match opened {
+        Ok(opened) => opened,
+        Err(e) => {
+            let _ = std::fs::remove_dir_all(&secondary_path);
+            return Err(Error::from_reason(match e {
+                OpenError::Locked => access::locked_message(path),
+                OpenError::Failed(e) => format!("Failed to open RocksDB: {}", e),
+            }));
+        }
+    };
+    let lease = match access_mode {
+        CacheAccessMode::Primary => {
+            access::record_owner(path);
+            access::Lease::Primary(path.to_path_buf())
+        }
+        CacheAccessMode::Secondary => access::Lease::Secondary(secondary_path),
+        _ => access::Lease::ReadOnly,
+    };
+    let backend = Backend::rocks(db, lease, compression)
+        .map_err(|e| Error::from_reason(format!("Failed to open RocksDB: {}", e)))?;
+    Ok((backend, recovery, access_mode))
+}
+
+/// Count entries that were just written and queue them for upload
+fn publish_writes(counters: &CacheCounters, remote: Option<&RemoteCache>, written: &[(String, Vec<u8>)]) {
+    for (key, bytes) in written {
+        counters.write(namespace_index(key.as_bytes()), target_of(key), bytes.len());
+    }
+    if let Some(remote) = remote {
+        for (key, bytes) in written {
+            remote.enqueue(key, bytes);
+        }
+    }
+}
+
+/// Create cache key for input fingerprint.
+/// Prefer `CacheKeyBuilder`, whose keys stay unambiguous when paths contain `:`.
 #[napi]
 pub fn create_input_key(file_path: String, content_hash: String) -> String {
     format!("input:{}:{}", file_path, content_hash)
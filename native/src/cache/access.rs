@@ -0,0 +1,252 @@
+// Multi-process access for Nexxo v2.0
+//
+// RocksDB allows one writer per directory. A second dev server or a test
+// runner on the same project can still open the cache read-only (a fixed
+// view as of opening) or as a secondary instance, which follows the
+// primary's writes each time it catches up.
+//
+// The primary records its PID next to the RocksDB lock so that processes
+// which cannot take the lock can say who holds it. A `Lease` removes that
+// record, and a secondary's private directory, once the cache is dropped.
+
+use napi_derive::napi;
+use rocksdb::ErrorKind;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use xxhash_rust::xxh3::xxh3_64;
+
+/// File in the cache directory naming the process that holds the write lock
+const OWNER_FILE: &str = "NEXXO_OWNER";
+
+/// How a `BuildCache` opens its directory
+#[napi(string_enum)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CacheAccessMode {
+    /// Read-write; fails if another process holds the cache
+    Primary,
+    /// Read-only view of the cache as it was when opened
+    ReadOnly,
+    /// Read-only instance that sees the primary's writes after `catchUp()`
+    Secondary,
+    /// Primary if the lock is free, otherwise Secondary
+    Auto,
+}
+
+/// Why the database could not be opened
+pub(crate) enum OpenError {
+    /// Another process holds the RocksDB lock
+    Locked,
+    Failed(String),
+}
+
+impl OpenError {
+    /// Classify an error from opening the database. RocksDB reports a held
+    /// lock as an I/O error naming the directory's LOCK file, whether another
+    /// process holds it ("While lock file: ...") or this one does ("lock hold
+    /// by current process ..."). The path in the message is spelled however
+    /// RocksDB normalized it, so only the file name is matched.
+    pub(crate) fn new(e: rocksdb::Error) -> Self {
+        let message = e.to_string();
+        let names_lock_file = message
+            .split_whitespace()
+            .any(|word| Path::new(word.trim_end_matches(':')).file_name() == Some("LOCK".as_ref()));
+        if e.kind() == ErrorKind::IOError && names_lock_file {
+            OpenError::Locked
+        } else {
+            OpenError::Failed(message)
+        }
+    }
+}
+
+/// Record this process as the owner of the cache directory
+pub(crate) fn record_owner(path: &Path) {
+    let owner = format!("{}\n{}\n", std::process::id(), process_name(std::process::id()).unwrap_or_default());
+    // Best effort: the owner file only improves error messages
+    let _ = std::fs::write(path.join(OWNER_FILE), owner);
+}
+
+/// PID recorded in the owner file of `path`
+fn recorded_owner(path: &Path) -> Option<u32> {
+    std::fs::read_to_string(path.join(OWNER_FILE))
+        .ok()
+        .and_then(|contents| contents.lines().next()?.trim().parse::<u32>().ok())
+}
+
+/// Error message for a cache whose lock is held by another process
+pub(crate) fn locked_message(path: &Path) -> String {
+    let owner = recorded_owner(path)
+        .map(|pid| match process_name(pid) {
+            Some(name) => format!(" by process {} ({})", pid, name),
+            None => format!(" by process {}", pid),
+        })
+        .unwrap_or_else(|| " by another process".to_string());
+
+    format!(
+        "Cache directory {} is locked{}. Open it with accessMode 'ReadOnly', 'Secondary' or 'Auto' to share it.",
+        path.display(),
+        owner
+    )
+}
+
+/// Command line of a running process, where the platform exposes it
+fn process_name(pid: u32) -> Option<String> {
+    let cmdline = std::fs::read(format!("/proc/{}/cmdline", pid)).ok()?;
+    let args: Vec<String> = cmdline
+        .split(|b| *b == 0)
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| String::from_utf8_lossy(arg).into_owned())
+        .collect();
+    if args.is_empty() {
+        None
+    } else {
+        Some(args.join(" "))
+    }
+}
+
+/// Private directory for a secondary instance's own RocksDB files, unique
+/// to this instance of the cache at `primary`
+pub(crate) fn secondary_path(primary: &Path) -> PathBuf {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let primary = primary.canonicalize().unwrap_or_else(|_| primary.to_path_buf());
+    std::env::temp_dir().join(format!(
+        "nexxo-cache-secondary-{}-{:016x}-{}",
+        std::process::id(),
+        xxh3_64(primary.to_string_lossy().as_bytes()),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+/// Files a cache instance leaves behind, removed when the last handle on
+/// the database goes away
+pub(crate) enum Lease {
+    /// The owner file in the cache directory
+    Primary(PathBuf),
+    /// The secondary instance's private directory
+    Secondary(PathBuf),
+    ReadOnly,
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        match self {
+            // Only if no later primary has recorded itself since
+            Lease::Primary(path) => {
+                if recorded_owner(path) == Some(std::process::id()) {
+                    let _ = std::fs::remove_file(path.join(OWNER_FILE));
+                }
+            }
+            Lease::Secondary(dir) => {
+                let _ = std::fs::remove_dir_all(dir);
+            }
+            Lease::ReadOnly => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{BuildCache, BuildCacheOptions};
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("nexxo-access-test-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn secondary_paths_are_per_cache_and_per_instance() {
+        let a = secondary_path(Path::new("/projects/a/.nexxo_cache"));
+        let b = secondary_path(Path::new("/projects/b/.nexxo_cache"));
+        let again = secondary_path(Path::new("/projects/a/.nexxo_cache"));
+
+        let name = |path: &Path| path.file_name().unwrap().to_string_lossy().into_owned();
+        let without_counter = |path: &Path| name(path).rsplit_once('-').unwrap().0.to_string();
+        assert_ne!(without_counter(&a), without_counter(&b));
+        assert_eq!(without_counter(&a), without_counter(&again));
+        assert_ne!(a, again);
+    }
+
+    #[test]
+    fn secondary_lease_removes_its_directory() {
+        let dir = temp_dir("secondary");
+        std::fs::write(dir.join("LOG"), "").unwrap();
+        drop(Lease::Secondary(dir.clone()));
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn primary_lease_removes_only_its_own_owner_file() {
+        let dir = temp_dir("primary");
+        record_owner(&dir);
+        assert_eq!(recorded_owner(&dir), Some(std::process::id()));
+        drop(Lease::Primary(dir.clone()));
+        assert!(!dir.join(OWNER_FILE).exists());
+
+        // Another process took the cache over after this one let go
+        std::fs::write(dir.join(OWNER_FILE), "1\ninit\n").unwrap();
+        drop(Lease::Primary(dir.clone()));
+        assert_eq!(recorded_owner(&dir), Some(1));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn open(path: &Path, mode: CacheAccessMode) -> napi::Result<BuildCache> {
+        let options = BuildCacheOptions {
+            remote: None,
+            producer_version: None,
+            access_mode: Some(mode),
+            compression: None,
+            trace: None,
+        };
+        BuildCache::new(path.to_string_lossy().into_owned(), Some(options))
+    }
+
+    #[test]
+    fn shares_a_locked_cache_with_other_instances() {
+        // Spelled with `..`, which RocksDB's error repeats as given
+        let root = temp_dir("shared");
+        std::fs::create_dir_all(root.join("nested")).unwrap();
+        let path = root.join("nested/../cache");
+        let primary = open(&path, CacheAccessMode::Primary).unwrap();
+        primary.set("graph:a".into(), "first".into()).unwrap();
+
+        let error = open(&path, CacheAccessMode::Primary).err().unwrap();
+        assert!(error.reason.contains("is locked"), "{}", error.reason);
+        assert!(error.reason.contains(&format!("by process {}", std::process::id())), "{}", error.reason);
+
+        let read_only = open(&path, CacheAccessMode::ReadOnly).unwrap();
+        let secondary = open(&path, CacheAccessMode::Secondary).unwrap();
+        let auto = open(&path, CacheAccessMode::Auto).unwrap();
+        assert_eq!(read_only.get_access_mode(), CacheAccessMode::ReadOnly);
+        assert_eq!(secondary.get_access_mode(), CacheAccessMode::Secondary);
+        assert_eq!(auto.get_access_mode(), CacheAccessMode::Secondary);
+        for shared in [&read_only, &secondary, &auto] {
+            assert_eq!(shared.get("graph:a".into()).as_deref(), Some("first"));
+            assert!(shared.set("graph:b".into(), "no".into()).is_err());
+        }
+
+        // Only secondaries follow writes made after they opened, once they catch up
+        primary.set("graph:a".into(), "second".into()).unwrap();
+        secondary.catch_up().unwrap();
+        auto.catch_up().unwrap();
+        assert_eq!(secondary.get("graph:a".into()).as_deref(), Some("second"));
+        assert_eq!(auto.get("graph:a".into()).as_deref(), Some("second"));
+        assert_eq!(read_only.get("graph:a".into()).as_deref(), Some("first"));
+        assert!(read_only.catch_up().is_err());
+
+        drop((primary, read_only, secondary, auto));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn auto_takes_a_free_cache_as_primary() {
+        let root = temp_dir("auto");
+        let cache = open(&root.join("cache"), CacheAccessMode::Auto).unwrap();
+        assert_eq!(cache.get_access_mode(), CacheAccessMode::Primary);
+        assert_eq!(recorded_owner(&root.join("cache")), Some(std::process::id()));
+        drop(cache);
+        assert_eq!(recorded_owner(&root.join("cache")), None);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
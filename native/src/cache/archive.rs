@@ -0,0 +1,505 @@
+// Portable cache archives for Nexxo v2.0
+//
+// Archives let CI runners and new machines start from a warm cache without
+// copying RocksDB directories between platforms. Layout:
+//
+//   magic        b"NXXCACHE"
+//   version      u32 LE
+//   namespaces   u16 LE count, then (u16 LE length, UTF-8 bytes) each
+//   body         zstd stream of (u32 LE key length, key, u32 LE value length, value)
+//   checksum     u64 LE, XXH3 of the header and the uncompressed body
+//
+// Import verifies the checksum over the whole archive before writing
+// anything, and rejects keys and values over `MAX_ENTRY_BYTES` without
+// allocating them. Export writes next to the destination and renames, so an
+// interrupted export never leaves a partial archive behind.
+
+use napi_derive::napi;
+use nexxo_storage::{BatchOp, Storage};
+
+use super::envelope::Envelope;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use xxhash_rust::xxh3::Xxh3;
+
+const MAGIC: &[u8; 8] = b"NXXCACHE";
+const FORMAT_VERSION: u32 = 2;
+/// Largest key or value accepted on import
+const MAX_ENTRY_BYTES: u32 = 256 * 1024 * 1024;
+const CHECKSUM_LEN: u64 = 8;
+const COMPRESSION_LEVEL: i32 = 3;
+const IMPORT_BATCH_SIZE: usize = 4096;
+
+/// Result of exporting the cache to an archive
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[napi(object)]
+pub struct ExportReport {
+    pub entries: u32,
+    pub namespaces: Vec<String>,
+    pub uncompressed_bytes: f64,
+    pub archive_bytes: f64,
+}
+
+/// Result of importing an archive into the cache
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[napi(object)]
+pub struct ImportReport {
+    pub imported: u32,
+    pub unchanged: u32,
+    pub conflicts: u32,
+    /// Entries skipped because they were written under another schema or producer version
+    pub incompatible: u32,
+    /// Keys whose archived value differed from the local value
+    pub conflicting_keys: Vec<String>,
+    pub namespaces: Vec<String>,
+}
+
+/// Writes one entry to the archive
+type WriteEntry<'a> = dyn FnMut(&[u8], &[u8]) -> io::Result<()> + 'a;
+
+/// Hashes everything written through it
+struct HashingWriter<W: Write> {
+    inner: W,
+    hasher: Xxh3,
+    bytes: u64,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.bytes += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+pub(crate) fn export(
+    storage: &dyn Storage,
+    path: &Path,
+    namespaces: Option<Vec<String>>,
+) -> io::Result<ExportReport> {
+    let mut selected = namespaces.unwrap_or_default();
+    selected.sort();
+    selected.dedup();
+
+    // Every key starts with its namespace, known or not
+    let prefixes: Vec<String> = if selected.is_empty() {
+        vec![String::new()]
+    } else {
+        selected.iter().map(|namespace| format!("{}:", namespace)).collect()
+    };
+    let entries = |write: &mut WriteEntry| {
+        for prefix in &prefixes {
+            let mut failed = None;
+            storage.scan_prefix(prefix.as_bytes(), &mut |key, value| match write(key, value) {
+                Ok(()) => true,
+                Err(e) => {
+                    failed = Some(e);
+                    false
+                }
+            })?;
+            if let Some(e) = failed {
+                return Err(e);
+            }
+        }
+        Ok(())
+    };
+
+    let (entries, uncompressed_bytes) = write_archive(path, &selected, entries)?;
+    Ok(ExportReport {
+        entries,
+        namespaces: selected,
+        uncompressed_bytes: uncompressed_bytes as f64,
+        archive_bytes: std::fs::metadata(path)?.len() as f64,
+    })
+}
+
+/// Where `write_archive` builds the archive before renaming it into place
+fn partial_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}.partial", std::process::id()));
+    path.with_file_name(name)
+}
+
+/// Write the entries `entries` passes to its writer as an archive at `path`,
+/// returning the entry count and uncompressed body size
+fn write_archive(
+    path: &Path,
+    namespaces: &[String],
+    entries: impl FnOnce(&mut WriteEntry) -> io::Result<()>,
+) -> io::Result<(u32, u64)> {
+    let partial = partial_path(path);
+    let written = write_archive_to(&partial, namespaces, entries).and_then(|written| {
+        std::fs::rename(&partial, path)?;
+        Ok(written)
+    });
+    if written.is_err() {
+        let _ = std::fs::remove_file(&partial);
+    }
+    written
+}
+
+fn write_archive_to(
+    path: &Path,
+    namespaces: &[String],
+    entries: impl FnOnce(&mut WriteEntry) -> io::Result<()>,
+) -> io::Result<(u32, u64)> {
+    let mut header = HashingWriter {
+        inner: BufWriter::new(File::create(path)?),
+        hasher: Xxh3::new(),
+        bytes: 0,
+    };
+    header.write_all(MAGIC)?;
+    header.write_all(&FORMAT_VERSION.to_le_bytes())?;
+    header.write_all(&(namespaces.len() as u16).to_le_bytes())?;
+    for namespace in namespaces {
+        header.write_all(&(namespace.len() as u16).to_le_bytes())?;
+        header.write_all(namespace.as_bytes())?;
+    }
+
+    let encoder = zstd::Encoder::new(header.inner, COMPRESSION_LEVEL)?;
+    let mut body = HashingWriter {
+        inner: encoder,
+        hasher: header.hasher,
+        bytes: 0,
+    };
+
+    let mut count = 0u32;
+    entries(&mut |key, value| {
+        body.write_all(&(key.len() as u32).to_le_bytes())?;
+        body.write_all(key)?;
+        body.write_all(&(value.len() as u32).to_le_bytes())?;
+        body.write_all(value)?;
+        count += 1;
+        Ok(())
+    })?;
+
+    let checksum = body.hasher.digest();
+    let uncompressed_bytes = body.bytes;
+    let mut file = body.inner.finish()?;
+    file.write_all(&checksum.to_le_bytes())?;
+    file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    Ok((count, uncompressed_bytes))
+}
+
+/// Parsed archive header plus the byte range of the compressed body
+struct ArchiveHeader {
+    namespaces: Vec<String>,
+    /// Checksum state after the header, continued over the body
+    hasher: Xxh3,
+    body_start: u64,
+    body_len: u64,
+    checksum: u64,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn read_header(file: &mut File) -> io::Result<ArchiveHeader> {
+    let file_len = file.metadata()?.len();
+    let mut reader = BufReader::new(&mut *file);
+    let mut hasher = Xxh3::new();
+
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic).map_err(|_| invalid("Not a Nexxo cache archive"))?;
+    if &magic != MAGIC {
+        return Err(invalid("Not a Nexxo cache archive"));
+    }
+    hasher.update(&magic);
+
+    let version = read_u32(&mut reader).map_err(truncated)?;
+    if version != FORMAT_VERSION {
+        return Err(invalid(&format!(
+            "Unsupported cache archive version {} (expected {})",
+            version, FORMAT_VERSION
+        )));
+    }
+    hasher.update(&version.to_le_bytes());
+
+    let mut header_len = 8 + 4 + 2;
+    let count = read_u16(&mut reader).map_err(truncated)?;
+    hasher.update(&count.to_le_bytes());
+    let mut namespaces = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let len = read_u16(&mut reader).map_err(truncated)?;
+        let mut name = vec![0u8; len as usize];
+        reader.read_exact(&mut name).map_err(truncated)?;
+        hasher.update(&len.to_le_bytes());
+        hasher.update(&name);
+        namespaces.push(String::from_utf8(name).map_err(|_| invalid("Corrupt namespace list"))?);
+        header_len += 2 + len as u64;
+    }
+
+    if file_len < header_len + CHECKSUM_LEN {
+        return Err(invalid("Cache archive is truncated"));
+    }
+    drop(reader);
+
+    file.seek(SeekFrom::Start(file_len - CHECKSUM_LEN))?;
+    let mut checksum = [0u8; 8];
+    file.read_exact(&mut checksum)?;
+
+    Ok(ArchiveHeader {
+        namespaces,
+        hasher,
+        body_start: header_len,
+        body_len: file_len - header_len - CHECKSUM_LEN,
+        checksum: u64::from_le_bytes(checksum),
+    })
+}
+
+/// Map an unexpected end of file to a "truncated" error
+fn truncated(e: io::Error) -> io::Error {
+    if e.kind() == io::ErrorKind::UnexpectedEof {
+        invalid("Cache archive is truncated")
+    } else {
+        e
+    }
+}
+
+/// Read one length-prefixed key or value, refusing lengths over `MAX_ENTRY_BYTES`
+fn read_field(reader: &mut impl Read, hasher: &mut Xxh3) -> io::Result<Vec<u8>> {
+    let len = read_u32(reader).map_err(truncated)?;
+    if len > MAX_ENTRY_BYTES {
+        return Err(invalid("Cache archive entry is too large"));
+    }
+    hasher.update(&len.to_le_bytes());
+    let mut field = Vec::new();
+    reader.take(len as u64).read_to_end(&mut field)?;
+    if field.len() != len as usize {
+        return Err(invalid("Cache archive is truncated"));
+    }
+    hasher.update(&field);
+    Ok(field)
+}
+
+/// Stream every entry of the archive body through `visit`, returning the
+/// checksum of the header and body
+fn for_each_entry(
+    file: &mut File,
+    header: &ArchiveHeader,
+    mut visit: impl FnMut(Vec<u8>, Vec<u8>) -> io::Result<()>,
+) -> io::Result<u64> {
+    file.seek(SeekFrom::Start(header.body_start))?;
+    let compressed = BufReader::new(&mut *file).take(header.body_len);
+    let mut decoder = zstd::Decoder::new(compressed)?;
+    let mut hasher = header.hasher.clone();
+
+    loop {
+        // A clean end of the stream can only fall between entries
+        let mut first = [0u8; 1];
+        match decoder.read(&mut first) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(truncated(e)),
+        }
+        let key = read_field(&mut (&first[..]).chain(&mut decoder), &mut hasher)?;
+        let value = read_field(&mut decoder, &mut hasher)?;
+        visit(key, value)?;
+    }
+
+    Ok(hasher.digest())
+}
+
+/// Entries of the archive at `path`, after checking the whole archive is intact
+fn read_archive(
+    path: &Path,
+    visit: impl FnMut(Vec<u8>, Vec<u8>) -> io::Result<()>,
+) -> io::Result<Vec<String>> {
+    let mut file = File::open(path)?;
+    let header = read_header(&mut file)?;
+
+    // First pass: make sure the archive is intact before visiting anything
+    let checksum = for_each_entry(&mut file, &header, |_, _| Ok(()))?;
+    if checksum != header.checksum {
+        return Err(invalid("Cache archive checksum mismatch"));
+    }
+    for_each_entry(&mut file, &header, visit)?;
+    Ok(header.namespaces)
+}
+
+pub(crate) fn import(
+    storage: &dyn Storage,
+    path: &Path,
+    overwrite: bool,
+    is_compatible: &dyn Fn(&[u8]) -> bool,
+) -> io::Result<ImportReport> {
+    let mut report = ImportReport {
+        imported: 0,
+        unchanged: 0,
+        conflicts: 0,
+        incompatible: 0,
+        conflicting_keys: Vec::new(),
+        namespaces: Vec::new(),
+    };
+    let mut batch = Vec::new();
+
+    report.namespaces = read_archive(path, |key, value| {
+        if !is_compatible(&value) {
+            report.incompatible += 1;
+            return Ok(());
+        }
+
+        match storage.get(&key)? {
+            // Envelopes also record when they were written and last read, so
+            // compare what they hold rather than their bytes
+            Some(existing) if same_contents(&existing, &value) => {
+                report.unchanged += 1;
+                return Ok(());
+            }
+            Some(_) => {
+                report.conflicts += 1;
+                report.conflicting_keys.push(String::from_utf8_lossy(&key).into_owned());
+                if !overwrite {
+                    return Ok(());
+                }
+            }
+            None => {}
+        }
+
+        batch.push(BatchOp::Put(key, value));
+        report.imported += 1;
+        if batch.len() >= IMPORT_BATCH_SIZE {
+            storage.write(std::mem::take(&mut batch))?;
+        }
+        Ok(())
+    })?;
+
+    storage.write(batch)?;
+    Ok(report)
+}
+
+fn same_contents(a: &[u8], b: &[u8]) -> bool {
+    match (Envelope::decode(a), Envelope::decode(b)) {
+        (Some(a), Some(b)) => a.same_contents(&b),
+        _ => a == b,
+    }
+}
+
+fn read_u16(reader: &mut impl Read) -> io::Result<u16> {
+    let mut buf = [0u8; 2];
+    reader.read_exact(&mut buf)?;
+    Ok(u16::from_le_bytes(buf))
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn temp_archive() -> PathBuf {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        std::env::temp_dir().join(format!(
+            "nexxo-archive-test-{}-{}.nxc",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ))
+    }
+
+    fn sample_entries() -> Vec<(Vec<u8>, Vec<u8>)> {
+        vec![
+            (b"input:src/App.tsx:abc".to_vec(), b"compiled".to_vec()),
+            (b"graph:deadbeef".to_vec(), Vec::new()),
+            (b"plan:prod:1".to_vec(), vec![0xff; 10_000]),
+        ]
+    }
+
+    fn write_sample(path: &Path) {
+        let namespaces = vec!["graph".to_string(), "input".to_string()];
+        let (count, _) = write_archive(path, &namespaces, |write| {
+            sample_entries().iter().try_for_each(|(key, value)| write(key, value))
+        })
+        .unwrap();
+        assert_eq!(count, 3);
+    }
+
+    fn read_all(path: &Path) -> io::Result<(Vec<String>, Vec<(Vec<u8>, Vec<u8>)>)> {
+        let mut entries = Vec::new();
+        let namespaces = read_archive(path, |key, value| {
+            entries.push((key, value));
+            Ok(())
+        })?;
+        Ok((namespaces, entries))
+    }
+
+    #[test]
+    fn round_trips_entries_and_namespaces() {
+        let path = temp_archive();
+        write_sample(&path);
+        assert!(!partial_path(&path).exists());
+
+        let (namespaces, entries) = read_all(&path).unwrap();
+        assert_eq!(namespaces, ["graph", "input"]);
+        assert_eq!(entries, sample_entries());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rejects_a_corrupt_checksum_or_header() {
+        let path = temp_archive();
+        write_sample(&path);
+        let original = std::fs::read(&path).unwrap();
+
+        let mut bytes = original.clone();
+        *bytes.last_mut().unwrap() ^= 1;
+        std::fs::write(&path, &bytes).unwrap();
+        assert_eq!(read_all(&path).unwrap_err().to_string(), "Cache archive checksum mismatch");
+
+        // Renaming a namespace in the header is caught too
+        let mut bytes = original;
+        let at = bytes.windows(5).position(|window| window == b"graph").unwrap();
+        bytes[at] = b'G';
+        std::fs::write(&path, &bytes).unwrap();
+        assert_eq!(read_all(&path).unwrap_err().to_string(), "Cache archive checksum mismatch");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rejects_truncated_archives() {
+        let path = temp_archive();
+        write_sample(&path);
+        let bytes = std::fs::read(&path).unwrap();
+
+        for len in [4, 12, 20, bytes.len() / 2, bytes.len() - 1] {
+            std::fs::write(&path, &bytes[..len]).unwrap();
+            assert!(read_all(&path).is_err(), "accepted {} of {} bytes", len, bytes.len());
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rejects_oversized_entries_without_allocating_them() {
+        let path = temp_archive();
+        let mut body = Vec::new();
+        body.extend_from_slice(&u32::MAX.to_le_bytes());
+        body.extend_from_slice(b"key");
+
+        let mut hasher = Xxh3::new();
+        let mut file = Vec::new();
+        file.extend_from_slice(MAGIC);
+        file.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        file.extend_from_slice(&0u16.to_le_bytes());
+        hasher.update(&file);
+        hasher.update(&body);
+        file.extend(zstd::encode_all(&body[..], COMPRESSION_LEVEL).unwrap());
+        file.extend_from_slice(&hasher.digest().to_le_bytes());
+        std::fs::write(&path, file).unwrap();
+
+        assert_eq!(read_all(&path).unwrap_err().to_string(), "Cache archive entry is too large");
+        std::fs::remove_file(path).unwrap();
+    }
+}
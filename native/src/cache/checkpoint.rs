@@ -0,0 +1,225 @@
+// Cache checkpoints for Nexxo v2.0
+//
+// A checkpoint is a RocksDB checkpoint of the whole cache. SST files never
+// change once written, so they are hard-linked when the checkpoint is on the
+// same filesystem and only the small manifest and options files are copied.
+// Taking a checkpoint costs a memtable flush; restoring one links the files
+// back into place, so a known-good cache can be set aside before upgrades or
+// mass invalidation and brought back in seconds.
+//
+// Relative checkpoint paths are placed under `{cache path}.checkpoints/`.
+
+use napi_derive::napi;
+use rocksdb::checkpoint::Checkpoint;
+use rocksdb::DB;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// File in each checkpoint describing it; absent from RocksDB's own files
+const MANIFEST_FILE: &str = "NEXXO_CHECKPOINT";
+
+/// A checkpoint of a `BuildCache`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[napi(object)]
+pub struct CheckpointInfo {
+    /// Directory name of the checkpoint
+    pub name: String,
+    pub path: String,
+    pub created_at: i64,
+    pub schema_version: u32,
+    pub producer_version: String,
+    /// RocksDB estimate at the time the checkpoint was taken
+    pub estimated_entries: i64,
+    pub size_bytes: f64,
+}
+
+/// Contents of `MANIFEST_FILE`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Manifest {
+    pub created_at: i64,
+    pub schema_version: u32,
+    pub producer_version: String,
+    pub estimated_entries: i64,
+    pub size_bytes: f64,
+}
+
+impl Manifest {
+    fn into_info(self, dir: &Path) -> CheckpointInfo {
+        CheckpointInfo {
+            name: dir
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            path: dir.to_string_lossy().into_owned(),
+            created_at: self.created_at,
+            schema_version: self.schema_version,
+            producer_version: self.producer_version,
+            estimated_entries: self.estimated_entries,
+            size_bytes: self.size_bytes,
+        }
+    }
+}
+
+/// `path` with `suffix` appended to its last component
+pub(crate) fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Directory holding checkpoints given by name
+pub(crate) fn checkpoints_root(cache_path: &Path) -> PathBuf {
+    sibling(cache_path, ".checkpoints")
+}
+
+/// Where the checkpoint `dir` lives
+pub(crate) fn resolve(cache_path: &Path, dir: &str) -> PathBuf {
+    let dir = Path::new(dir);
+    if dir.is_absolute() {
+        dir.to_path_buf()
+    } else {
+        checkpoints_root(cache_path).join(dir)
+    }
+}
+
+/// Take a checkpoint of `db` in `dir`, which must not exist yet
+pub(crate) fn create(db: &DB, dir: &Path, manifest: Manifest) -> io::Result<CheckpointInfo> {
+    if dir.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", dir.display()),
+        ));
+    }
+    if let Some(parent) = dir.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    Checkpoint::new(db)
+        .and_then(|checkpoint| checkpoint.create_checkpoint(dir))
+        .map_err(io::Error::other)?;
+    let json = serde_json::to_vec_pretty(&manifest).map_err(io::Error::other)?;
+    fs::write(dir.join(MANIFEST_FILE), json)?;
+    Ok(manifest.into_info(dir))
+}
+
+/// Describe the checkpoint in `dir`, or `None` if it is not one
+pub(crate) fn read(dir: &Path) -> Option<CheckpointInfo> {
+    let json = fs::read(dir.join(MANIFEST_FILE)).ok()?;
+    let manifest: Manifest = serde_json::from_slice(&json).ok()?;
+    Some(manifest.into_info(dir))
+}
+
+/// Checkpoints directly under `root`, oldest first
+pub(crate) fn list(root: &Path) -> Vec<CheckpointInfo> {
+    let Ok(entries) = fs::read_dir(root) else {
+        return Vec::new();
+    };
+    let mut checkpoints: Vec<CheckpointInfo> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| read(&entry.path()))
+        .collect();
+    checkpoints.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.name.cmp(&b.name)));
+    checkpoints
+}
+
+/// Lay out a database directory at `target` with the files of `checkpoint`,
+/// hard-linking SST files where possible
+pub(crate) fn stage(checkpoint: &Path, target: &Path) -> io::Result<()> {
+    if target.exists() {
+        fs::remove_dir_all(target)?;
+    }
+    fs::create_dir_all(target)?;
+
+    for entry in fs::read_dir(checkpoint)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if name == MANIFEST_FILE || !entry.file_type()?.is_file() {
+            continue;
+        }
+        let (from, to) = (entry.path(), target.join(&name));
+        // Only SST files are immutable; RocksDB appends to the others after opening
+        let linked = from.extension().is_some_and(|ext| ext == "sst") && fs::hard_link(&from, &to).is_ok();
+        if !linked {
+            fs::copy(&from, &to)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::BuildCache;
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn temp_cache() -> (BuildCache, PathBuf) {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "nexxo-checkpoint-test-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&path);
+        let _ = fs::remove_dir_all(checkpoints_root(&path));
+        let cache = BuildCache::new(path.to_string_lossy().into_owned(), None).unwrap();
+        (cache, path)
+    }
+
+    #[test]
+    fn restore_brings_back_the_checkpointed_values() {
+        let (mut cache, path) = temp_cache();
+        cache.set("graph:g".into(), "good".into()).unwrap();
+        cache.set("plan:prod:p".into(), "kept".into()).unwrap();
+
+        let info = cache.checkpoint("known-good".into()).unwrap();
+        assert_eq!(info.name, "known-good");
+        assert_eq!(Path::new(&info.path), checkpoints_root(&path).join("known-good"));
+        assert!(cache.checkpoint("known-good".into()).is_err());
+
+        cache.set("graph:g".into(), "broken".into()).unwrap();
+        cache.set("graph:new".into(), "added later".into()).unwrap();
+        cache.delete("plan:prod:p".into()).unwrap();
+
+        cache.restore("known-good".into()).unwrap();
+        assert_eq!(cache.get("graph:g".into()).as_deref(), Some("good"));
+        assert_eq!(cache.get("plan:prod:p".into()).as_deref(), Some("kept"));
+        assert_eq!(cache.get("graph:new".into()), None);
+        // The restored cache is writable, and the checkpoint is left as it was
+        cache.set("graph:g".into(), "rebuilt".into()).unwrap();
+        cache.restore(info.path).unwrap();
+        assert_eq!(cache.get("graph:g".into()).as_deref(), Some("good"));
+
+        assert!(cache.restore("missing".into()).is_err());
+        assert_eq!(cache.get("graph:g".into()).as_deref(), Some("good"));
+
+        drop(cache);
+        fs::remove_dir_all(checkpoints_root(&path)).unwrap();
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn lists_checkpoints_oldest_first() {
+        let (cache, path) = temp_cache();
+        assert!(cache.list_checkpoints().is_empty());
+
+        cache.set("graph:g".into(), "1".into()).unwrap();
+        cache.checkpoint("b-first".into()).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        cache.set("graph:h".into(), "2".into()).unwrap();
+        cache.checkpoint("a-second".into()).unwrap();
+        // Directories that are not checkpoints are left out
+        fs::create_dir_all(checkpoints_root(&path).join("stray")).unwrap();
+
+        let listed = cache.list_checkpoints();
+        let names: Vec<&str> = listed.iter().map(|info| info.name.as_str()).collect();
+        assert_eq!(names, ["b-first", "a-second"]);
+        assert!(listed[0].created_at < listed[1].created_at);
+        assert_eq!(listed[1].producer_version, listed[0].producer_version);
+
+        drop(cache);
+        fs::remove_dir_all(checkpoints_root(&path)).unwrap();
+        fs::remove_dir_all(path).unwrap();
+    }
+}
@@ -0,0 +1,541 @@
+// Compression for BuildCache in Nexxo v2.0
+//
+// Blocks are compressed by RocksDB with the configured algorithm. With zstd
+// and a dictionary size set, each namespace also gets a dictionary trained
+// from samples of its existing entries and stored in the meta column family.
+// Entries written after that are compressed with their namespace's
+// dictionary before they reach RocksDB. Artifacts are thousands of small,
+// similar JS modules, which compress poorly one at a time but share most of
+// their byte patterns with the rest of their namespace.
+//
+// A namespace's dictionary is trained once, when a primary opens a cache
+// holding enough of its entries and none is stored yet, and is never
+// replaced, so every entry it compressed stays readable. Entries written
+// before it, or under other settings, are stored as they were.
+//
+// Files written under other block settings keep their old compression until
+// compaction rewrites them; `compact()` forces that for existing entries.
+
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+use nexxo_storage::{BatchOp, Storage};
+use rocksdb::{properties, ColumnFamily, DBCompressionType, IteratorMode, Options, DB};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::io::{self, Read};
+use std::sync::{Arc, OnceLock};
+use zstd::dict::{DecoderDictionary, EncoderDictionary};
+
+use super::{namespace_cf, namespace_index, NAMESPACES};
+
+/// zstd level used when none is given
+const DEFAULT_ZSTD_LEVEL: i32 = 3;
+
+/// Dictionary size used when none is given
+const DEFAULT_DICTIONARY_BYTES: u32 = 16 * 1024;
+
+/// Fewest entries a namespace needs before a dictionary is trained for it
+const MIN_TRAINING_SAMPLES: usize = 64;
+
+/// Key in `META_CF` of a namespace's dictionary, followed by the namespace name
+const DICTIONARY_KEY_PREFIX: &str = "dictionary:";
+
+/// First bytes of a zstd frame. Envelopes start with `NX`, so a stored value
+/// starting with these was compressed with its namespace's dictionary.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// zstd recommends roughly 100x the dictionary size as training input
+const TRAINING_BYTES_PER_DICTIONARY_BYTE: i64 = 100;
+
+/// Window bits and strategy are only used by zlib; these are RocksDB's defaults
+const DEFAULT_WINDOW_BITS: i32 = -14;
+const DEFAULT_STRATEGY: i32 = 0;
+
+/// Tells RocksDB to use the library's default level
+const LIBRARY_DEFAULT_LEVEL: i32 = 32767;
+
+/// Block compression algorithm
+#[napi(string_enum)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CacheCompression {
+    None,
+    Lz4,
+    /// zstd, with a dictionary trained for each namespace
+    Zstd,
+}
+
+/// Compression settings for `BuildCacheOptions.compression`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[napi(object)]
+pub struct CompressionOptions {
+    /// Algorithm (default: Lz4)
+    pub algorithm: Option<CacheCompression>,
+    /// zstd level from 1 to 22 (default: 3). Only valid with Zstd.
+    pub level: Option<i32>,
+    /// Maximum size of each namespace's zstd dictionary (default: 16 KiB).
+    /// 0 compresses every entry on its own.
+    pub dictionary_bytes: Option<u32>,
+}
+
+/// Validated compression settings
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Compression {
+    algorithm: CacheCompression,
+    level: i32,
+    dictionary_bytes: u32,
+}
+
+impl Compression {
+    pub(crate) fn from_options(options: Option<&CompressionOptions>) -> Result<Self> {
+        let algorithm = options
+            .and_then(|options| options.algorithm)
+            .unwrap_or(CacheCompression::Lz4);
+        let level = options.and_then(|options| options.level);
+        let dictionary_bytes = options.and_then(|options| options.dictionary_bytes);
+
+        if algorithm != CacheCompression::Zstd {
+            if level.is_some() || dictionary_bytes.is_some() {
+                return Err(Error::from_reason(format!(
+                    "Compression level and dictionary size only apply to Zstd, not {:?}",
+                    algorithm
+                )));
+            }
+            return Ok(Self {
+                algorithm,
+                level: LIBRARY_DEFAULT_LEVEL,
+                dictionary_bytes: 0,
+            });
+        }
+
+        let level = level.unwrap_or(DEFAULT_ZSTD_LEVEL);
+        if !(1..=22).contains(&level) {
+            return Err(Error::from_reason(format!(
+                "zstd compression level must be between 1 and 22, got {}",
+                level
+            )));
+        }
+        let dictionary_bytes = dictionary_bytes.unwrap_or(DEFAULT_DICTIONARY_BYTES);
+        if dictionary_bytes as i64 * TRAINING_BYTES_PER_DICTIONARY_BYTE > i32::MAX as i64 {
+            return Err(Error::from_reason(format!(
+                "zstd dictionary size must be at most {} bytes",
+                i32::MAX as i64 / TRAINING_BYTES_PER_DICTIONARY_BYTE
+            )));
+        }
+
+        Ok(Self {
+            algorithm,
+            level,
+            dictionary_bytes,
+        })
+    }
+
+    /// Configure block compression for a column family. Dictionaries are
+    /// applied to entries by `Dictionaries`, not by RocksDB.
+    pub(crate) fn apply(&self, opts: &mut Options) {
+        let compression_type = match self.algorithm {
+            CacheCompression::None => DBCompressionType::None,
+            CacheCompression::Lz4 => DBCompressionType::Lz4,
+            CacheCompression::Zstd => DBCompressionType::Zstd,
+        };
+        opts.set_compression_type(compression_type);
+        opts.set_compression_options(DEFAULT_WINDOW_BITS, self.level, DEFAULT_STRATEGY, 0);
+    }
+
+    /// Maximum dictionary size, if namespaces get zstd dictionaries
+    pub(crate) fn dictionary_bytes(&self) -> Option<usize> {
+        (self.algorithm == CacheCompression::Zstd && self.dictionary_bytes > 0)
+            .then_some(self.dictionary_bytes as usize)
+    }
+}
+
+/// A namespace's trained dictionary, prepared for both directions
+struct NamespaceDictionary {
+    decoder: DecoderDictionary<'static>,
+    /// `None` unless the cache was opened with zstd dictionaries
+    encoder: Option<EncoderDictionary<'static>>,
+}
+
+/// The zstd dictionaries of a cache's namespaces, in the order of `NAMESPACES`.
+/// Stored dictionaries are always used to read; they are only used to write
+/// when the cache was opened with zstd dictionaries.
+pub(crate) struct Dictionaries {
+    /// zstd level to compress entries at, when they are compressed
+    level: Option<i32>,
+    namespaces: Vec<OnceLock<NamespaceDictionary>>,
+}
+
+impl Dictionaries {
+    pub(crate) fn new(compression: &Compression) -> Self {
+        Self {
+            level: compression.dictionary_bytes().map(|_| compression.level),
+            namespaces: NAMESPACES.iter().map(|_| OnceLock::new()).collect(),
+        }
+    }
+
+    /// Read only, with nothing loaded
+    pub(crate) fn none() -> Self {
+        Self {
+            level: None,
+            namespaces: NAMESPACES.iter().map(|_| OnceLock::new()).collect(),
+        }
+    }
+
+    /// Load the stored dictionaries that are not loaded yet
+    pub(crate) fn load(&self, meta: &dyn Storage) -> io::Result<()> {
+        for (index, name) in NAMESPACES.iter().enumerate() {
+            if self.namespaces[index].get().is_none() {
+                if let Some(dictionary) = meta.get(dictionary_key(name).as_bytes())? {
+                    self.install(index, &dictionary);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn install(&self, index: usize, dictionary: &[u8]) {
+        let _ = self.namespaces[index].set(NamespaceDictionary {
+            decoder: DecoderDictionary::copy(dictionary),
+            encoder: self.level.map(|level| EncoderDictionary::copy(dictionary, level)),
+        });
+    }
+
+    /// Train a dictionary of at most `max_bytes` for each namespace that has
+    /// none yet and holds enough entries, and store it in `meta`. Returns the
+    /// number of dictionaries trained.
+    pub(crate) fn train(&self, db: &DB, meta: &dyn Storage, max_bytes: usize) -> io::Result<usize> {
+        let training_bytes = max_bytes.saturating_mul(TRAINING_BYTES_PER_DICTIONARY_BYTE as usize);
+        let mut trained = 0;
+        for (index, name) in NAMESPACES.iter().enumerate() {
+            if self.namespaces[index].get().is_some() {
+                continue;
+            }
+
+            let (mut samples, mut sampled_bytes) = (Vec::new(), 0);
+            for item in db.iterator_cf(namespace_cf(db, index), IteratorMode::Start) {
+                let (_, value) = item.map_err(io::Error::other)?;
+                sampled_bytes += value.len();
+                samples.push(value);
+                if sampled_bytes >= training_bytes {
+                    break;
+                }
+            }
+            if samples.len() < MIN_TRAINING_SAMPLES {
+                continue;
+            }
+            // zstd gives up on samples with too little in common; try again next time
+            let Ok(dictionary) = zstd::dict::from_samples(&samples, max_bytes) else {
+                continue;
+            };
+
+            meta.put(dictionary_key(name).as_bytes(), &dictionary)?;
+            self.install(index, &dictionary);
+            trained += 1;
+        }
+        Ok(trained)
+    }
+
+    /// Compress a value with its namespace's dictionary. Values without a
+    /// dictionary to write with, or that would not shrink, are kept as they are.
+    pub(crate) fn compress<'a>(&self, key: &[u8], value: &'a [u8]) -> Cow<'a, [u8]> {
+        let encoder = self.namespaces[namespace_index(key)]
+            .get()
+            .and_then(|dictionary| dictionary.encoder.as_ref());
+        let compressed = encoder.and_then(|encoder| {
+            zstd::bulk::Compressor::with_prepared_dictionary(encoder)
+                .and_then(|mut compressor| compressor.compress(value))
+                .ok()
+        });
+        match compressed {
+            Some(compressed) if compressed.len() < value.len() => Cow::Owned(compressed),
+            _ => Cow::Borrowed(value),
+        }
+    }
+
+    /// Undo `compress`. Values that can't be decompressed are returned as
+    /// stored, so they fail envelope decoding like any other corrupt entry.
+    pub(crate) fn decompress<'a>(&self, key: &[u8], value: &'a [u8]) -> Cow<'a, [u8]> {
+        if !value.starts_with(&ZSTD_MAGIC) {
+            return Cow::Borrowed(value);
+        }
+        let Some(dictionary) = self.namespaces[namespace_index(key)].get() else {
+            return Cow::Borrowed(value);
+        };
+        let mut decompressed = Vec::new();
+        let read = zstd::stream::read::Decoder::with_prepared_dictionary(value, &dictionary.decoder)
+            .and_then(|mut decoder| decoder.read_to_end(&mut decompressed));
+        match read {
+            Ok(_) => Cow::Owned(decompressed),
+            Err(_) => Cow::Borrowed(value),
+        }
+    }
+
+    /// `decompress` for a value the caller owns
+    fn decompress_owned(&self, key: &[u8], value: Vec<u8>) -> Vec<u8> {
+        match self.decompress(key, &value) {
+            Cow::Owned(decompressed) => decompressed,
+            Cow::Borrowed(_) => value,
+        }
+    }
+}
+
+fn dictionary_key(namespace: &str) -> String {
+    format!("{}{}", DICTIONARY_KEY_PREFIX, namespace)
+}
+
+/// Entries compressed with their namespace's dictionary on the way into
+/// `inner` and decompressed on the way out
+pub(crate) struct DictionaryCompressed<S: Storage> {
+    pub inner: S,
+    pub dictionaries: Arc<Dictionaries>,
+}
+
+impl<S: Storage> Storage for DictionaryCompressed<S> {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn get(&self, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.inner.get(key)?.map(|value| self.dictionaries.decompress_owned(key, value)))
+    }
+
+    fn multi_get(&self, keys: &[&[u8]]) -> io::Result<Vec<Option<Vec<u8>>>> {
+        Ok(self
+            .inner
+            .multi_get(keys)?
+            .into_iter()
+            .zip(keys)
+            .map(|(value, key)| value.map(|value| self.dictionaries.decompress_owned(key, value)))
+            .collect())
+    }
+
+    fn put(&self, key: &[u8], value: &[u8]) -> io::Result<()> {
+        self.inner.put(key, &self.dictionaries.compress(key, value))
+    }
+
+    fn delete(&self, key: &[u8]) -> io::Result<()> {
+        self.inner.delete(key)
+    }
+
+    fn write(&self, batch: Vec<BatchOp>) -> io::Result<()> {
+        let batch = batch
+            .into_iter()
+            .map(|op| match op {
+                BatchOp::Put(key, value) => {
+                    let value = self.dictionaries.compress(&key, &value).into_owned();
+                    BatchOp::Put(key, value)
+                }
+                op => op,
+            })
+            .collect();
+        self.inner.write(batch)
+    }
+
+    fn scan_prefix(&self, prefix: &[u8], visit: &mut dyn FnMut(&[u8], &[u8]) -> bool) -> io::Result<()> {
+        self.inner
+            .scan_prefix(prefix, &mut |key, value| visit(key, &self.dictionaries.decompress(key, value)))
+    }
+
+    fn scan_from(
+        &self,
+        prefix: &[u8],
+        start: &[u8],
+        visit: &mut dyn FnMut(&[u8], &[u8]) -> bool,
+    ) -> io::Result<()> {
+        self.inner
+            .scan_from(prefix, start, &mut |key, value| visit(key, &self.dictionaries.decompress(key, value)))
+    }
+
+    fn clear(&self) -> io::Result<()> {
+        self.inner.clear()
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Uncompressed and on-disk size of the data blocks in a column family's SST
+/// files. Memtables are not compressed and are not counted.
+pub(crate) fn block_sizes(db: &DB, cf: &ColumnFamily) -> (u64, u64) {
+    let Some(table_properties) = db
+        .property_value_cf(cf, properties::AGGREGATED_TABLE_PROPERTIES)
+        .ok()
+        .flatten()
+    else {
+        return (0, 0);
+    };
+
+    // Formatted as "name=value; name=value; ..."
+    let (mut raw, mut stored) = (0u64, 0u64);
+    for property in table_properties.split(';') {
+        let Some((name, value)) = property.split_once('=') else {
+            continue;
+        };
+        let value = value.trim().parse::<u64>().unwrap_or(0);
+        match name.trim() {
+            "raw key size" | "raw value size" => raw += value,
+            "data block size" => stored += value,
+            _ => {}
+        }
+    }
+    (raw, stored)
+}
+
+/// Uncompressed size divided by on-disk size; 1 until anything is flushed
+pub(crate) fn ratio(raw: u64, stored: u64) -> f64 {
+    if raw == 0 || stored == 0 {
+        1.0
+    } else {
+        raw as f64 / stored as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{BuildCache, BuildCacheOptions};
+    use super::*;
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn temp_path() -> PathBuf {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "nexxo-compression-test-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_dir_all(&path);
+        path
+    }
+
+    fn open(path: &Path, compression: CompressionOptions) -> Result<BuildCache> {
+        let options = BuildCacheOptions {
+            remote: None,
+            producer_version: None,
+            access_mode: None,
+            compression: Some(compression),
+            trace: None,
+        };
+        BuildCache::new(path.to_string_lossy().into_owned(), Some(options))
+    }
+
+    fn zstd(level: Option<i32>) -> CompressionOptions {
+        CompressionOptions {
+            algorithm: Some(CacheCompression::Zstd),
+            level,
+            dictionary_bytes: None,
+        }
+    }
+
+    /// A small compiled module, much like its neighbours
+    fn module(index: usize) -> String {
+        format!(
+            "import {{ createElement }} from 'react';\n\
+             export const Card{index} = ({{ title, children }}) =>\n  \
+             createElement('section', {{ className: 'card card-{index}', 'data-index': {index} }},\n    \
+             createElement('h2', {{ className: 'card-title' }}, title),\n    \
+             createElement('div', {{ className: 'card-body' }}, children));\n\
+             export default Card{index};\n"
+        )
+    }
+
+    /// The bytes RocksDB holds for a key, before any dictionary is undone
+    fn stored(cache: &BuildCache, key: &str) -> Vec<u8> {
+        let db = cache.backend.db.as_ref().unwrap();
+        let cf = namespace_cf(db, namespace_index(key.as_bytes()));
+        db.get_cf(cf, key).unwrap().unwrap()
+    }
+
+    #[test]
+    fn zstd_at_a_level_compresses_similar_entries() {
+        let path = temp_path();
+        let cache = open(&path, zstd(Some(19))).unwrap();
+        for index in 0..200 {
+            cache.set(format!("artifact:prod:card-{}.js", index), module(index)).unwrap();
+        }
+        cache.compact().unwrap();
+
+        let stats = cache.get_stats().unwrap();
+        assert!(stats.compression_ratio > 1.0, "ratio {}", stats.compression_ratio);
+        let artifact = stats.namespaces.iter().find(|stats| stats.namespace == "artifact").unwrap();
+        assert!(artifact.compression_ratio > 1.0);
+
+        drop(cache);
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn trains_a_dictionary_per_namespace_from_existing_entries() {
+        let path = temp_path();
+        let cache = open(&path, zstd(None)).unwrap();
+        for index in 0..200 {
+            cache.set(format!("artifact:prod:card-{}.js", index), module(index)).unwrap();
+        }
+        cache.set("graph:g".into(), "too few to train on".into()).unwrap();
+        assert!(cache.backend.meta.get(b"dictionary:artifact").unwrap().is_none());
+        drop(cache);
+
+        // Reopening trains the artifact namespace, which now has enough entries
+        let cache = open(&path, zstd(None)).unwrap();
+        assert!(cache.backend.meta.get(b"dictionary:artifact").unwrap().is_some());
+        assert!(cache.backend.meta.get(b"dictionary:graph").unwrap().is_none());
+
+        cache.set("artifact:prod:card-new.js".into(), module(1000)).unwrap();
+        cache.set("graph:g2".into(), "not compressed".into()).unwrap();
+        let compressed = stored(&cache, "artifact:prod:card-new.js");
+        assert!(compressed.starts_with(&ZSTD_MAGIC));
+        assert!(compressed.len() < module(1000).len());
+        assert!(stored(&cache, "graph:g2").starts_with(b"NX"));
+        // Entries written before the dictionary stay as they were
+        assert!(stored(&cache, "artifact:prod:card-7.js").starts_with(b"NX"));
+
+        assert_eq!(cache.get("artifact:prod:card-new.js".into()), Some(module(1000)));
+        assert_eq!(cache.get("artifact:prod:card-7.js".into()), Some(module(7)));
+        let snapshot = cache.snapshot().unwrap();
+        assert_eq!(snapshot.get("artifact:prod:card-new.js".into()).unwrap(), Some(module(1000)));
+        drop(snapshot);
+        assert_eq!(cache.verify().unwrap().corrupted, 0);
+        drop(cache);
+
+        // Other settings still read with the stored dictionary, but write without it
+        let lz4 = CompressionOptions {
+            algorithm: Some(CacheCompression::Lz4),
+            level: None,
+            dictionary_bytes: None,
+        };
+        let cache = open(&path, lz4).unwrap();
+        assert_eq!(cache.get("artifact:prod:card-new.js".into()), Some(module(1000)));
+        cache.set("artifact:prod:card-lz4.js".into(), module(2000)).unwrap();
+        assert!(stored(&cache, "artifact:prod:card-lz4.js").starts_with(b"NX"));
+
+        drop(cache);
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn only_zstd_takes_a_level_or_dictionary_size() {
+        let path = temp_path();
+        let lz4_with_level = CompressionOptions {
+            algorithm: Some(CacheCompression::Lz4),
+            level: Some(9),
+            dictionary_bytes: None,
+        };
+        let none_with_dictionary = CompressionOptions {
+            algorithm: Some(CacheCompression::None),
+            level: None,
+            dictionary_bytes: Some(4096),
+        };
+        let default_with_level = CompressionOptions {
+            algorithm: None,
+            level: Some(3),
+            dictionary_bytes: None,
+        };
+        for options in [lz4_with_level, none_with_dictionary, default_with_level] {
+            let error = open(&path, options).err().unwrap();
+            assert!(error.reason.contains("only apply to Zstd"), "{}", error.reason);
+        }
+        assert!(open(&path, zstd(Some(23))).is_err());
+        // Settings are checked before anything is created
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,195 @@
+// Cache entry envelopes for Nexxo v2.0
+//
+// Every value in the cache is wrapped in a small binary header so entries
+// written by an older Nexxo (or an older transformer) can be told apart from
+// current ones. Layout:
+//
+//   magic            b"NX"
+//   format           u8
+//   schema version   u32 LE
+//   created at       i64 LE (ms since epoch)
+//   last access      i64 LE (ms since epoch)
+//   payload size     u64 LE
+//   checksum         u64 LE, XXH3 of the payload
+//   producer         u8 length, then UTF-8 bytes
+//   payload
+//
+// The checksum covers only the payload so refreshing the last-access time
+// does not have to rehash the value.
+
+use napi_derive::napi;
+use serde::{Deserialize, Serialize};
+use xxhash_rust::xxh3::xxh3_64;
+
+const MAGIC: &[u8; 2] = b"NX";
+const FORMAT: u8 = 2;
+const FIXED_HEADER_LEN: usize = 2 + 1 + 4 + 8 + 8 + 8 + 8 + 1;
+const LAST_ACCESS_OFFSET: usize = 2 + 1 + 4 + 8;
+
+/// Bump whenever the meaning of cached values changes
+pub(crate) const SCHEMA_VERSION: u32 = 3;
+
+/// Version of this crate, used when JS does not supply a producer version
+pub(crate) const DEFAULT_PRODUCER_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Last-access times are only rewritten once they are this stale, so hot
+/// entries are not rewritten on every read
+pub(crate) const ACCESS_RESOLUTION_MS: i64 = 60 * 60 * 1000;
+
+/// Metadata stored alongside every cache entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[napi(object)]
+pub struct EntryMetadata {
+    pub created_at: i64,
+    pub last_access: i64,
+    pub size: f64,
+    pub producer_version: String,
+    pub schema_version: u32,
+}
+
+/// A decoded envelope borrowing its payload from the stored bytes
+pub(crate) struct Envelope<'a> {
+    pub schema_version: u32,
+    pub created_at: i64,
+    pub last_access: i64,
+    pub producer_version: &'a str,
+    pub payload: &'a [u8],
+}
+
+impl<'a> Envelope<'a> {
+    /// Decode and verify an envelope. Returns `None` if the bytes are
+    /// truncated, not an envelope, or fail the payload checksum.
+    pub(crate) fn decode(bytes: &'a [u8]) -> Option<Self> {
+        if bytes.len() < FIXED_HEADER_LEN || &bytes[..2] != MAGIC || bytes[2] != FORMAT {
+            return None;
+        }
+
+        let schema_version = u32::from_le_bytes(bytes[3..7].try_into().ok()?);
+        let created_at = i64::from_le_bytes(bytes[7..15].try_into().ok()?);
+        let last_access = i64::from_le_bytes(bytes[15..23].try_into().ok()?);
+        let size = u64::from_le_bytes(bytes[23..31].try_into().ok()?);
+        let checksum = u64::from_le_bytes(bytes[31..39].try_into().ok()?);
+        let producer_len = bytes[39] as usize;
+
+        // The size is untrusted: reject it before it can overflow
+        if size > bytes.len() as u64 {
+            return None;
+        }
+        let producer_end = FIXED_HEADER_LEN + producer_len;
+        if bytes.len() != producer_end.checked_add(size as usize)? {
+            return None;
+        }
+        let producer_version = std::str::from_utf8(&bytes[FIXED_HEADER_LEN..producer_end]).ok()?;
+        let payload = &bytes[producer_end..];
+        if xxh3_64(payload) != checksum {
+            return None;
+        }
+
+        Some(Self {
+            schema_version,
+            created_at,
+            last_access,
+            producer_version,
+            payload,
+        })
+    }
+
+    /// Whether the entry was written under the current schema by `producer_version`
+    pub(crate) fn is_current(&self, producer_version: &str) -> bool {
+        self.schema_version == SCHEMA_VERSION && self.producer_version == producer_version
+    }
+
+    /// Whether two envelopes hold the same value for the same schema and
+    /// producer, whenever they were written or last read
+    pub(crate) fn same_contents(&self, other: &Envelope) -> bool {
+        self.schema_version == other.schema_version
+            && self.producer_version == other.producer_version
+            && self.payload == other.payload
+    }
+
+    pub(crate) fn metadata(&self) -> EntryMetadata {
+        EntryMetadata {
+            created_at: self.created_at,
+            last_access: self.last_access,
+            size: self.payload.len() as f64,
+            producer_version: self.producer_version.to_string(),
+            schema_version: self.schema_version,
+        }
+    }
+}
+
+/// Wrap a payload in a fresh envelope. `producer_version` must fit in 255 bytes.
+pub(crate) fn encode(payload: &[u8], producer_version: &str, now: i64) -> Vec<u8> {
+    let producer = producer_version.as_bytes();
+    let mut bytes = Vec::with_capacity(FIXED_HEADER_LEN + producer.len() + payload.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.push(FORMAT);
+    bytes.extend_from_slice(&SCHEMA_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&now.to_le_bytes());
+    bytes.extend_from_slice(&now.to_le_bytes());
+    bytes.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(&xxh3_64(payload).to_le_bytes());
+    bytes.push(producer.len() as u8);
+    bytes.extend_from_slice(producer);
+    bytes.extend_from_slice(payload);
+    bytes
+}
+
+/// Overwrite the last-access time of an encoded envelope in place
+pub(crate) fn touch(bytes: &mut [u8], now: i64) {
+    bytes[LAST_ACCESS_OFFSET..LAST_ACCESS_OFFSET + 8].copy_from_slice(&now.to_le_bytes());
+}
+
+pub(crate) fn now_ms() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_payload_and_metadata() {
+        let bytes = encode(b"compiled", "nexxo@2.0.0", 1_000);
+        let envelope = Envelope::decode(&bytes).unwrap();
+        assert_eq!(envelope.payload, b"compiled");
+        assert_eq!(envelope.producer_version, "nexxo@2.0.0");
+        assert_eq!((envelope.created_at, envelope.last_access), (1_000, 1_000));
+        assert!(envelope.is_current("nexxo@2.0.0"));
+        assert!(!envelope.is_current("nexxo@1.0.0"));
+    }
+
+    #[test]
+    fn touch_keeps_contents_the_same() {
+        let original = encode(b"value", "p", 1_000);
+        let mut touched = original.clone();
+        touch(&mut touched, 5_000);
+        assert_ne!(original, touched);
+
+        let (a, b) = (Envelope::decode(&original).unwrap(), Envelope::decode(&touched).unwrap());
+        assert_eq!(b.last_access, 5_000);
+        assert!(a.same_contents(&b));
+        assert!(!a.same_contents(&Envelope::decode(&encode(b"value", "q", 1_000)).unwrap()));
+        assert!(!a.same_contents(&Envelope::decode(&encode(b"other", "p", 1_000)).unwrap()));
+    }
+
+    #[test]
+    fn rejects_truncated_and_tampered_bytes() {
+        let bytes = encode(b"payload", "p", 0);
+        assert!(Envelope::decode(&bytes[..bytes.len() - 1]).is_none());
+        assert!(Envelope::decode(&bytes[..FIXED_HEADER_LEN - 1]).is_none());
+
+        let mut tampered = bytes.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(Envelope::decode(&tampered).is_none());
+    }
+
+    #[test]
+    fn rejects_sizes_that_would_overflow() {
+        for size in [u64::MAX, u64::MAX - 40, (usize::MAX - FIXED_HEADER_LEN + 1) as u64] {
+            let mut bytes = encode(b"", "", 0);
+            bytes[23..31].copy_from_slice(&size.to_le_bytes());
+            assert!(Envelope::decode(&bytes).is_none());
+        }
+    }
+}
@@ -0,0 +1,194 @@
+// Corruption detection and recovery for Nexxo v2.0
+//
+// Every entry carries a payload checksum (see `envelope`), so a torn write
+// or bit rot shows up as a failed decode instead of broken build output.
+// This module handles the two coarser failures: a database RocksDB refuses
+// to open, and a column family whose files can no longer be read.
+
+use napi_derive::napi;
+use rocksdb::{ErrorKind, IteratorMode, Options, WriteBatch, DB};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use super::access::OpenError;
+use super::compression::Dictionaries;
+use super::envelope::{self, Envelope};
+use super::{namespace_cf, NAMESPACES};
+
+/// Upper bound for every key, used to drop whole column families by range
+const KEY_SPACE_END: &[u8] = &[0xff; 16];
+
+/// How the cache recovered from a database it could not open
+#[napi(string_enum)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CacheRecovery {
+    /// RocksDB repaired the database in place; some entries may be gone
+    Repaired,
+    /// The database was moved aside and a fresh one created
+    Rebuilt,
+}
+
+/// Result of a full integrity scan
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[napi(object)]
+pub struct VerifyReport {
+    pub scanned: u32,
+    /// Entries that failed their checksum or could not be decoded
+    pub corrupted: u32,
+    /// Corrupted entries deleted so they get rebuilt
+    pub removed: u32,
+    pub corrupted_keys: Vec<String>,
+    /// Namespaces whose files could not be read and were dropped wholesale
+    pub rebuilt_namespaces: Vec<String>,
+}
+
+/// Open the database, repairing it or moving it aside if RocksDB reports corruption
+pub(crate) fn open_or_recover(
+    path: &Path,
+    opts: &Options,
+    open: impl Fn(&Path) -> Result<DB, rocksdb::Error>,
+) -> Result<(DB, Option<CacheRecovery>), OpenError> {
+    match open(path) {
+        Ok(db) => return Ok((db, None)),
+        Err(e) if e.kind() == ErrorKind::Corruption => {}
+        Err(e) => return Err(OpenError::new(e)),
+    }
+
+    if DB::repair(opts, path).is_ok() {
+        if let Ok(db) = open(path) {
+            return Ok((db, Some(CacheRecovery::Repaired)));
+        }
+    }
+
+    // Keep the broken database around for inspection rather than deleting it
+    let mut aside = PathBuf::from(path);
+    aside.set_file_name(format!(
+        "{}.corrupt-{}",
+        path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default(),
+        envelope::now_ms()
+    ));
+    std::fs::rename(path, &aside)
+        .map_err(|e| OpenError::Failed(format!("Failed to move corrupt cache aside: {}", e)))?;
+
+    let db = open(path).map_err(OpenError::new)?;
+    Ok((db, Some(CacheRecovery::Rebuilt)))
+}
+
+/// Scan every entry, deleting those that fail to decode and dropping
+/// namespaces whose files RocksDB can no longer read
+pub(crate) fn verify(db: &DB, dictionaries: &Dictionaries) -> Result<VerifyReport, rocksdb::Error> {
+    let mut report = VerifyReport {
+        scanned: 0,
+        corrupted: 0,
+        removed: 0,
+        corrupted_keys: Vec::new(),
+        rebuilt_namespaces: Vec::new(),
+    };
+
+    for (index, name) in NAMESPACES.iter().enumerate() {
+        let cf = namespace_cf(db, index);
+        let mut batch = WriteBatch::default();
+        let mut unreadable = false;
+
+        for item in db.iterator_cf(cf, IteratorMode::Start) {
+            let (key, value) = match item {
+                Ok(item) => item,
+                Err(_) => {
+                    unreadable = true;
+                    break;
+                }
+            };
+            report.scanned += 1;
+            if Envelope::decode(&dictionaries.decompress(&key, &value)).is_none() {
+                report.corrupted += 1;
+                report.corrupted_keys.push(String::from_utf8_lossy(&key).into_owned());
+                batch.delete_cf(cf, &key);
+            }
+        }
+
+        if unreadable {
+            // Deleting whole files never reads them, unlike deleting keys
+            db.delete_file_in_range_cf(cf, &[][..], KEY_SPACE_END)?;
+            db.delete_range_cf(cf, &[][..], KEY_SPACE_END)?;
+            report.rebuilt_namespaces.push(name.to_string());
+        } else {
+            report.removed += batch.len() as u32;
+            db.write(batch)?;
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::BuildCache;
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn temp_path() -> PathBuf {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "nexxo-integrity-test-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_dir_all(&path);
+        path
+    }
+
+    fn open(path: &Path) -> BuildCache {
+        BuildCache::new(path.to_string_lossy().into_owned(), None).unwrap()
+    }
+
+    #[test]
+    fn verify_removes_exactly_the_corrupt_entry() {
+        let path = temp_path();
+        let cache = open(&path);
+        for key in ["input:a.ts:1", "input:b.ts:1", "graph:g"] {
+            cache.set(key.into(), format!("value of {}", key)).unwrap();
+        }
+
+        // Flip the last payload byte, so the checksum no longer matches
+        let entries = &cache.backend.entries;
+        let mut bytes = entries.get(b"input:b.ts:1").unwrap().unwrap();
+        *bytes.last_mut().unwrap() ^= 0x01;
+        entries.put(b"input:b.ts:1", &bytes).unwrap();
+
+        let report = cache.verify().unwrap();
+        assert_eq!((report.scanned, report.corrupted, report.removed), (3, 1, 1));
+        assert_eq!(report.corrupted_keys, ["input:b.ts:1"]);
+        assert!(report.rebuilt_namespaces.is_empty());
+        assert_eq!(entries.get(b"input:b.ts:1").unwrap(), None);
+        assert_eq!(cache.get("input:a.ts:1".into()).as_deref(), Some("value of input:a.ts:1"));
+        assert_eq!(cache.get("graph:g".into()).as_deref(), Some("value of graph:g"));
+
+        assert_eq!(cache.verify().unwrap().corrupted, 0);
+        drop(cache);
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn recovers_a_database_rocksdb_refuses_to_open() {
+        let path = temp_path();
+        open(&path).set("graph:g".into(), "kept".into()).unwrap();
+        assert_eq!(open(&path).get_recovery(), None);
+
+        // A CURRENT file without its trailing newline is reported as corruption
+        std::fs::write(path.join("CURRENT"), "MANIFEST-garbage").unwrap();
+        let cache = open(&path);
+        assert!(cache.get_recovery().is_some());
+        cache.set("graph:after".into(), "works".into()).unwrap();
+        assert_eq!(cache.get("graph:after".into()).as_deref(), Some("works"));
+
+        drop(cache);
+        let parent = path.parent().unwrap();
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        for entry in std::fs::read_dir(parent).unwrap().flatten() {
+            if entry.file_name().to_string_lossy().starts_with(&format!("{}.corrupt-", name)) {
+                std::fs::remove_dir_all(entry.path()).unwrap();
+            }
+        }
+        std::fs::remove_dir_all(path).unwrap();
+    }
+}
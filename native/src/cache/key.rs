@@ -0,0 +1,365 @@
+// Structured cache keys for Nexxo v2.0
+//
+// Keys built here keep the `namespace:` prefix (and `target:` for plan and
+// artifact keys) that column family routing and per-target stats rely on,
+// then append the caller's components and a context section:
+//
+//   {namespace}:[{target}:]{component}:{component}...#{nexxo version}:{config}:{env}
+//
+// Every component is percent-escaped, so paths containing `:` or `#`
+// (Windows drive letters, `\0virtual:x` ids) cannot run into each other and
+// every key decodes back to exactly the components it was built from.
+
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+use serde::{Deserialize, Serialize};
+use xxhash_rust::xxh3::xxh3_64;
+
+use super::envelope::DEFAULT_PRODUCER_VERSION;
+
+/// Namespaces whose keys carry a build target as their first component
+const TARGETED_NAMESPACES: [&str; 2] = ["plan", "artifact"];
+
+/// Inputs folded into every key a builder produces
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[napi(object)]
+pub struct CacheKeyOptions {
+    /// Fingerprint of the resolved config, see `fingerprintConfig`
+    pub config_fingerprint: Option<String>,
+    /// Environment variables whose values should invalidate keys, read once when the builder is created
+    pub env: Option<Vec<String>>,
+    /// Nexxo version (default: the native crate version)
+    pub nexxo_version: Option<String>,
+}
+
+/// A key split back into its parts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[napi(object)]
+pub struct DecodedCacheKey {
+    pub namespace: String,
+    pub target: Option<String>,
+    pub components: Vec<String>,
+    /// Context section; absent for keys not made by `CacheKeyBuilder`
+    pub nexxo_version: Option<String>,
+    pub config_fingerprint: Option<String>,
+    pub env_fingerprint: Option<String>,
+}
+
+/// Builds unambiguous cache keys that fold in config, environment and Nexxo version
+#[napi]
+pub struct CacheKeyBuilder {
+    nexxo_version: String,
+    config_fingerprint: String,
+    env_fingerprint: String,
+}
+
+#[napi]
+impl CacheKeyBuilder {
+    #[napi(constructor)]
+    pub fn new(options: Option<CacheKeyOptions>) -> Self {
+        let options = options.unwrap_or(CacheKeyOptions {
+            config_fingerprint: None,
+            env: None,
+            nexxo_version: None,
+        });
+
+        let mut env: Vec<(String, Option<String>)> = options
+            .env
+            .unwrap_or_default()
+            .into_iter()
+            .map(|name| {
+                let value = std::env::var(&name).ok();
+                (name, value)
+            })
+            .collect();
+        env.sort();
+        env.dedup();
+
+        Self {
+            nexxo_version: options
+                .nexxo_version
+                .unwrap_or_else(|| DEFAULT_PRODUCER_VERSION.to_string()),
+            config_fingerprint: options.config_fingerprint.unwrap_or_default(),
+            env_fingerprint: fingerprint_env(&env),
+        }
+    }
+
+    /// Key for a source file's transformed output
+    #[napi]
+    pub fn input(&self, file_path: String, content_hash: String) -> String {
+        self.build("input", None, &[&file_path, &content_hash])
+    }
+
+    /// Key for a module graph
+    #[napi]
+    pub fn graph(&self, graph_hash: String) -> String {
+        self.build("graph", None, &[&graph_hash])
+    }
+
+    /// Key for a build plan
+    #[napi]
+    pub fn plan(&self, plan_hash: String, target: String) -> String {
+        self.build("plan", Some(&target), &[&plan_hash])
+    }
+
+    /// Key for an emitted artifact
+    #[napi]
+    pub fn artifact(&self, artifact_id: String, target: String) -> String {
+        self.build("artifact", Some(&target), &[&artifact_id])
+    }
+
+    /// Key in any other namespace. `plan` and `artifact` keys need a target.
+    #[napi]
+    pub fn key(&self, namespace: String, components: Vec<String>, target: Option<String>) -> Result<String> {
+        if namespace.is_empty() || escape(&namespace) != namespace {
+            return Err(Error::from_reason(format!("Invalid cache key namespace: {:?}", namespace)));
+        }
+        if TARGETED_NAMESPACES.contains(&namespace.as_str()) != target.is_some() {
+            return Err(Error::from_reason(format!(
+                "Keys in the {} namespace {} a target",
+                namespace,
+                if target.is_some() { "do not take" } else { "need" }
+            )));
+        }
+        let components: Vec<&str> = components.iter().map(String::as_str).collect();
+        Ok(self.build(&namespace, target.as_deref(), &components))
+    }
+
+    /// Fingerprint of the selected environment values
+    #[napi(getter)]
+    pub fn get_env_fingerprint(&self) -> String {
+        self.env_fingerprint.clone()
+    }
+
+    fn build(&self, namespace: &str, target: Option<&str>, components: &[&str]) -> String {
+        let mut key = String::from(namespace);
+        for part in target.into_iter().chain(components.iter().copied()) {
+            key.push(':');
+            key.push_str(&escape(part));
+        }
+        key.push('#');
+        key.push_str(&escape(&self.nexxo_version));
+        key.push(':');
+        key.push_str(&escape(&self.config_fingerprint));
+        key.push(':');
+        key.push_str(&escape(&self.env_fingerprint));
+        key
+    }
+}
+
+/// Split a cache key into its namespace, target, components and context
+#[napi]
+pub fn decode_cache_key(key: String) -> Result<DecodedCacheKey> {
+    let invalid = || Error::from_reason(format!("Malformed cache key: {:?}", key));
+
+    let (body, context) = match key.split_once('#') {
+        Some((body, context)) => (body, Some(context)),
+        None => (key.as_str(), None),
+    };
+
+    let mut parts = body.split(':').map(unescape);
+    let namespace = parts.next().ok_or_else(invalid)?.ok_or_else(invalid)?;
+    let mut components = parts.collect::<Option<Vec<String>>>().ok_or_else(invalid)?;
+    let target = if TARGETED_NAMESPACES.contains(&namespace.as_str()) && !components.is_empty() {
+        Some(components.remove(0))
+    } else {
+        None
+    };
+
+    let (nexxo_version, config_fingerprint, env_fingerprint) = match context {
+        Some(context) => {
+            let context = context
+                .split(':')
+                .map(unescape)
+                .collect::<Option<Vec<String>>>()
+                .ok_or_else(invalid)?;
+            match <[String; 3]>::try_from(context) {
+                Ok([version, config, env]) => (Some(version), Some(config), Some(env)),
+                Err(_) => return Err(invalid()),
+            }
+        }
+        None => (None, None, None),
+    };
+
+    Ok(DecodedCacheKey {
+        namespace,
+        target,
+        components,
+        nexxo_version,
+        config_fingerprint,
+        env_fingerprint,
+    })
+}
+
+/// Stable fingerprint of a JSON config: object keys are sorted before hashing,
+/// so formatting and key order do not change it
+#[napi]
+pub fn fingerprint_config(config_json: String) -> Result<String> {
+    let config: serde_json::Value = serde_json::from_str(&config_json)
+        .map_err(|e| Error::from_reason(format!("Invalid config JSON: {}", e)))?;
+    let mut canonical = String::new();
+    write_canonical(&config, &mut canonical);
+    Ok(format!("{:016x}", xxh3_64(canonical.as_bytes())))
+}
+
+/// Serialize JSON with object keys sorted, whatever map order serde_json was built with
+fn write_canonical(value: &serde_json::Value, out: &mut String) {
+    match value {
+        serde_json::Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            out.push('{');
+            for (i, (key, value)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&serde_json::Value::String(key.clone()).to_string());
+                out.push(':');
+                write_canonical(value, out);
+            }
+            out.push('}');
+        }
+        serde_json::Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        scalar => out.push_str(&scalar.to_string()),
+    }
+}
+
+fn fingerprint_env(env: &[(String, Option<String>)]) -> String {
+    if env.is_empty() {
+        return String::new();
+    }
+    let mut text = String::new();
+    for (name, value) in env {
+        text.push_str(name);
+        match value {
+            Some(value) => {
+                text.push('=');
+                text.push_str(value);
+            }
+            // Distinguish unset from set-but-empty
+            None => text.push('!'),
+        }
+        text.push('\0');
+    }
+    format!("{:016x}", xxh3_64(text.as_bytes()))
+}
+
+/// Percent-escape the separators, `%` itself and control characters
+fn escape(part: &str) -> String {
+    let mut escaped = String::with_capacity(part.len());
+    for c in part.chars() {
+        match c {
+            '%' | ':' | '#' => escaped.push_str(&format!("%{:02X}", c as u32)),
+            c if c.is_ascii_control() => escaped.push_str(&format!("%{:02X}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub(crate) fn unescape(part: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(part.len());
+    let mut input = part.bytes();
+    while let Some(b) = input.next() {
+        if b == b'%' {
+            let hex = [input.next()?, input.next()?];
+            // `from_str_radix` alone would also take a sign, as in `%+f`
+            if !hex.iter().all(u8::is_ascii_hexdigit) {
+                return None;
+            }
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(b);
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn builder() -> CacheKeyBuilder {
+        CacheKeyBuilder::new(Some(CacheKeyOptions {
+            config_fingerprint: Some("cfg:1#a".to_string()),
+            env: None,
+            nexxo_version: Some("2.0.0".to_string()),
+        }))
+    }
+
+    #[test]
+    fn escaping_round_trips_separators_escapes_and_controls() {
+        for part in ["", "plain", "C:\\src\\a.ts", "\0virtual:x#y", "100%", "%3A", "line\nbreak\t", "é:ü"] {
+            let escaped = escape(part);
+            assert!(!escaped.contains([':', '#']), "{:?} escaped to {:?}", part, escaped);
+            assert_eq!(unescape(&escaped).as_deref(), Some(part));
+        }
+    }
+
+    #[test]
+    fn decodes_exactly_the_components_a_key_was_built_from() {
+        let components = vec!["".to_string(), "a:b".to_string(), "#".to_string(), "".to_string(), "%".to_string()];
+        let key = builder().key("custom".to_string(), components.clone(), None).unwrap();
+        let decoded = decode_cache_key(key).unwrap();
+        assert_eq!(decoded.namespace, "custom");
+        assert_eq!(decoded.target, None);
+        assert_eq!(decoded.components, components);
+        assert_eq!(decoded.nexxo_version.as_deref(), Some("2.0.0"));
+        assert_eq!(decoded.config_fingerprint.as_deref(), Some("cfg:1#a"));
+        assert_eq!(decoded.env_fingerprint.as_deref(), Some(""));
+    }
+
+    #[test]
+    fn keeps_namespace_and_target_prefixes_for_routing() {
+        let key = builder().plan("h:1".to_string(), "prod".to_string());
+        assert!(key.starts_with("plan:prod:h%3A1#"));
+        let decoded = decode_cache_key(key).unwrap();
+        assert_eq!(decoded.target.as_deref(), Some("prod"));
+        assert_eq!(decoded.components, ["h:1"]);
+
+        // Distinct components never produce the same key
+        let a = builder().input("a:b".to_string(), "c".to_string());
+        let b = builder().input("a".to_string(), "b:c".to_string());
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn decodes_legacy_keys_without_a_context_section() {
+        let decoded = decode_cache_key("artifact:dev:chunk-1".to_string()).unwrap();
+        assert_eq!(decoded.target.as_deref(), Some("dev"));
+        assert_eq!(decoded.components, ["chunk-1"]);
+        assert_eq!(decoded.nexxo_version, None);
+    }
+
+    #[test]
+    fn rejects_bad_escapes_and_non_utf8() {
+        assert_eq!(unescape("%FF"), None);
+        assert_eq!(unescape("%C3"), None);
+        assert_eq!(unescape("%4"), None);
+        assert_eq!(unescape("%G1"), None);
+        assert_eq!(unescape("%+f"), None);
+        assert_eq!(unescape("%-1"), None);
+        assert_eq!(unescape("%C3%A9").as_deref(), Some("é"));
+
+        assert!(decode_cache_key("input:%FF:abc".to_string()).is_err());
+        assert!(decode_cache_key("input:a%+f:abc".to_string()).is_err());
+        assert!(decode_cache_key("input:a#only:two".to_string()).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_namespaces_and_targets() {
+        assert!(builder().key("".to_string(), vec![], None).is_err());
+        assert!(builder().key("a:b".to_string(), vec![], None).is_err());
+        assert!(builder().key("plan".to_string(), vec![], None).is_err());
+        assert!(builder().key("graph".to_string(), vec![], Some("dev".to_string())).is_err());
+    }
+}
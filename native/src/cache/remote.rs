@@ -0,0 +1,557 @@
+// Remote HTTP cache tier for Nexxo v2.0
+//
+// This module lets the local RocksDB cache read through and write behind to
+// an HTTP cache server speaking one of two protocols:
+// - Bazel remote cache: `/cas/{sha256(value)}` holds the value itself and
+//   `/ac/{sha256(key)}` an `ActionResult` whose one output file points at it,
+//   so servers that validate action cache entries (bazel-remote does by
+//   default) accept it
+// - Turborepo remote cache: `/v8/artifacts/{sha256(key)}`
+//
+// Uploads are queued and drained by a fixed pool of worker threads so that
+// `set` never waits on the network. Downloads are only made by the async
+// lookups, on the blocking pool, and are bounded by a separate concurrency
+// limit; the synchronous ones never leave the machine.
+
+use napi_derive::napi;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+const DEFAULT_UPLOAD_CONCURRENCY: u32 = 4;
+const DEFAULT_DOWNLOAD_CONCURRENCY: u32 = 8;
+const DEFAULT_TIMEOUT_MS: u32 = 10_000;
+const UPLOAD_QUEUE_CAPACITY: usize = 1024;
+const MAX_DOWNLOAD_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Path of the single output file in the `ActionResult`s we write
+const OUTPUT_PATH: &str = "nexxo-cache-entry";
+
+/// Wire protocol spoken by the remote cache server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[napi(string_enum)]
+pub enum RemoteCacheProtocol {
+    Bazel,
+    Turborepo,
+}
+
+/// Remote cache configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[napi(object)]
+pub struct RemoteCacheOptions {
+    /// Base URL of the cache server, e.g. `http://cache.internal:8080`
+    pub url: String,
+    /// Protocol spoken by the server (default: Bazel)
+    pub protocol: Option<RemoteCacheProtocol>,
+    /// Bearer token sent as the `Authorization` header
+    pub token: Option<String>,
+    /// Extra headers sent with every request
+    pub headers: Option<HashMap<String, String>>,
+    /// Turborepo team id (`teamId` query parameter)
+    pub team_id: Option<String>,
+    /// Turborepo team slug (`slug` query parameter)
+    pub team_slug: Option<String>,
+    /// Maximum number of uploads in flight (default: 4)
+    pub max_concurrent_uploads: Option<u32>,
+    /// Maximum number of downloads in flight (default: 8)
+    pub max_concurrent_downloads: Option<u32>,
+    /// Per-request timeout in milliseconds (default: 10000)
+    pub timeout_ms: Option<u32>,
+    /// Only read from the remote cache, never upload
+    pub read_only: Option<bool>,
+}
+
+/// Remote cache statistics
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[napi(object)]
+pub struct RemoteCacheStats {
+    pub hits: i64,
+    pub misses: i64,
+    pub uploads: i64,
+    pub upload_failures: i64,
+    pub download_failures: i64,
+    pub dropped_uploads: i64,
+    pub pending_uploads: i64,
+}
+
+struct UploadJob {
+    key: String,
+    value: Vec<u8>,
+}
+
+/// Counting semaphore used to cap concurrent downloads
+struct Semaphore {
+    permits: Mutex<u32>,
+    available: Condvar,
+}
+
+impl Semaphore {
+    fn new(permits: u32) -> Self {
+        Self {
+            permits: Mutex::new(permits.max(1)),
+            available: Condvar::new(),
+        }
+    }
+
+    fn acquire(&self) -> SemaphorePermit<'_> {
+        let mut permits = self.permits.lock().unwrap();
+        while *permits == 0 {
+            permits = self.available.wait(permits).unwrap();
+        }
+        *permits -= 1;
+        SemaphorePermit { semaphore: self }
+    }
+}
+
+struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        *self.semaphore.permits.lock().unwrap() += 1;
+        self.semaphore.available.notify_one();
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    uploads: AtomicU64,
+    upload_failures: AtomicU64,
+    download_failures: AtomicU64,
+    dropped_uploads: AtomicU64,
+}
+
+/// HTTP client shared by the download path and the upload workers
+struct RemoteClient {
+    agent: ureq::Agent,
+    base_url: String,
+    protocol: RemoteCacheProtocol,
+    headers: Vec<(String, String)>,
+    query: String,
+}
+
+impl RemoteClient {
+    fn new(options: &RemoteCacheOptions) -> Self {
+        let timeout = Duration::from_millis(options.timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS) as u64);
+        let agent = ureq::AgentBuilder::new().timeout(timeout).build();
+
+        let mut headers: Vec<(String, String)> = options
+            .headers
+            .clone()
+            .unwrap_or_default()
+            .into_iter()
+            .collect();
+        if let Some(token) = &options.token {
+            headers.push(("Authorization".to_string(), format!("Bearer {}", token)));
+        }
+
+        let mut params = Vec::new();
+        if let Some(team_id) = &options.team_id {
+            params.push(format!("teamId={}", percent_encode(team_id)));
+        }
+        if let Some(slug) = &options.team_slug {
+            params.push(format!("slug={}", percent_encode(slug)));
+        }
+        let query = if params.is_empty() {
+            String::new()
+        } else {
+            format!("?{}", params.join("&"))
+        };
+
+        Self {
+            agent,
+            base_url: options.url.trim_end_matches('/').to_string(),
+            protocol: options.protocol.unwrap_or(RemoteCacheProtocol::Bazel),
+            headers,
+            query,
+        }
+    }
+
+    fn request(&self, method: &str, url: &str) -> ureq::Request {
+        let mut request = self.agent.request(method, url);
+        for (name, value) in &self.headers {
+            request = request.set(name, value);
+        }
+        request
+    }
+
+    /// GET a blob, mapping 404 to `Ok(None)`. Bodies over `MAX_DOWNLOAD_BYTES`
+    /// are an error rather than cut short, since a truncated artifact would
+    /// otherwise be cached as a hit.
+    fn get_blob(&self, url: &str) -> std::result::Result<Option<Vec<u8>>, String> {
+        match self.request("GET", url).call() {
+            Ok(response) => {
+                let mut body = Vec::new();
+                response
+                    .into_reader()
+                    .take(MAX_DOWNLOAD_BYTES + 1)
+                    .read_to_end(&mut body)
+                    .map_err(|e| format!("Failed to read {}: {}", url, e))?;
+                if body.len() as u64 > MAX_DOWNLOAD_BYTES {
+                    return Err(format!("{} is larger than {} bytes", url, MAX_DOWNLOAD_BYTES));
+                }
+                Ok(Some(body))
+            }
+            Err(ureq::Error::Status(404, _)) => Ok(None),
+            Err(e) => Err(format!("GET {} failed: {}", url, e)),
+        }
+    }
+
+    fn put_blob(&self, url: &str, body: &[u8]) -> std::result::Result<(), String> {
+        self.request("PUT", url)
+            .set("Content-Type", "application/octet-stream")
+            .send_bytes(body)
+            .map(|_| ())
+            .map_err(|e| format!("PUT {} failed: {}", url, e))
+    }
+
+    fn fetch(&self, key: &str) -> std::result::Result<Option<Vec<u8>>, String> {
+        let key_digest = sha256_hex(key.as_bytes());
+        match self.protocol {
+            RemoteCacheProtocol::Bazel => {
+                let ac_url = format!("{}/ac/{}", self.base_url, key_digest);
+                let value_digest = match self.get_blob(&ac_url)? {
+                    Some(body) => action_result::decode(&body)
+                        .ok_or_else(|| format!("{} is not an ActionResult with an output file", ac_url))?,
+                    None => return Ok(None),
+                };
+                let cas_url = format!("{}/cas/{}", self.base_url, value_digest);
+                match self.get_blob(&cas_url)? {
+                    // Never trust a blob whose content does not match its address
+                    Some(value) if sha256_hex(&value) == value_digest => Ok(Some(value)),
+                    Some(_) => Err(format!("Digest mismatch for {}", cas_url)),
+                    None => Ok(None),
+                }
+            }
+            RemoteCacheProtocol::Turborepo => {
+                let url = format!("{}/v8/artifacts/{}{}", self.base_url, key_digest, self.query);
+                self.get_blob(&url)
+            }
+        }
+    }
+
+    fn store(&self, key: &str, value: &[u8]) -> std::result::Result<(), String> {
+        let key_digest = sha256_hex(key.as_bytes());
+        match self.protocol {
+            RemoteCacheProtocol::Bazel => {
+                let value_digest = sha256_hex(value);
+                // CAS first so the AC entry never points at a missing blob
+                self.put_blob(&format!("{}/cas/{}", self.base_url, value_digest), value)?;
+                self.put_blob(
+                    &format!("{}/ac/{}", self.base_url, key_digest),
+                    &action_result::encode(&value_digest, value.len() as u64),
+                )
+            }
+            RemoteCacheProtocol::Turborepo => {
+                let url = format!("{}/v8/artifacts/{}{}", self.base_url, key_digest, self.query);
+                self.put_blob(&url, value)
+            }
+        }
+    }
+}
+
+/// Remote tier attached to a `BuildCache`
+pub(crate) struct RemoteCache {
+    client: Arc<RemoteClient>,
+    downloads: Semaphore,
+    uploads: Option<SyncSender<UploadJob>>,
+    pending: Arc<(Mutex<u64>, Condvar)>,
+    counters: Arc<Counters>,
+}
+
+impl RemoteCache {
+    pub(crate) fn new(options: &RemoteCacheOptions) -> std::result::Result<Self, String> {
+        if !options.url.starts_with("http://") && !options.url.starts_with("https://") {
+            return Err(format!("Remote cache URL must be http(s): {}", options.url));
+        }
+
+        let client = Arc::new(RemoteClient::new(options));
+        let counters = Arc::new(Counters::default());
+        let pending = Arc::new((Mutex::new(0u64), Condvar::new()));
+
+        let uploads = if options.read_only.unwrap_or(false) {
+            None
+        } else {
+            let (sender, receiver) = mpsc::sync_channel::<UploadJob>(UPLOAD_QUEUE_CAPACITY);
+            let receiver = Arc::new(Mutex::new(receiver));
+            let worker_count = options
+                .max_concurrent_uploads
+                .unwrap_or(DEFAULT_UPLOAD_CONCURRENCY)
+                .max(1);
+            for i in 0..worker_count {
+                let client = client.clone();
+                let receiver = receiver.clone();
+                let pending = pending.clone();
+                let counters = counters.clone();
+                std::thread::Builder::new()
+                    .name(format!("nexxo-cache-upload-{}", i))
+                    .spawn(move || upload_worker(client, receiver, pending, counters))
+                    .map_err(|e| format!("Failed to spawn upload worker: {}", e))?;
+            }
+            Some(sender)
+        };
+
+        Ok(Self {
+            client,
+            downloads: Semaphore::new(
+                options
+                    .max_concurrent_downloads
+                    .unwrap_or(DEFAULT_DOWNLOAD_CONCURRENCY),
+            ),
+            uploads,
+            pending,
+            counters,
+        })
+    }
+
+    /// Look up a key remotely; network errors are counted and reported as a miss
+    pub(crate) fn fetch(&self, key: &str) -> Option<Vec<u8>> {
+        let _permit = self.downloads.acquire();
+        match self.client.fetch(key) {
+            Ok(Some(value)) => {
+                self.counters.hits.fetch_add(1, Ordering::Relaxed);
+                Some(value)
+            }
+            Ok(None) => {
+                self.counters.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+            Err(_) => {
+                self.counters.download_failures.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    /// Queue a write-behind upload; drops the upload if the queue is full
+    pub(crate) fn enqueue(&self, key: &str, value: &[u8]) {
+        let Some(sender) = &self.uploads else {
+            return;
+        };
+
+        let (lock, _) = &*self.pending;
+        *lock.lock().unwrap() += 1;
+
+        let job = UploadJob {
+            key: key.to_string(),
+            value: value.to_vec(),
+        };
+        if sender.try_send(job).is_err() {
+            self.counters.dropped_uploads.fetch_add(1, Ordering::Relaxed);
+            finish_upload(&self.pending);
+        }
+    }
+
+    /// Block until every queued upload has finished or the timeout elapses.
+    /// Returns true if the queue drained.
+    pub(crate) fn flush(&self, timeout: Duration) -> bool {
+        let (lock, drained) = &*self.pending;
+        let pending = lock.lock().unwrap();
+        let (pending, _) = drained
+            .wait_timeout_while(pending, timeout, |pending| *pending > 0)
+            .unwrap();
+        *pending == 0
+    }
+
+    pub(crate) fn stats(&self) -> RemoteCacheStats {
+        let pending = *self.pending.0.lock().unwrap();
+        RemoteCacheStats {
+            hits: self.counters.hits.load(Ordering::Relaxed) as i64,
+            misses: self.counters.misses.load(Ordering::Relaxed) as i64,
+            uploads: self.counters.uploads.load(Ordering::Relaxed) as i64,
+            upload_failures: self.counters.upload_failures.load(Ordering::Relaxed) as i64,
+            download_failures: self.counters.download_failures.load(Ordering::Relaxed) as i64,
+            dropped_uploads: self.counters.dropped_uploads.load(Ordering::Relaxed) as i64,
+            pending_uploads: pending as i64,
+        }
+    }
+}
+
+impl Drop for RemoteCache {
+    fn drop(&mut self) {
+        // Closing the channel lets the workers drain what is queued and exit.
+        // They are not joined: this may run in a JS finalizer, which must not
+        // wait on the network. Use `flushRemote` to wait for uploads.
+        self.uploads.take();
+    }
+}
+
+fn upload_worker(
+    client: Arc<RemoteClient>,
+    receiver: Arc<Mutex<Receiver<UploadJob>>>,
+    pending: Arc<(Mutex<u64>, Condvar)>,
+    counters: Arc<Counters>,
+) {
+    loop {
+        let job = match receiver.lock().unwrap().recv() {
+            Ok(job) => job,
+            Err(_) => return,
+        };
+        match client.store(&job.key, &job.value) {
+            Ok(()) => counters.uploads.fetch_add(1, Ordering::Relaxed),
+            Err(_) => counters.upload_failures.fetch_add(1, Ordering::Relaxed),
+        };
+        finish_upload(&pending);
+    }
+}
+
+fn finish_upload(pending: &(Mutex<u64>, Condvar)) {
+    let (lock, drained) = pending;
+    let mut count = lock.lock().unwrap();
+    *count = count.saturating_sub(1);
+    if *count == 0 {
+        drained.notify_all();
+    }
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+/// Percent-encode a query parameter value
+fn percent_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// The few `build.bazel.remote.execution.v2` messages the action cache needs,
+/// in protobuf wire format:
+///
+///   ActionResult { repeated OutputFile output_files = 2; }
+///   OutputFile   { string path = 1; Digest digest = 2; }
+///   Digest       { string hash = 1; int64 size_bytes = 2; }
+mod action_result {
+    use super::OUTPUT_PATH;
+
+    const LENGTH_DELIMITED: u8 = 2;
+    const VARINT: u8 = 0;
+
+    fn put_varint(out: &mut Vec<u8>, mut value: u64) {
+        while value >= 0x80 {
+            out.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+    }
+
+    fn put_bytes(out: &mut Vec<u8>, field: u8, bytes: &[u8]) {
+        out.push(field << 3 | LENGTH_DELIMITED);
+        put_varint(out, bytes.len() as u64);
+        out.extend_from_slice(bytes);
+    }
+
+    /// An `ActionResult` with one output file holding the blob `hash`
+    pub(super) fn encode(hash: &str, size: u64) -> Vec<u8> {
+        let mut digest = Vec::new();
+        put_bytes(&mut digest, 1, hash.as_bytes());
+        digest.push(2 << 3 | VARINT);
+        put_varint(&mut digest, size);
+
+        let mut output_file = Vec::new();
+        put_bytes(&mut output_file, 1, OUTPUT_PATH.as_bytes());
+        put_bytes(&mut output_file, 2, &digest);
+
+        let mut action_result = Vec::new();
+        put_bytes(&mut action_result, 2, &output_file);
+        action_result
+    }
+
+    fn varint(bytes: &mut &[u8]) -> Option<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let (&byte, rest) = bytes.split_first()?;
+            *bytes = rest;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    /// The length-delimited fields numbered `field` in `message`, skipping
+    /// any other field
+    fn fields(mut message: &[u8], field: u64) -> Option<Vec<&[u8]>> {
+        let mut found = Vec::new();
+        while !message.is_empty() {
+            let tag = varint(&mut message)?;
+            match (tag & 7) as u8 {
+                VARINT => {
+                    varint(&mut message)?;
+                }
+                LENGTH_DELIMITED => {
+                    let len = usize::try_from(varint(&mut message)?).ok()?;
+                    let value = message.get(..len)?;
+                    message = &message[len..];
+                    if tag >> 3 == field {
+                        found.push(value);
+                    }
+                }
+                // 64- and 32-bit fixed-width fields
+                1 => message = message.get(8..)?,
+                5 => message = message.get(4..)?,
+                _ => return None,
+            }
+        }
+        Some(found)
+    }
+
+    /// Hash of the blob of the first output file of an `ActionResult`
+    pub(super) fn decode(bytes: &[u8]) -> Option<String> {
+        let output_file = *fields(bytes, 2)?.first()?;
+        let digest = *fields(output_file, 2)?.first()?;
+        let hash = *fields(digest, 1)?.first()?;
+        String::from_utf8(hash.to_vec()).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn action_result_round_trips() {
+        let hash = sha256_hex(b"compiled-app");
+        let encoded = action_result::encode(&hash, 12);
+        assert_eq!(action_result::decode(&encoded).as_deref(), Some(hash.as_str()));
+    }
+
+    #[test]
+    fn action_result_skips_unknown_fields() {
+        let hash = sha256_hex(b"value");
+        // exit_code = 4 (varint), then the output files
+        let mut encoded = vec![4 << 3, 0];
+        encoded.extend(action_result::encode(&hash, 5));
+        assert_eq!(action_result::decode(&encoded).as_deref(), Some(hash.as_str()));
+    }
+
+    #[test]
+    fn action_result_rejects_raw_digests_and_truncation() {
+        let hash = sha256_hex(b"value");
+        assert_eq!(action_result::decode(hash.as_bytes()), None);
+        let encoded = action_result::encode(&hash, 5);
+        assert_eq!(action_result::decode(&encoded[..encoded.len() - 3]), None);
+        assert_eq!(action_result::decode(&[]), None);
+    }
+
+    #[test]
+    fn percent_encodes_query_values() {
+        assert_eq!(percent_encode("team_123"), "team_123");
+        assert_eq!(percent_encode("a&b=c d"), "a%26b%3Dc%20d");
+        assert_eq!(percent_encode("é"), "%C3%A9");
+    }
+}
@@ -0,0 +1,201 @@
+// Point-in-time cache views for Nexxo v2.0
+//
+// A `CacheSnapshot` wraps a RocksDB snapshot so a build can plan against one
+// consistent state of the cache while other rebuilds keep writing. Reads
+// through a snapshot never go to the remote tier and never refresh access
+// times, since both would write to the live cache.
+
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+use rocksdb::{Snapshot, DB};
+use std::sync::{Arc, Mutex};
+
+use super::compression::Dictionaries;
+use super::envelope::Envelope;
+use super::stats::CacheCounters;
+use super::storage::Backend;
+use super::{namespace_cf, namespace_index, target_of};
+
+/// A RocksDB snapshot that keeps its database alive
+struct OwnedSnapshot {
+    // Declared before `db` so it is released first
+    snapshot: Snapshot<'static>,
+    db: Arc<DB>,
+    /// The cache's storage, which `db` belongs to. Held so a restore waits
+    /// for the snapshot to be released.
+    _backend: Arc<Backend>,
+}
+
+impl OwnedSnapshot {
+    fn new(db: Arc<DB>, backend: Arc<Backend>) -> Self {
+        let snapshot = db.snapshot();
+        // SAFETY: the snapshot borrows the DB behind the `Arc`, which lives at a
+        // stable address and is kept alive by `db` until after the snapshot drops
+        let snapshot = unsafe { std::mem::transmute::<Snapshot<'_>, Snapshot<'static>>(snapshot) };
+        Self { snapshot, db, _backend: backend }
+    }
+}
+
+/// A frozen, read-only view of a `BuildCache`
+#[napi]
+pub struct CacheSnapshot {
+    /// `None` once released
+    inner: Mutex<Option<OwnedSnapshot>>,
+    dictionaries: Arc<Dictionaries>,
+    counters: Arc<CacheCounters>,
+    producer_version: String,
+    created_at: i64,
+}
+
+impl CacheSnapshot {
+    pub(crate) fn new(
+        db: Arc<DB>,
+        backend: Arc<Backend>,
+        counters: Arc<CacheCounters>,
+        producer_version: String,
+    ) -> Self {
+        Self {
+            dictionaries: backend.dictionaries.clone(),
+            inner: Mutex::new(Some(OwnedSnapshot::new(db, backend))),
+            counters,
+            producer_version,
+            created_at: super::envelope::now_ms(),
+        }
+    }
+
+    /// Unwrap a stored entry and count the lookup
+    fn resolve(&self, key: &str, bytes: Option<Vec<u8>>) -> Option<String> {
+        let value = bytes.and_then(|bytes| {
+            let bytes = self.dictionaries.decompress(key.as_bytes(), &bytes);
+            let entry = Envelope::decode(&bytes);
+            if entry.is_none() {
+                self.counters.corrupt();
+            }
+            entry
+                .filter(|entry| entry.is_current(&self.producer_version))
+                .and_then(|entry| String::from_utf8(entry.payload.to_vec()).ok())
+        });
+
+        let namespace = namespace_index(key.as_bytes());
+        match &value {
+            Some(value) => self.counters.hit(namespace, target_of(key), value.len()),
+            None => self.counters.miss(namespace, target_of(key)),
+        }
+        value
+    }
+}
+
+#[napi]
+impl CacheSnapshot {
+    /// Get a value as it was when the snapshot was taken
+    #[napi]
+    pub fn get(&self, key: String) -> Result<Option<String>> {
+        let inner = self.inner.lock().unwrap();
+        let inner = inner.as_ref().ok_or_else(|| Error::from_reason("Cache snapshot has been released"))?;
+
+        let cf = namespace_cf(&inner.db, namespace_index(key.as_bytes()));
+        let bytes = inner
+            .snapshot
+            .get_cf(cf, key.as_bytes())
+            .map_err(|e| Error::from_reason(format!("Failed to read cache snapshot: {}", e)))?;
+        Ok(self.resolve(&key, bytes))
+    }
+
+    /// Get many values as they were when the snapshot was taken, in the order of `keys`
+    #[napi]
+    pub fn multi_get(&self, keys: Vec<String>) -> Result<Vec<Option<String>>> {
+        let inner = self.inner.lock().unwrap();
+        let inner = inner.as_ref().ok_or_else(|| Error::from_reason("Cache snapshot has been released"))?;
+
+        let lookups = keys
+            .iter()
+            .map(|key| (namespace_cf(&inner.db, namespace_index(key.as_bytes())), key.as_bytes()));
+        let results = inner.snapshot.multi_get_cf(lookups);
+
+        Ok(keys
+            .iter()
+            .zip(results)
+            .map(|(key, result)| self.resolve(key, result.ok().flatten()))
+            .collect())
+    }
+
+    /// When the snapshot was taken (ms since epoch)
+    #[napi(getter)]
+    pub fn get_created_at(&self) -> i64 {
+        self.created_at
+    }
+
+    /// Release the snapshot so RocksDB can drop the data it pins.
+    /// Later reads fail; releasing twice is a no-op.
+    #[napi]
+    pub fn release(&self) {
+        self.inner.lock().unwrap().take();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::BuildCache;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn temp_cache() -> (BuildCache, PathBuf) {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "nexxo-snapshot-test-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_dir_all(&path);
+        let cache = BuildCache::new(path.to_string_lossy().into_owned(), None).unwrap();
+        (cache, path)
+    }
+
+    #[test]
+    fn keeps_serving_the_state_it_was_taken_at() {
+        let (cache, path) = temp_cache();
+        cache.set("graph:g".into(), "before".into()).unwrap();
+        cache.set("graph:deleted".into(), "there".into()).unwrap();
+
+        let snapshot = cache.snapshot().unwrap();
+        cache.set("graph:g".into(), "after".into()).unwrap();
+        cache.set("graph:new".into(), "written later".into()).unwrap();
+        cache.delete("graph:deleted".into()).unwrap();
+
+        assert_eq!(snapshot.get("graph:g".into()).unwrap().as_deref(), Some("before"));
+        assert_eq!(snapshot.get("graph:new".into()).unwrap(), None);
+        assert_eq!(
+            snapshot.multi_get(vec!["graph:deleted".into(), "graph:g".into()]).unwrap(),
+            [Some("there".to_string()), Some("before".to_string())]
+        );
+        assert_eq!(cache.get("graph:g".into()).as_deref(), Some("after"));
+
+        snapshot.release();
+        snapshot.release();
+        assert!(snapshot.get("graph:g".into()).is_err());
+
+        drop(cache);
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn restore_waits_for_live_snapshots() {
+        let (mut cache, path) = temp_cache();
+        cache.set("graph:g".into(), "checkpointed".into()).unwrap();
+        let checkpoint = cache.checkpoint("before".into()).unwrap();
+        cache.set("graph:g".into(), "changed".into()).unwrap();
+
+        let snapshot = cache.snapshot().unwrap();
+        let error = cache.restore(checkpoint.path.clone()).unwrap_err();
+        assert!(error.reason.contains("1 snapshot(s)"), "{}", error.reason);
+        assert_eq!(snapshot.get("graph:g".into()).unwrap().as_deref(), Some("changed"));
+
+        drop(snapshot);
+        cache.restore(checkpoint.path).unwrap();
+        assert_eq!(cache.get("graph:g".into()).as_deref(), Some("checkpointed"));
+
+        drop(cache);
+        std::fs::remove_dir_all(super::super::checkpoint::checkpoints_root(&path)).unwrap();
+        std::fs::remove_dir_all(path).unwrap();
+    }
+}
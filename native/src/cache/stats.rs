@@ -0,0 +1,149 @@
+// Traffic counters for BuildCache
+//
+// Counters are 64-bit atomics broken down by namespace (one slot per column
+// family) and by build target. Entry counts and sizes are not counted here;
+// they come from RocksDB property estimates so `get_stats` never scans the
+// database.
+
+use napi_derive::napi;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+/// Per-namespace cache statistics
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[napi(object)]
+pub struct NamespaceStats {
+    pub namespace: String,
+    /// RocksDB estimate, may be off for recently deleted keys
+    pub estimated_entries: i64,
+    pub size_bytes: f64,
+    /// Uncompressed over on-disk size of flushed data; 1 until anything is flushed
+    pub compression_ratio: f64,
+    pub hits: i64,
+    pub misses: i64,
+    pub bytes_read: i64,
+    pub bytes_written: i64,
+}
+
+/// Per-target (dev/prod/lib) cache statistics
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[napi(object)]
+pub struct TargetStats {
+    pub target: String,
+    pub hits: i64,
+    pub misses: i64,
+    pub bytes_read: i64,
+    pub bytes_written: i64,
+}
+
+/// Lock-free counters for one namespace, target or the whole cache
+#[derive(Default)]
+pub(crate) struct TrafficCounters {
+    pub hits: AtomicU64,
+    pub misses: AtomicU64,
+    pub bytes_read: AtomicU64,
+    pub bytes_written: AtomicU64,
+}
+
+impl TrafficCounters {
+    fn record_hit(&self, bytes: usize) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        self.bytes_read.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    fn record_miss(&self) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn record_write(&self, bytes: usize) {
+        self.bytes_written.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn load(&self) -> (u64, u64, u64, u64) {
+        (
+            self.hits.load(Ordering::Relaxed),
+            self.misses.load(Ordering::Relaxed),
+            self.bytes_read.load(Ordering::Relaxed),
+            self.bytes_written.load(Ordering::Relaxed),
+        )
+    }
+}
+
+/// All counters of a `BuildCache`
+pub(crate) struct CacheCounters {
+    pub total: TrafficCounters,
+    /// Entries that failed their checksum on read
+    pub corrupted: AtomicU64,
+    pub namespaces: Vec<TrafficCounters>,
+    targets: RwLock<HashMap<String, Arc<TrafficCounters>>>,
+}
+
+impl CacheCounters {
+    pub(crate) fn new(namespace_count: usize) -> Self {
+        Self {
+            total: TrafficCounters::default(),
+            corrupted: AtomicU64::new(0),
+            namespaces: (0..namespace_count).map(|_| TrafficCounters::default()).collect(),
+            targets: RwLock::new(HashMap::new()),
+        }
+    }
+
+    fn target(&self, target: &str) -> Arc<TrafficCounters> {
+        if let Some(counters) = self.targets.read().unwrap().get(target) {
+            return counters.clone();
+        }
+        self.targets
+            .write()
+            .unwrap()
+            .entry(target.to_string())
+            .or_default()
+            .clone()
+    }
+
+    fn each(&self, namespace: usize, target: Option<&str>, record: impl Fn(&TrafficCounters)) {
+        record(&self.total);
+        record(&self.namespaces[namespace]);
+        if let Some(target) = target {
+            record(&self.target(target));
+        }
+    }
+
+    pub(crate) fn hit(&self, namespace: usize, target: Option<&str>, bytes: usize) {
+        self.each(namespace, target, |counters| counters.record_hit(bytes));
+    }
+
+    pub(crate) fn miss(&self, namespace: usize, target: Option<&str>) {
+        self.each(namespace, target, |counters| counters.record_miss());
+    }
+
+    pub(crate) fn corrupt(&self) {
+        self.corrupted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn write(&self, namespace: usize, target: Option<&str>, bytes: usize) {
+        self.each(namespace, target, |counters| counters.record_write(bytes));
+    }
+
+    pub(crate) fn target_stats(&self) -> Vec<TargetStats> {
+        let mut stats: Vec<TargetStats> = self
+            .targets
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(target, counters)| {
+                let (hits, misses, bytes_read, bytes_written) = counters.load();
+                TargetStats {
+                    target: target.clone(),
+                    hits: hits as i64,
+                    misses: misses as i64,
+                    bytes_read: bytes_read as i64,
+                    bytes_written: bytes_written as i64,
+                }
+            })
+            .collect();
+        stats.sort_by(|a, b| a.target.cmp(&b.target));
+        stats
+    }
+}
@@ -0,0 +1,239 @@
+// Storage backends for BuildCache
+//
+// A `BuildCache` keeps its entries in any `nexxo_storage::Storage`, chosen
+// with `NEXXO_CACHE_BACKEND` when the cache is opened:
+// - `rocksdb` (default): one column family per namespace, see `NamespacedRocks`
+// - `directory`: one file per entry under `{cachePath}/entries`
+// - `memory`: nothing survives the process, for tests and ephemeral CI runs
+//
+// Envelopes, stats, tracing, archives and the remote tier work the same on
+// every backend. Features built on RocksDB itself (snapshots, checkpoints,
+// integrity scans, compaction, read-only and secondary access) need it and
+// report an error otherwise.
+
+use nexxo_storage::{BackendKind, BatchOp, DirectoryStorage, MemoryStorage, RocksStorage, Storage};
+use rocksdb::{ColumnFamily, Direction, IteratorMode, WriteBatch, DB};
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::access::Lease;
+use super::compression::{self, Compression, DictionaryCompressed, Dictionaries};
+use super::{namespace_cf, namespace_index, META_CF, NAMESPACES};
+
+/// Size of one namespace, as reported in cache stats
+#[derive(Default)]
+pub(crate) struct NamespaceSizes {
+    pub entries: u64,
+    /// Bytes on disk, or of keys and values off RocksDB
+    pub size: u64,
+    /// Uncompressed and stored block bytes, zero when nothing is compressed
+    pub raw: u64,
+    pub stored: u64,
+}
+
+/// Where a cache keeps its entries
+pub(crate) struct Backend {
+    /// Entries of every namespace, under their full keys
+    pub entries: Box<dyn Storage>,
+    /// Namespace dictionaries `entries` compresses with, for code that reads
+    /// the database directly
+    pub dictionaries: Arc<Dictionaries>,
+    /// Bookkeeping that is not a cache entry, such as the schema record
+    pub meta: Box<dyn Storage>,
+    /// The database behind `entries`, on the RocksDB backend
+    pub db: Option<Arc<DB>>,
+    /// Declared last so the database closes before its files are removed
+    _lease: Lease,
+}
+
+impl Backend {
+    /// Serve the cache from an open RocksDB database, loading its namespace dictionaries
+    pub(crate) fn rocks(db: DB, lease: Lease, compression: &Compression) -> io::Result<Self> {
+        let db = Arc::new(db);
+        let meta = RocksStorage::with_db(db.clone(), Some(META_CF.to_string()))?;
+        let dictionaries = Arc::new(Dictionaries::new(compression));
+        dictionaries.load(&meta)?;
+        Ok(Self {
+            entries: Box::new(DictionaryCompressed {
+                inner: NamespacedRocks { db: db.clone() },
+                dictionaries: dictionaries.clone(),
+            }),
+            dictionaries,
+            meta: Box::new(meta),
+            db: Some(db),
+            _lease: lease,
+        })
+    }
+
+    /// Serve the cache from a backend other than RocksDB at `path`
+    pub(crate) fn open(kind: BackendKind, path: &Path) -> io::Result<Self> {
+        let (entries, meta): (Box<dyn Storage>, Box<dyn Storage>) = match kind {
+            BackendKind::Memory => (Box::new(MemoryStorage::new()), Box::new(MemoryStorage::new())),
+            BackendKind::Directory => (
+                Box::new(DirectoryStorage::open(&path.join("entries"))?),
+                Box::new(DirectoryStorage::open(&path.join("meta"))?),
+            ),
+            BackendKind::RocksDb => unreachable!("RocksDB caches are opened with `Backend::rocks`"),
+        };
+        Ok(Self {
+            entries,
+            dictionaries: Arc::new(Dictionaries::none()),
+            meta,
+            db: None,
+            _lease: Lease::ReadOnly,
+        })
+    }
+
+    /// An empty, in-memory stand-in for a cache whose database is gone
+    pub(crate) fn empty() -> Self {
+        Self {
+            entries: Box::new(MemoryStorage::new()),
+            dictionaries: Arc::new(Dictionaries::none()),
+            meta: Box::new(MemoryStorage::new()),
+            db: None,
+            _lease: Lease::ReadOnly,
+        }
+    }
+
+    /// Sizes of every namespace, in the order of `NAMESPACES`. RocksDB
+    /// estimates them from its properties; other backends scan every entry.
+    pub(crate) fn namespace_sizes(&self) -> io::Result<Vec<NamespaceSizes>> {
+        let mut sizes: Vec<NamespaceSizes> = NAMESPACES.iter().map(|_| NamespaceSizes::default()).collect();
+        match &self.db {
+            Some(db) => {
+                for (index, sizes) in sizes.iter_mut().enumerate() {
+                    let cf = namespace_cf(db, index);
+                    let property = |name: &str| db.property_int_value_cf(cf, name).ok().flatten().unwrap_or(0);
+                    let (raw, stored) = compression::block_sizes(db, cf);
+                    *sizes = NamespaceSizes {
+                        entries: property("rocksdb.estimate-num-keys"),
+                        size: property("rocksdb.total-sst-files-size") + property("rocksdb.cur-size-all-mem-tables"),
+                        raw,
+                        stored,
+                    };
+                }
+            }
+            None => self.entries.scan_prefix(b"", &mut |key, value| {
+                let sizes = &mut sizes[namespace_index(key)];
+                sizes.entries += 1;
+                sizes.size += (key.len() + value.len()) as u64;
+                true
+            })?,
+        }
+        Ok(sizes)
+    }
+
+    /// The RocksDB database, or an error naming the feature that needs it
+    pub(crate) fn db(&self, feature: &str) -> Result<&Arc<DB>, String> {
+        self.db.as_ref().ok_or_else(|| {
+            format!(
+                "{} needs the RocksDB cache backend, not {}",
+                feature,
+                self.entries.name()
+            )
+        })
+    }
+}
+
+/// The namespace column families of a cache database as one store, routing
+/// each key by its namespace
+pub(crate) struct NamespacedRocks {
+    db: Arc<DB>,
+}
+
+impl NamespacedRocks {
+    fn cf(&self, key: &[u8]) -> &ColumnFamily {
+        namespace_cf(&self.db, namespace_index(key))
+    }
+
+    /// Column families that can hold keys starting with `prefix`
+    fn cfs(&self, prefix: &[u8]) -> Vec<&ColumnFamily> {
+        // A prefix that names its namespace only needs that column family
+        if prefix.contains(&b':') {
+            vec![self.cf(prefix)]
+        } else {
+            (0..NAMESPACES.len()).map(|index| namespace_cf(&self.db, index)).collect()
+        }
+    }
+}
+
+impl Storage for NamespacedRocks {
+    fn name(&self) -> &'static str {
+        "rocksdb"
+    }
+
+    fn get(&self, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        self.db.get_cf(self.cf(key), key).map_err(io::Error::other)
+    }
+
+    fn multi_get(&self, keys: &[&[u8]]) -> io::Result<Vec<Option<Vec<u8>>>> {
+        self.db
+            .multi_get_cf(keys.iter().map(|key| (self.cf(key), key)))
+            .into_iter()
+            .map(|result| result.map_err(io::Error::other))
+            .collect()
+    }
+
+    fn put(&self, key: &[u8], value: &[u8]) -> io::Result<()> {
+        self.db.put_cf(self.cf(key), key, value).map_err(io::Error::other)
+    }
+
+    fn delete(&self, key: &[u8]) -> io::Result<()> {
+        self.db.delete_cf(self.cf(key), key).map_err(io::Error::other)
+    }
+
+    fn write(&self, batch: Vec<BatchOp>) -> io::Result<()> {
+        let mut write_batch = WriteBatch::default();
+        for op in &batch {
+            match op {
+                BatchOp::Put(key, value) => write_batch.put_cf(self.cf(key), key, value),
+                BatchOp::Delete(key) => write_batch.delete_cf(self.cf(key), key),
+            }
+        }
+        self.db.write(write_batch).map_err(io::Error::other)
+    }
+
+    fn scan_prefix(&self, prefix: &[u8], visit: &mut dyn FnMut(&[u8], &[u8]) -> bool) -> io::Result<()> {
+        self.scan_from(prefix, prefix, visit)
+    }
+
+    fn scan_from(
+        &self,
+        prefix: &[u8],
+        start: &[u8],
+        visit: &mut dyn FnMut(&[u8], &[u8]) -> bool,
+    ) -> io::Result<()> {
+        for cf in self.cfs(prefix) {
+            let mode = IteratorMode::From(start.max(prefix), Direction::Forward);
+            for item in self.db.iterator_cf(cf, mode) {
+                let (key, value) = item.map_err(io::Error::other)?;
+                if !key.starts_with(prefix) {
+                    break;
+                }
+                if !visit(&key, &value) {
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn clear(&self) -> io::Result<()> {
+        let mut batch = WriteBatch::default();
+        for cf in self.cfs(b"") {
+            for item in self.db.iterator_cf(cf, IteratorMode::Start) {
+                let (key, _) = item.map_err(io::Error::other)?;
+                batch.delete_cf(cf, &key);
+            }
+        }
+        self.db.write(batch).map_err(io::Error::other)
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        for cf in self.cfs(b"") {
+            self.db.flush_cf(cf).map_err(io::Error::other)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,508 @@
+// Lookup tracing and miss explanation for Nexxo v2.0
+//
+// With tracing on, every `get`/`multiGet` records its outcome. A miss is
+// explained on the spot by looking for the nearest existing key with the
+// same structure (see `key`): keys sort by namespace, target and components,
+// so the closest entries share the missed key's longest prefix. Comparing
+// the two keys part by part shows what changed, e.g. the content hash of an
+// input, the target of an artifact or the config fingerprint.
+//
+// Explaining a miss scans at most a few hundred keys, and tracing is off by
+// default, so normal lookups only pay for two atomic loads: one for tracing
+// and one for the orchestrator timeline lookups may also be drawn on.
+
+use napi_derive::napi;
+use nexxo_storage::Storage;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use super::envelope;
+use super::key::unescape;
+use crate::orchestrator::timeline::Timeline;
+
+/// Lookups kept before the oldest are discarded
+const MAX_TRACED_LOOKUPS: usize = 10_000;
+
+/// Keys examined per prefix when looking for the nearest key
+const MAX_CANDIDATES: usize = 64;
+
+/// Other targets probed for keys that differ only in their target
+const MAX_TARGETS: usize = 32;
+
+/// Names of the key parts before the context section, per namespace
+fn part_names(namespace: &str) -> &'static [&'static str] {
+    match namespace {
+        "input" => &["filePath", "contentHash"],
+        "graph" => &["graphHash"],
+        "plan" => &["target", "planHash"],
+        "artifact" => &["target", "artifactId"],
+        _ => &[],
+    }
+}
+
+/// Names of the context section parts, in key order
+const CONTEXT_NAMES: [&str; 3] = ["nexxoVersion", "config", "env"];
+
+/// How a traced lookup ended
+#[napi(string_enum)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LookupOutcome {
+    LocalHit,
+    /// Fetched from the remote cache and stored locally
+    RemoteHit,
+    Miss,
+}
+
+/// Why a lookup missed
+#[napi(string_enum)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MissReason {
+    /// No entry under this key
+    NotFound,
+    /// An entry existed but was written by another schema or producer version
+    Incompatible,
+    /// An entry existed but failed its checksum
+    Corrupt,
+}
+
+/// One key part that differs between a missed key and its nearest neighbour
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[napi(object)]
+pub struct KeyDifference {
+    /// Part name, e.g. `contentHash`, `target` or `config`
+    pub component: String,
+    /// Value in the key that was looked up; empty if it has no such part
+    pub expected: String,
+    /// Value in the nearest existing key; empty if it has no such part
+    pub found: String,
+}
+
+/// Explanation of a missed lookup
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[napi(object)]
+pub struct MissExplanation {
+    pub reason: MissReason,
+    /// Closest existing key with the same structured prefix
+    pub nearest_key: Option<String>,
+    pub differences: Vec<KeyDifference>,
+}
+
+/// One traced lookup
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[napi(object)]
+pub struct CacheLookup {
+    pub key: String,
+    pub outcome: LookupOutcome,
+    pub timestamp: i64,
+    /// Present for misses
+    pub miss: Option<MissExplanation>,
+}
+
+/// Traced lookups, as returned by `explain`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[napi(object)]
+pub struct ExplainReport {
+    pub hits: u32,
+    pub misses: u32,
+    /// Older lookups dropped to keep the trace bounded
+    pub discarded: u32,
+    pub lookups: Vec<CacheLookup>,
+}
+
+#[derive(Default)]
+struct TraceState {
+    lookups: VecDeque<CacheLookup>,
+    discarded: u32,
+    /// Entries dropped by the lookup in progress, so its miss can say why
+    dropped: HashMap<String, MissReason>,
+}
+
+/// Lookup trace shared by all clones of a `BuildCache`
+#[derive(Default)]
+pub(crate) struct CacheTracer {
+    enabled: AtomicBool,
+    state: Mutex<TraceState>,
+    /// Set while `timeline` holds one, so lookups skip its lock otherwise
+    on_timeline: AtomicBool,
+    timeline: Mutex<Option<Arc<Timeline>>>,
+}
+
+impl CacheTracer {
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Draw every lookup that started at `start` on `timeline`, or stop with `None`
+    pub(crate) fn set_timeline(&self, timeline: Option<Arc<Timeline>>) {
+        let mut current = self.timeline.lock().unwrap();
+        self.on_timeline.store(timeline.is_some(), Ordering::Relaxed);
+        *current = timeline;
+    }
+
+    /// Note that a lookup found an entry it could not use
+    pub(crate) fn dropped(&self, key: &str, reason: MissReason) {
+        if self.is_enabled() {
+            self.state.lock().unwrap().dropped.insert(key.to_string(), reason);
+        }
+    }
+
+    /// Record a lookup that started at `start`, explaining it against
+    /// `entries` if it missed
+    pub(crate) fn record(&self, entries: &dyn Storage, key: &str, outcome: LookupOutcome, start: Instant) {
+        if self.on_timeline.load(Ordering::Relaxed) {
+            if let Some(timeline) = self.timeline.lock().unwrap().as_ref() {
+                timeline.record_lookup(key, outcome, start, Some(Instant::now()));
+            }
+        }
+        if !self.is_enabled() {
+            return;
+        }
+
+        let reason = self.state.lock().unwrap().dropped.remove(key);
+        let miss = (outcome == LookupOutcome::Miss).then(|| {
+            let nearest = nearest_key(entries, key);
+            MissExplanation {
+                reason: reason.unwrap_or(MissReason::NotFound),
+                differences: nearest.as_deref().map(|nearest| differences(key, nearest)).unwrap_or_default(),
+                nearest_key: nearest,
+            }
+        });
+
+        let mut state = self.state.lock().unwrap();
+        if state.lookups.len() == MAX_TRACED_LOOKUPS {
+            state.lookups.pop_front();
+            state.discarded += 1;
+        }
+        state.lookups.push_back(CacheLookup {
+            key: key.to_string(),
+            outcome,
+            timestamp: envelope::now_ms(),
+            miss,
+        });
+    }
+
+    /// Traced lookups, optionally only those for one key
+    pub(crate) fn report(&self, key: Option<&str>) -> ExplainReport {
+        let state = self.state.lock().unwrap();
+        let lookups: Vec<CacheLookup> = state
+            .lookups
+            .iter()
+            .filter(|lookup| key.is_none_or(|key| lookup.key == key))
+            .cloned()
+            .collect();
+        let misses = lookups.iter().filter(|lookup| lookup.outcome == LookupOutcome::Miss).count();
+        ExplainReport {
+            hits: (lookups.len() - misses) as u32,
+            misses: misses as u32,
+            discarded: state.discarded,
+            lookups,
+        }
+    }
+
+    pub(crate) fn clear(&self) {
+        *self.state.lock().unwrap() = TraceState::default();
+    }
+}
+
+/// A key split into its escaped parts
+struct KeyParts<'a> {
+    namespace: &'a str,
+    /// Target (for plan and artifact keys) and components
+    parts: Vec<&'a str>,
+    context: Option<Vec<&'a str>>,
+}
+
+impl<'a> KeyParts<'a> {
+    fn split(key: &'a str) -> Self {
+        let (body, context) = match key.split_once('#') {
+            Some((body, context)) => (body, Some(context.split(':').collect())),
+            None => (key, None),
+        };
+        let mut parts = body.split(':');
+        let namespace = parts.next().unwrap_or_default();
+        Self {
+            namespace,
+            parts: parts.collect(),
+            context,
+        }
+    }
+
+    fn body(&self) -> String {
+        let prefix = self.prefix(self.parts.len());
+        prefix.strip_suffix(':').unwrap_or(&prefix).to_string()
+    }
+
+    /// The namespace and the first `count` parts, each followed by `:`
+    fn prefix(&self, count: usize) -> String {
+        let mut prefix = format!("{}:", self.namespace);
+        for part in &self.parts[..count] {
+            prefix.push_str(part);
+            prefix.push(':');
+        }
+        prefix
+    }
+
+    fn is_targeted(&self) -> bool {
+        part_names(self.namespace).first() == Some(&"target") && !self.parts.is_empty()
+    }
+}
+
+/// First keys starting with `prefix` other than `key`, at most `limit` of them
+fn keys_with_prefix(entries: &dyn Storage, key: &str, prefix: &str, limit: usize) -> Vec<String> {
+    let mut found = Vec::new();
+    let _ = entries.scan_from(prefix.as_bytes(), prefix.as_bytes(), &mut |candidate, _| {
+        if candidate != key.as_bytes() {
+            found.push(String::from_utf8_lossy(candidate).into_owned());
+        }
+        found.len() < limit
+    });
+    found
+}
+
+/// Keys that differ from `key` only in their target
+fn other_targets(entries: &dyn Storage, key: &str, parts: &KeyParts) -> Vec<String> {
+    let namespace_prefix = parts.prefix(0);
+    let rest = parts.parts[1..].join(":");
+
+    let mut found = Vec::new();
+    let mut seek = namespace_prefix.clone();
+    for _ in 0..MAX_TARGETS {
+        let mut next = None;
+        let _ = entries.scan_from(namespace_prefix.as_bytes(), seek.as_bytes(), &mut |found, _| {
+            next = Some(String::from_utf8_lossy(found).into_owned());
+            false
+        });
+        let Some(target) = next
+            .as_deref()
+            .and_then(|next| next.strip_prefix(&namespace_prefix))
+            .and_then(|rest| rest.split([':', '#']).next())
+            .map(str::to_string)
+        else {
+            break;
+        };
+
+        if target != parts.parts[0] {
+            let body = format!("{}{}:{}", namespace_prefix, target, rest);
+            found.extend(
+                keys_with_prefix(entries, key, &body, MAX_CANDIDATES)
+                    .into_iter()
+                    .filter(|candidate| KeyParts::split(candidate).body() == body),
+            );
+        }
+        // `;` sorts right after `:`, so this skips every key of this target
+        seek = format!("{}{};", namespace_prefix, target);
+    }
+    found
+}
+
+/// Number of parts two keys have in common, position by position
+fn similarity(a: &KeyParts, b: &KeyParts) -> usize {
+    let same_parts = a.parts.iter().zip(&b.parts).filter(|(a, b)| a == b).count();
+    let same_context = match (&a.context, &b.context) {
+        (Some(a), Some(b)) => a.iter().zip(b).filter(|(a, b)| a == b).count(),
+        _ => 0,
+    };
+    same_parts + same_context
+}
+
+/// Closest existing key to `key`: first keys with the same parts and another
+/// context or target, then keys sharing ever shorter prefixes of its parts
+fn nearest_key(entries: &dyn Storage, key: &str) -> Option<String> {
+    let parts = KeyParts::split(key);
+    let body = parts.body();
+
+    let mut candidates: Vec<String> = keys_with_prefix(entries, key, &body, MAX_CANDIDATES)
+        .into_iter()
+        .filter(|candidate| KeyParts::split(candidate).body() == body)
+        .collect();
+    if parts.is_targeted() {
+        candidates.extend(other_targets(entries, key, &parts));
+    }
+    let mut kept = parts.parts.len();
+    while candidates.is_empty() && kept > 0 {
+        kept -= 1;
+        candidates = keys_with_prefix(entries, key, &parts.prefix(kept), MAX_CANDIDATES);
+    }
+
+    // Earlier candidates share a longer prefix, so they win ties
+    candidates
+        .into_iter()
+        .enumerate()
+        .max_by_key(|(index, candidate)| (similarity(&parts, &KeyParts::split(candidate)), usize::MAX - index))
+        .map(|(_, candidate)| candidate)
+}
+
+/// Parts of `expected` and `found` that differ, by name
+fn differences(expected: &str, found: &str) -> Vec<KeyDifference> {
+    let expected = KeyParts::split(expected);
+    let found = KeyParts::split(found);
+    let decode = |part: Option<&&str>| {
+        part.map(|part| unescape(part).unwrap_or_else(|| part.to_string()))
+            .unwrap_or_default()
+    };
+
+    let names = part_names(expected.namespace);
+    let mut differences = Vec::new();
+    for index in 0..expected.parts.len().max(found.parts.len()) {
+        let (a, b) = (expected.parts.get(index), found.parts.get(index));
+        if a != b {
+            differences.push(KeyDifference {
+                component: names
+                    .get(index)
+                    .map(|name| name.to_string())
+                    .unwrap_or_else(|| format!("component{}", index)),
+                expected: decode(a),
+                found: decode(b),
+            });
+        }
+    }
+
+    let no_context = Vec::new();
+    let expected_context = expected.context.as_ref().unwrap_or(&no_context);
+    let found_context = found.context.as_ref().unwrap_or(&no_context);
+    for (index, name) in CONTEXT_NAMES.iter().enumerate() {
+        let (a, b) = (expected_context.get(index), found_context.get(index));
+        if a != b {
+            differences.push(KeyDifference {
+                component: name.to_string(),
+                expected: decode(a),
+                found: decode(b),
+            });
+        }
+    }
+    differences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nexxo_storage::MemoryStorage;
+
+    fn storage_with(keys: &[&str]) -> MemoryStorage {
+        let storage = MemoryStorage::new();
+        for key in keys {
+            storage.put(key.as_bytes(), b"value").unwrap();
+        }
+        storage
+    }
+
+    fn enabled() -> CacheTracer {
+        let tracer = CacheTracer::default();
+        tracer.set_enabled(true);
+        tracer
+    }
+
+    fn only_miss(tracer: &CacheTracer) -> MissExplanation {
+        let report = tracer.report(None);
+        assert_eq!((report.hits, report.misses), (0, 1));
+        report.lookups[0].miss.clone().unwrap()
+    }
+
+    fn components(miss: &MissExplanation) -> Vec<(&str, &str, &str)> {
+        miss.differences
+            .iter()
+            .map(|difference| (difference.component.as_str(), difference.expected.as_str(), difference.found.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn records_only_while_enabled_and_filters_by_key() {
+        let storage = storage_with(&[]);
+        let tracer = CacheTracer::default();
+        tracer.record(&storage, "graph:g1", LookupOutcome::Miss, Instant::now());
+        assert!(tracer.report(None).lookups.is_empty());
+
+        tracer.set_enabled(true);
+        tracer.record(&storage, "graph:g1", LookupOutcome::LocalHit, Instant::now());
+        tracer.record(&storage, "graph:g2", LookupOutcome::RemoteHit, Instant::now());
+        tracer.record(&storage, "graph:g1", LookupOutcome::Miss, Instant::now());
+        let report = tracer.report(None);
+        assert_eq!((report.hits, report.misses, report.discarded), (2, 1, 0));
+        assert_eq!(report.lookups[1].outcome, LookupOutcome::RemoteHit);
+
+        let report = tracer.report(Some("graph:g1"));
+        assert_eq!(report.lookups.len(), 2);
+        assert!(report.lookups[0].miss.is_none());
+        assert_eq!(report.lookups[1].miss.as_ref().unwrap().reason, MissReason::NotFound);
+
+        tracer.clear();
+        assert!(tracer.report(None).lookups.is_empty());
+    }
+
+    #[test]
+    fn draws_lookups_on_a_timeline_even_while_tracing_is_off() {
+        let storage = storage_with(&[]);
+        let tracer = CacheTracer::default();
+        let timeline = Arc::new(Timeline::new());
+        timeline.set_enabled(true);
+        tracer.set_timeline(Some(timeline.clone()));
+        tracer.record(&storage, "graph:g1", LookupOutcome::Miss, Instant::now());
+        tracer.set_timeline(None);
+        tracer.record(&storage, "graph:g2", LookupOutcome::LocalHit, Instant::now());
+
+        assert!(tracer.report(None).lookups.is_empty());
+        let trace = timeline.export();
+        let spans: Vec<_> = trace["traceEvents"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|event| event["ph"] == "X")
+            .map(|event| (event["name"].clone(), event["args"]["key"].clone()))
+            .collect();
+        assert_eq!(spans, [("Miss".into(), "graph:g1".into())]);
+    }
+
+    #[test]
+    fn explains_a_changed_content_hash() {
+        let storage = storage_with(&["input:src/a.ts:h1#2.0.0:cfg:env", "input:src/b.ts:h2#2.0.0:cfg:env"]);
+        let tracer = enabled();
+        tracer.record(&storage, "input:src/a.ts:h2#2.0.0:cfg:env", LookupOutcome::Miss, Instant::now());
+
+        let miss = only_miss(&tracer);
+        assert_eq!(miss.nearest_key.as_deref(), Some("input:src/a.ts:h1#2.0.0:cfg:env"));
+        assert_eq!(components(&miss), [("contentHash", "h2", "h1")]);
+    }
+
+    #[test]
+    fn explains_a_changed_config_fingerprint() {
+        let storage = storage_with(&["plan:prod:p1#2.0.0:old%3Acfg:env"]);
+        let tracer = enabled();
+        tracer.record(&storage, "plan:prod:p1#2.0.0:new:env", LookupOutcome::Miss, Instant::now());
+
+        let miss = only_miss(&tracer);
+        assert_eq!(miss.nearest_key.as_deref(), Some("plan:prod:p1#2.0.0:old%3Acfg:env"));
+        assert_eq!(components(&miss), [("config", "new", "old:cfg")]);
+    }
+
+    #[test]
+    fn finds_the_same_artifact_built_for_another_target() {
+        let storage = storage_with(&["artifact:dev:other#v:c:e", "artifact:prod:main#v:c:e"]);
+        let tracer = enabled();
+        tracer.record(&storage, "artifact:lib:main#v:c:e", LookupOutcome::Miss, Instant::now());
+
+        let miss = only_miss(&tracer);
+        assert_eq!(miss.nearest_key.as_deref(), Some("artifact:prod:main#v:c:e"));
+        assert_eq!(components(&miss), [("target", "lib", "prod")]);
+    }
+
+    #[test]
+    fn reports_why_an_existing_entry_was_dropped() {
+        let storage = storage_with(&[]);
+        let tracer = enabled();
+        tracer.dropped("graph:g1", MissReason::Incompatible);
+        tracer.record(&storage, "graph:g1", LookupOutcome::Miss, Instant::now());
+        tracer.record(&storage, "graph:g1", LookupOutcome::Miss, Instant::now());
+
+        let report = tracer.report(None);
+        let reasons: Vec<MissReason> = report.lookups.iter().map(|lookup| lookup.miss.as_ref().unwrap().reason).collect();
+        assert_eq!(reasons, [MissReason::Incompatible, MissReason::NotFound]);
+        assert_eq!(report.lookups[0].miss.as_ref().unwrap().nearest_key, None);
+    }
+}
@@ -0,0 +1,268 @@
+// Staged build writes for Nexxo v2.0
+//
+// A build stages its writes in a `CacheTransaction` instead of writing to
+// the cache directly. On success the staged entries are written in a single
+// batch, atomic on RocksDB, so the cache moves from one consistent build to the
+// next; on failure or cancellation they are discarded and the cache is left
+// exactly as the previous build left it.
+
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+use nexxo_storage::BatchOp;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use super::envelope::{self, Envelope};
+use super::remote::RemoteCache;
+use super::stats::CacheCounters;
+use super::storage::Backend;
+use super::publish_writes;
+
+const FINISHED: &str = "Cache transaction has already been committed or rolled back";
+
+/// A staged write: an encoded envelope, or `None` for a delete
+type Staged = BTreeMap<String, Option<Vec<u8>>>;
+
+/// State of an open transaction
+struct Pending {
+    backend: Arc<Backend>,
+    staged: Staged,
+}
+
+/// Pending writes for one build, committed atomically or discarded
+#[napi]
+pub struct CacheTransaction {
+    counters: Arc<CacheCounters>,
+    remote: Option<Arc<RemoteCache>>,
+    producer_version: String,
+    /// `None` once the transaction has been committed or rolled back, so a
+    /// finished transaction no longer holds the database open
+    pending: Mutex<Option<Pending>>,
+}
+
+impl CacheTransaction {
+    pub(crate) fn new(
+        backend: Arc<Backend>,
+        counters: Arc<CacheCounters>,
+        remote: Option<Arc<RemoteCache>>,
+        producer_version: String,
+    ) -> Self {
+        Self {
+            counters,
+            remote,
+            producer_version,
+            pending: Mutex::new(Some(Pending {
+                backend,
+                staged: BTreeMap::new(),
+            })),
+        }
+    }
+
+    /// Run `f` on the open transaction, failing if it is finished
+    fn with_pending<T>(&self, f: impl FnOnce(&mut Pending) -> T) -> Result<T> {
+        match self.pending.lock().unwrap().as_mut() {
+            Some(pending) => Ok(f(pending)),
+            None => Err(Error::from_reason(FINISHED)),
+        }
+    }
+
+    /// Run `f` on the staged writes, failing if the transaction is finished
+    fn with_staged<T>(&self, f: impl FnOnce(&mut Staged) -> T) -> Result<T> {
+        self.with_pending(|pending| f(&mut pending.staged))
+    }
+
+    fn take_pending(&self) -> Result<Pending> {
+        self.pending
+            .lock()
+            .unwrap()
+            .take()
+            .ok_or_else(|| Error::from_reason(FINISHED))
+    }
+}
+
+#[napi]
+impl CacheTransaction {
+    /// Stage a value to be written on commit
+    #[napi]
+    pub fn set(&self, key: String, value: String) -> Result<()> {
+        let bytes = envelope::encode(value.as_bytes(), &self.producer_version, envelope::now_ms());
+        self.with_staged(|staged| {
+            staged.insert(key, Some(bytes));
+        })
+    }
+
+    /// Stage many values to be written on commit
+    #[napi]
+    pub fn batch_set(&self, entries: std::collections::HashMap<String, String>) -> Result<()> {
+        let now = envelope::now_ms();
+        self.with_staged(|staged| {
+            for (key, value) in entries {
+                let bytes = envelope::encode(value.as_bytes(), &self.producer_version, now);
+                staged.insert(key, Some(bytes));
+            }
+        })
+    }
+
+    /// Stage a delete to be applied on commit
+    #[napi]
+    pub fn delete(&self, key: String) -> Result<()> {
+        self.with_staged(|staged| {
+            staged.insert(key, None);
+        })
+    }
+
+    /// Read a value as this build sees it: staged writes first, then the cache.
+    /// Does not count as a cache hit or miss.
+    #[napi]
+    pub fn get(&self, key: String) -> Result<Option<String>> {
+        let (staged, backend) =
+            self.with_pending(|pending| (pending.staged.get(&key).cloned(), pending.backend.clone()))?;
+        let bytes = match staged {
+            Some(staged) => staged,
+            None => backend
+                .entries
+                .get(key.as_bytes())
+                .map_err(|e| Error::from_reason(format!("Failed to read cache: {}", e)))?,
+        };
+
+        Ok(bytes.and_then(|bytes| {
+            Envelope::decode(&bytes)
+                .filter(|entry| entry.is_current(&self.producer_version))
+                .and_then(|entry| String::from_utf8(entry.payload.to_vec()).ok())
+        }))
+    }
+
+    /// Number of staged writes and deletes
+    #[napi(getter)]
+    pub fn get_pending(&self) -> u32 {
+        self.pending
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map_or(0, |pending| pending.staged.len() as u32)
+    }
+
+    /// Write every staged change in one atomic batch and queue the new
+    /// entries for upload. Returns the number of changes applied.
+    #[napi]
+    pub fn commit(&self) -> Result<u32> {
+        let Pending { backend, staged } = self.take_pending()?;
+        let count = staged.len() as u32;
+
+        let batch = staged
+            .iter()
+            .map(|(key, bytes)| match bytes {
+                Some(bytes) => BatchOp::Put(key.as_bytes().to_vec(), bytes.clone()),
+                None => BatchOp::Delete(key.as_bytes().to_vec()),
+            })
+            .collect();
+        backend
+            .entries
+            .write(batch)
+            .map_err(|e| Error::from_reason(format!("Failed to commit cache transaction: {}", e)))?;
+
+        let written: Vec<(String, Vec<u8>)> = staged
+            .into_iter()
+            .filter_map(|(key, bytes)| bytes.map(|bytes| (key, bytes)))
+            .collect();
+        publish_writes(&self.counters, self.remote.as_deref(), &written);
+        Ok(count)
+    }
+
+    /// Discard every staged change. Returns the number of changes dropped.
+    #[napi]
+    pub fn rollback(&self) -> Result<u32> {
+        Ok(self.take_pending()?.staged.len() as u32)
+    }
+
+    /// Whether the transaction can still stage writes
+    #[napi(getter)]
+    pub fn get_is_open(&self) -> bool {
+        self.pending.lock().unwrap().is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::BuildCache;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn temp_cache() -> (BuildCache, PathBuf) {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "nexxo-transaction-test-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_dir_all(&path);
+        let cache = BuildCache::new(path.to_string_lossy().into_owned(), None).unwrap();
+        (cache, path)
+    }
+
+    #[test]
+    fn staged_writes_stay_invisible_until_commit() {
+        let (cache, path) = temp_cache();
+        cache.set("graph:old".into(), "kept".into()).unwrap();
+        cache.set("graph:gone".into(), "old".into()).unwrap();
+
+        let transaction = cache.begin_transaction().unwrap();
+        transaction.set("graph:new".into(), "fresh".into()).unwrap();
+        transaction.delete("graph:gone".into()).unwrap();
+        assert_eq!(transaction.get_pending(), 2);
+
+        // The build sees its own writes, everyone else sees the cache as it was
+        assert_eq!(transaction.get("graph:new".into()).unwrap().as_deref(), Some("fresh"));
+        assert_eq!(transaction.get("graph:gone".into()).unwrap(), None);
+        assert_eq!(transaction.get("graph:old".into()).unwrap().as_deref(), Some("kept"));
+        assert_eq!(cache.get("graph:new".into()), None);
+        assert_eq!(cache.get("graph:gone".into()).as_deref(), Some("old"));
+
+        assert_eq!(transaction.commit().unwrap(), 2);
+        assert!(!transaction.get_is_open());
+        assert_eq!(cache.get("graph:new".into()).as_deref(), Some("fresh"));
+        assert_eq!(cache.get("graph:gone".into()), None);
+        assert_eq!(cache.get("graph:old".into()).as_deref(), Some("kept"));
+
+        drop(cache);
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn rollback_discards_every_staged_write() {
+        let (cache, path) = temp_cache();
+        cache.set("plan:dev:p".into(), "before".into()).unwrap();
+
+        let transaction = cache.begin_transaction().unwrap();
+        transaction.set("plan:dev:p".into(), "after".into()).unwrap();
+        transaction.batch_set([("plan:dev:q".to_string(), "new".to_string())].into()).unwrap();
+        assert_eq!(transaction.rollback().unwrap(), 2);
+
+        assert_eq!(cache.get("plan:dev:p".into()).as_deref(), Some("before"));
+        assert_eq!(cache.get("plan:dev:q".into()), None);
+
+        drop(cache);
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn a_finished_transaction_rejects_everything() {
+        let (cache, path) = temp_cache();
+        let transaction = cache.begin_transaction().unwrap();
+        transaction.set("graph:a".into(), "1".into()).unwrap();
+        assert_eq!(transaction.commit().unwrap(), 1);
+
+        for error in [
+            transaction.commit().err(),
+            transaction.rollback().err(),
+            transaction.set("graph:b".into(), "2".into()).err(),
+            transaction.get("graph:a".into()).err(),
+        ] {
+            assert_eq!(error.unwrap().reason, super::FINISHED);
+        }
+        assert_eq!(transaction.get_pending(), 0);
+
+        drop(cache);
+        std::fs::remove_dir_all(path).unwrap();
+    }
+}
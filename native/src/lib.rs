@@ -17,13 +17,19 @@ pub use graph::{
 
 // Re-export orchestrator module
 pub use orchestrator::{
-  BuildOrchestrator, BuildEvent, OrchestratorStats,
+  BuildOrchestrator, BuildEvent, BuildStage, OrchestratorStats, OtlpExporterOptions, RunOptions, StageEvent, StageTiming,
+  BuildReport, BuildStatus, DeterminismMismatch, DeterminismReport, FailureMode, NativeTaskKind, RetryPolicy, TaskFailure,
+  TaskInvocation, TaskResult, TaskSpec, TaskStatus,
   get_optimal_parallelism, benchmark_parallelism
 };
 
 // Re-export cache module
 pub use cache::{
-  BuildCache, CacheStats,
+  BuildCache, BuildCacheOptions, CacheAccessMode, CacheCompression, CacheRecovery, CacheSnapshot,
+  CacheStats, CacheTransaction, CheckpointInfo, CompactProgress, CompressionOptions, EntryMetadata, ExportReport,
+  ImportReport, NamespaceStats, RemoteCacheOptions, RemoteCacheProtocol, RemoteCacheStats, TargetStats, VerifyReport,
+  CacheLookup, ExplainReport, KeyDifference, LookupOutcome, MissExplanation, MissReason,
+  CacheKeyBuilder, CacheKeyOptions, DecodedCacheKey, decode_cache_key, fingerprint_config,
   create_input_key, create_graph_key, create_plan_key, create_artifact_key
 };
 
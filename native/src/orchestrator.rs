@@ -4,34 +4,45 @@
 // This module provides multi-core parallel orchestration with:
 // - Work-stealing scheduler for optimal CPU utilization
 // - Parallel workers for graph/plan/execute stages
-// - Deterministic stable IDs
-// - Structured event logging
+// - Deterministic stable IDs, and builds checked for determinism (see `determinism`)
+// - Structured event logging, with bounded history and subscribers (see `events`)
+// - Dependency-ordered task graphs (see `dag`) of native and JS tasks (see `task`)
+// - A build lifecycle of stages with JS hooks (see `stage`)
+// - Chrome Trace export of build timelines (see `timeline`)
+// - OpenTelemetry export of finished builds (see `otlp`)
+
+mod dag;
+mod determinism;
+mod events;
+mod otlp;
+mod stage;
+mod task;
+pub(crate) mod timeline;
 
 use napi::bindgen_prelude::*;
 use napi_derive::napi;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use tokio::sync::RwLock;
 use rayon::prelude::*;
 
-/// Build stage for orchestration
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[napi(string_enum)]
-pub enum BuildStage {
-    Init,
-    Graph,
-    Plan,
-    DeterminismCheck,
-    Execute,
-    Emit,
-}
+use crate::cache::{BuildCache, LookupOutcome};
+
+pub use dag::{BuildReport, BuildStatus, FailureMode, TaskFailure, TaskResult, TaskStatus};
+pub use determinism::{DeterminismMismatch, DeterminismReport};
+pub use events::{EventCallback, EventFilter, EventLevel};
+pub use otlp::OtlpExporterOptions;
+pub use stage::{BuildStage, StageEvent, StageHook, StageTiming};
+pub use task::{NativeTaskKind, RetryPolicy, TaskInvocation, TaskSpec};
 
 /// Build event for structured logging
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[napi(object)]
 pub struct BuildEvent {
-    pub stage: String,
+    pub stage: BuildStage,
+    pub level: EventLevel,
     pub message: String,
     pub timestamp: i64,
     pub duration_ms: Option<f64>,
@@ -46,26 +57,62 @@ pub struct OrchestratorStats {
     pub completed_tasks: u32,
     pub failed_tasks: u32,
     pub total_duration_ms: f64,
+    /// Time tasks spent ready but waiting for a free slot
+    pub total_queued_ms: f64,
     pub parallelism: u32,
 }
 
-// Unused TaskResult struct removed
-// pub struct TaskResult { ... }
+/// Options for one `run` call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[napi(object)]
+pub struct RunOptions {
+    /// Id reported back in the build report and used by `cancel` and
+    /// `prioritize` (default: a fresh `nextBuildId()`)
+    pub build_id: Option<String>,
+    /// Maximum number of tasks running at once (default: the orchestrator's parallelism)
+    pub max_concurrency: Option<u32>,
+    /// Maximum number of JS callback tasks running at once, within
+    /// `maxConcurrency` (default: `maxConcurrency`)
+    pub max_js_concurrency: Option<u32>,
+    /// Whether to go on after a task fails (default: ContinueOnError)
+    pub failure_mode: Option<FailureMode>,
+    /// Retries for tasks that have no policy of their own (default: none)
+    pub retry: Option<RetryPolicy>,
+    /// Run the Plan and Execute stages twice, in a different order, and
+    /// compare their outputs (default: false). `prioritize` does not affect
+    /// the rehearsal, and rehearsed tasks record no spans for `exportTrace`.
+    pub check_determinism: Option<bool>,
+}
 
 /// Tokio-based build orchestrator
 #[napi]
 pub struct BuildOrchestrator {
     runtime: Arc<tokio::runtime::Runtime>,
-    events: Arc<RwLock<Vec<BuildEvent>>>,
+    events: Arc<events::EventLog>,
     stats: Arc<RwLock<OrchestratorStats>>,
     parallelism: u32,
+    next_build: AtomicU64,
+    /// Builds currently running, by build id
+    running: Arc<Mutex<HashMap<String, RunningBuild>>>,
+    stage_hooks: Arc<Mutex<stage::StageHooks>>,
+    timeline: Arc<timeline::Timeline>,
+    otlp: Arc<Mutex<Option<Arc<otlp::OtlpExporter>>>>,
+}
+
+/// Handle on a running build for `cancel` and `prioritize`
+struct RunningBuild {
+    cancel: Arc<dag::CancelToken>,
+    raise: tokio::sync::mpsc::UnboundedSender<(usize, i32)>,
+    /// Task indices by id
+    tasks: HashMap<String, usize>,
 }
 
 #[napi]
 impl BuildOrchestrator {
-    /// Create a new orchestrator with specified parallelism
+    /// Create a new orchestrator with specified parallelism, keeping the
+    /// last `event_history` events (default: 1000)
     #[napi(constructor)]
-    pub fn new(parallelism: Option<u32>) -> Result<Self> {
+    pub fn new(parallelism: Option<u32>, event_history: Option<u32>) -> Result<Self> {
         let parallelism = parallelism.unwrap_or_else(|| num_cpus::get() as u32);
         
         // Create Tokio runtime with work-stealing scheduler
@@ -78,50 +125,330 @@ impl BuildOrchestrator {
         
         Ok(Self {
             runtime: Arc::new(runtime),
-            events: Arc::new(RwLock::new(Vec::new())),
+            events: Arc::new(events::EventLog::new(
+                event_history.unwrap_or(events::DEFAULT_EVENT_HISTORY) as usize,
+            )),
             stats: Arc::new(RwLock::new(OrchestratorStats {
                 total_tasks: 0,
                 completed_tasks: 0,
                 failed_tasks: 0,
                 total_duration_ms: 0.0,
+                total_queued_ms: 0.0,
                 parallelism,
             })),
             parallelism,
+            next_build: AtomicU64::new(1),
+            running: Arc::new(Mutex::new(HashMap::new())),
+            stage_hooks: Arc::new(Mutex::new(stage::StageHooks::default())),
+            timeline: Arc::new(timeline::Timeline::new()),
+            otlp: Arc::new(Mutex::new(None)),
         })
     }
     
-    /// Log a build event
-    #[napi]
-    pub async fn log_event(&self, stage: String, message: String, duration_ms: Option<f64>) -> Result<()> {
-        let event = BuildEvent {
-            stage,
-            message,
-            timestamp: chrono::Utc::now().timestamp_millis(),
-            duration_ms,
-            metadata: None,
-        };
-        
-        let mut events = self.events.write().await;
-        events.push(event);
+    /// Log a build event (default level: Info). `stage` is a `BuildStage`, or
+    /// its name in any case, such as `"execute"` or `"determinism-check"`.
+    #[napi(
+        ts_args_type = "stage: BuildStage | string, message: string, durationMs?: number | undefined | null, level?: EventLevel | undefined | null"
+    )]
+    pub async fn log_event(
+        &self,
+        stage: String,
+        message: String,
+        duration_ms: Option<f64>,
+        level: Option<EventLevel>,
+    ) -> Result<()> {
+        let stage = BuildStage::parse(&stage)
+            .ok_or_else(|| Error::from_reason(format!("Unknown build stage {:?}", stage)))?;
+        self.events.emit(stage, level.unwrap_or(EventLevel::Info), message, duration_ms);
         Ok(())
     }
     
-    /// Get all logged events
+    /// Get the stored events, oldest first, optionally only those matching `filter`
+    #[napi]
+    pub async fn get_events(&self, filter: Option<EventFilter>) -> Result<Vec<BuildEvent>> {
+        Ok(self.events.history(filter.as_ref()))
+    }
+    
+    /// Call `callback` with every event matching `filter` as it is logged.
+    /// Returns an id for `unsubscribe`.
+    #[napi(ts_args_type = "callback: (event: BuildEvent) => void, filter?: EventFilter | undefined | null")]
+    pub fn subscribe(&self, callback: EventCallback, filter: Option<EventFilter>) -> u32 {
+        self.events.subscribe(callback, filter)
+    }
+    
+    /// Stop a subscription. Returns false if there was none with this id.
     #[napi]
-    pub async fn get_events(&self) -> Result<Vec<BuildEvent>> {
-        let events = self.events.read().await;
-        Ok(events.clone())
+    pub fn unsubscribe(&self, id: u32) -> bool {
+        self.events.unsubscribe(id)
     }
     
     /// Clear all events
     #[napi]
     pub async fn clear_events(&self) -> Result<()> {
-        let mut events = self.events.write().await;
-        events.clear();
+        self.events.clear();
+        Ok(())
+    }
+    
+    /// Run a graph of native and JS tasks on the orchestrator's runtime. Each
+    /// task starts as soon as its dependencies have succeeded; tasks depending
+    /// on a failed task are skipped. Fails only if the graph itself is invalid;
+    /// a build stopped by `cancel` resolves with status Cancelled, one whose
+    /// stage hook throws with status Failed, and one whose outputs differ
+    /// between runs under `checkDeterminism` with status Nondeterministic.
+    #[napi]
+    pub async fn run(&self, tasks: Vec<TaskSpec>, options: Option<RunOptions>) -> Result<BuildReport> {
+        let options = options.unwrap_or(RunOptions {
+            build_id: None,
+            max_concurrency: None,
+            max_js_concurrency: None,
+            failure_mode: None,
+            retry: None,
+            check_determinism: None,
+        });
+        let build_id = options.build_id.unwrap_or_else(|| self.next_build_id());
+        let max_concurrency = options.max_concurrency.unwrap_or(self.parallelism);
+        if max_concurrency == 0 {
+            return Err(Error::from_reason("maxConcurrency must be at least 1"));
+        }
+        let max_js_concurrency = options.max_js_concurrency.unwrap_or(max_concurrency);
+        if max_js_concurrency == 0 {
+            return Err(Error::from_reason("maxJsConcurrency must be at least 1"));
+        }
+        let limits = dag::Limits {
+            total: max_concurrency as usize,
+            js: max_js_concurrency as usize,
+        };
+        
+        let task_indices = tasks
+            .iter()
+            .enumerate()
+            .map(|(i, task)| (task.id.clone(), i))
+            .collect();
+        let failure_mode = options.failure_mode.unwrap_or(FailureMode::ContinueOnError);
+        let default_retry = match &options.retry {
+            Some(policy) => policy.to_retry()?,
+            None => dag::Retry::NONE,
+        };
+        let tasks = tasks
+            .into_iter()
+            .map(|task| task.into_task(default_retry))
+            .collect::<Result<Vec<_>>>()?;
+        
+        let cancel = Arc::new(dag::CancelToken::default());
+        let (raise, raise_requests) = tokio::sync::mpsc::unbounded_channel();
+        {
+            let mut running = self.running.lock().unwrap();
+            if running.contains_key(&build_id) {
+                return Err(Error::from_reason(format!("Build {} is already running", build_id)));
+            }
+            running.insert(build_id.clone(), RunningBuild {
+                cancel: cancel.clone(),
+                raise,
+                tasks: task_indices,
+            });
+        }
+        let controls = dag::Controls {
+            cancel,
+            raise: raise_requests,
+        };
+        let observers = dag::Observers {
+            hooks: self.stage_hooks.lock().unwrap().clone(),
+            events: self.events.clone(),
+            timeline: self.timeline.clone(),
+        };
+        let start_unix_nanos = chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default();
+        let result = self.runtime
+            .spawn(dag::execute(
+                build_id.clone(),
+                tasks,
+                limits,
+                failure_mode,
+                options.check_determinism.unwrap_or(false),
+                controls,
+                observers,
+            ))
+            .await;
+        self.running.lock().unwrap().remove(&build_id);
+        let report = result
+            .map_err(|e| Error::from_reason(format!("Build task failed: {}", e)))?
+            .map_err(Error::from_reason)?;
+        
+        let mut stats = self.stats.write().await;
+        stats.total_tasks += report.tasks.len() as u32;
+        stats.completed_tasks += report.succeeded;
+        stats.failed_tasks += report.failed;
+        stats.total_duration_ms += report.duration_ms;
+        stats.total_queued_ms += report.total_queued_ms;
+        drop(stats);
+        
+        let level = match report.status {
+            BuildStatus::Succeeded => EventLevel::Info,
+            BuildStatus::Cancelled => EventLevel::Warn,
+            BuildStatus::Failed | BuildStatus::Nondeterministic => EventLevel::Error,
+        };
+        // The summary goes under the last stage the build reached
+        let last_stage = report.stages.last().map_or(BuildStage::Init, |timing| timing.stage);
+        self.events.emit(
+            last_stage,
+            level,
+            format!(
+                "Build {} {:?}: {} succeeded, {} failed, {} skipped, {} cancelled",
+                report.build_id, report.status, report.succeeded, report.failed, report.skipped, report.cancelled
+            ),
+            Some(report.duration_ms),
+        );
+        
+        if let Some(exporter) = self.otlp.lock().unwrap().as_ref() {
+            exporter.enqueue(report.clone(), start_unix_nanos, last_stage);
+        }
+        
+        Ok(report)
+    }
+    
+    /// A fresh id of the form `build-{n}`, never handed out before. Pass it as
+    /// `RunOptions.buildId` to be able to `cancel` or `prioritize` the build
+    /// before `run` resolves.
+    #[napi]
+    pub fn next_build_id(&self) -> String {
+        format!("build-{}", self.next_build.fetch_add(1, Ordering::Relaxed))
+    }
+    
+    /// Cancel a running build: no new tasks start, running native tasks are
+    /// told to stop, tasks waiting to retry are cancelled, and its `run` call
+    /// resolves with status Cancelled once the tasks already running have
+    /// returned. A build whose tasks had all finished completes as usual.
+    /// Returns false if no build with this id is running.
+    #[napi]
+    pub fn cancel(&self, build_id: String) -> bool {
+        match self.running.lock().unwrap().get(&build_id) {
+            Some(build) => {
+                build.cancel.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+    
+    /// Raise a task of a running build, and every task it depends on, to at
+    /// least `priority` so it starts ahead of other queued work. Tasks that
+    /// already started are unaffected, as is a determinism rehearsal.
+    /// Returns false if no build with this id is running.
+    #[napi]
+    pub fn prioritize(&self, build_id: String, task_id: String, priority: i32) -> Result<bool> {
+        let running = self.running.lock().unwrap();
+        let Some(build) = running.get(&build_id) else {
+            return Ok(false);
+        };
+        let &task = build.tasks.get(&task_id).ok_or_else(|| {
+            Error::from_reason(format!("Build {} has no task {:?}", build_id, task_id))
+        })?;
+        // Fails only if the build has just finished
+        Ok(build.raise.send((task, priority)).is_ok())
+    }
+    
+    /// Call `hook` whenever a build enters a stage. Hooks run in the order
+    /// they were registered and the build waits for each. If one throws or
+    /// rejects, no more tasks start and the build goes straight to Emit, then
+    /// resolves with status Failed and the hook's error in `error`.
+    #[napi(ts_args_type = "hook: (event: StageEvent) => void | Promise<void>")]
+    pub fn on_stage_enter(&self, hook: StageHook) {
+        self.stage_hooks.lock().unwrap().enter.push(Arc::new(hook));
+    }
+    
+    /// Call `hook` whenever a build leaves a stage, with the time spent in
+    /// it. Like `onStageEnter`, the build waits for each hook.
+    #[napi(ts_args_type = "hook: (event: StageEvent) => void | Promise<void>")]
+    pub fn on_stage_leave(&self, hook: StageHook) {
+        self.stage_hooks.lock().unwrap().leave.push(Arc::new(hook));
+    }
+    
+    /// Remove all stage hooks. Builds already running keep theirs.
+    #[napi]
+    pub fn clear_stage_hooks(&self) {
+        *self.stage_hooks.lock().unwrap() = stage::StageHooks::default();
+    }
+    
+    /// Start or stop recording spans for `exportTrace`
+    #[napi]
+    pub fn set_tracing(&self, enabled: bool) {
+        self.timeline.set_enabled(enabled);
+    }
+    
+    /// Whether spans are being recorded
+    #[napi(getter)]
+    pub fn get_tracing(&self) -> bool {
+        self.timeline.is_enabled()
+    }
+    
+    /// Record a cache lookup made for a build, e.g. by a JS task, that took
+    /// `duration_ms` and just finished. Ignored while tracing is off.
+    #[napi]
+    pub fn trace_cache_lookup(&self, key: String, outcome: LookupOutcome, duration_ms: Option<f64>) {
+        let end = std::time::Instant::now();
+        let start = duration_ms
+            .filter(|ms| ms.is_finite() && *ms > 0.0)
+            .and_then(|ms| end.checked_sub(std::time::Duration::from_secs_f64(ms / 1000.0)));
+        // Without a duration it is drawn as an instant
+        self.timeline.record_lookup(&key, outcome, start.unwrap_or(end), start.map(|_| end));
+    }
+    
+    /// Draw every lookup through `cache` (and its clones) on this timeline
+    /// while tracing is on, replacing any orchestrator it was drawn on
+    /// before. Pass `enabled: false` to stop.
+    #[napi]
+    pub fn trace_cache(&self, cache: &BuildCache, enabled: Option<bool>) {
+        cache.set_timeline(enabled.unwrap_or(true).then(|| self.timeline.clone()));
+    }
+    
+    /// Recorded spans as Chrome Trace Event JSON, for chrome://tracing or
+    /// Perfetto. Also written to `path` if given.
+    #[napi]
+    pub fn export_trace(&self, path: Option<String>) -> Result<String> {
+        let json = serde_json::to_string(&self.timeline.export())
+            .map_err(|e| Error::from_reason(format!("Failed to serialize trace: {}", e)))?;
+        if let Some(path) = path {
+            std::fs::write(&path, &json)
+                .map_err(|e| Error::from_reason(format!("Failed to write trace to {}: {}", path, e)))?;
+        }
+        Ok(json)
+    }
+    
+    /// Drop all recorded spans
+    #[napi]
+    pub fn clear_trace(&self) {
+        self.timeline.clear();
+    }
+    
+    /// Send every finished build to an OpenTelemetry collector over OTLP/HTTP,
+    /// or stop sending with `null`. Builds are sent in the background, so
+    /// `run` does not wait for the collector; export failures are logged as
+    /// Warn events. Builds already queued are still sent after a change.
+    #[napi]
+    pub fn set_otlp_exporter(&self, options: Option<OtlpExporterOptions>) -> Result<()> {
+        let exporter = options
+            .map(|options| otlp::OtlpExporter::new(&options, self.events.clone()).map(Arc::new))
+            .transpose()
+            .map_err(Error::from_reason)?;
+        *self.otlp.lock().unwrap() = exporter;
         Ok(())
     }
     
-    /// Execute tasks in parallel
+    /// Wait until the current OTLP exporter has sent every finished build,
+    /// resolving to false if some were still queued after `timeout_ms`
+    /// (default: 30000). Resolves to true when no exporter is set.
+    #[napi]
+    pub async fn flush_otlp(&self, timeout_ms: Option<u32>) -> Result<bool> {
+        let Some(exporter) = self.otlp.lock().unwrap().clone() else {
+            return Ok(true);
+        };
+        let timeout = std::time::Duration::from_millis(timeout_ms.unwrap_or(30_000) as u64);
+        tokio::task::spawn_blocking(move || exporter.flush(timeout))
+            .await
+            .map_err(|e| Error::from_reason(format!("OTLP flush failed: {}", e)))
+    }
+    
+    /// Benchmark the runtime with `task_count` independent 10ms tasks.
+    /// Use `run` for real work.
     #[napi]
     pub async fn execute_parallel(&self, task_count: u32) -> Result<OrchestratorStats> {
         let start = std::time::Instant::now();
@@ -176,9 +503,7 @@ impl BuildOrchestrator {
     /// Generate deterministic stable ID from content
     #[napi]
     pub fn generate_stable_id(&self, content: String, prefix: String) -> String {
-        use xxhash_rust::xxh3::xxh3_64;
-        let hash = xxh3_64(content.as_bytes());
-        format!("{}:{:016x}", prefix, hash)
+        determinism::stable_id(&content, &prefix)
     }
     
     /// Batch generate stable IDs
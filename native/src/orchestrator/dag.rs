@@ -0,0 +1,1287 @@
+// Dependency-ordered task execution for Nexxo v2.0
+//
+// A build is a set of named tasks, each listing the tasks it depends on.
+// Tasks are started as soon as all their dependencies have succeeded, up to
+// a concurrency limit, so independent work always runs in parallel. A task
+// whose dependency failed is skipped rather than run against missing input.
+// Failed tasks can be retried with backoff first, and a build can either stop
+// at its first failure or run everything that does not depend on it.
+//
+// Tasks run stage by stage (see `stage`): a task's dependencies must be in
+// its own stage or an earlier one, and a stage's tasks only start once every
+// task of the stages before it has finished.
+//
+// When more tasks are ready than can run, the highest priority goes first.
+// Priorities can be raised while the build runs, e.g. for a module the
+// browser is waiting on; its dependencies are raised with it, since it
+// cannot start before they finish.
+//
+// Cancelling a build stops new tasks from starting and tells running native
+// tasks through their context; the build then ends once the tasks already
+// running have returned, with the rest reported as cancelled, including tasks
+// waiting to retry. A cancel that lands after every task has finished
+// changes nothing.
+//
+// Tasks are plain async functions, so the same scheduler runs native work
+// and JS callbacks alike. JS tasks get a concurrency limit of their own on
+// top of the overall one, since they all share the one JS thread.
+//
+// A build can also check that it is deterministic, by running its Plan and
+// Execute stages a second time (see `determinism`).
+
+use napi_derive::napi;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Notify};
+use tokio::task::JoinSet;
+
+use super::determinism::{self, Comparison, DeterminismReport, Input, RehearsedTask, RunOutcome};
+use super::events::{EventLevel, EventLog};
+use super::stage::{BuildStage, StageHooks, StageMachine, StageTiming};
+use super::timeline::{Timeline, Track};
+
+/// Why a task did not produce an output
+#[derive(Debug)]
+pub(crate) enum TaskError {
+    Failed(String),
+    /// Stopped early because the build was cancelled
+    Cancelled,
+}
+
+impl From<String> for TaskError {
+    fn from(message: String) -> Self {
+        TaskError::Failed(message)
+    }
+}
+
+/// Output of a task, or why it has none
+pub(crate) type TaskOutcome = std::result::Result<String, TaskError>;
+
+pub(crate) type TaskFuture = Pin<Box<dyn Future<Output = TaskOutcome> + Send>>;
+
+/// The work of a task, called once its dependencies have succeeded and again
+/// for each retry
+pub(crate) type TaskFn = Arc<dyn Fn(TaskContext) -> TaskFuture + Send + Sync>;
+
+/// Cancellation signal shared by a build and its tasks
+#[derive(Default)]
+pub(crate) struct CancelToken {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        self.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Resolves once the build is cancelled
+    pub async fn cancelled(&self) {
+        loop {
+            // Registered before the check, so a cancel in between still wakes it
+            let notified = self.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// What a task gets to see when it starts
+#[derive(Clone)]
+pub(crate) struct TaskContext {
+    pub id: String,
+    /// 1 for the first run, 2 for the first retry and so on
+    pub attempt: u32,
+    /// Outputs of the task's dependencies, in the order they were declared
+    pub inputs: Vec<(String, String)>,
+    /// Long-running tasks should stop with `TaskError::Cancelled` once this fires
+    pub cancel: Arc<CancelToken>,
+}
+
+/// Where a task's work runs, for concurrency limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Lane {
+    Native,
+    Js,
+}
+
+/// A task to schedule
+pub(crate) struct Task {
+    pub id: String,
+    pub dependencies: Vec<String>,
+    pub stage: BuildStage,
+    pub lane: Lane,
+    /// Module the task's output belongs to, if any
+    pub module: Option<String>,
+    /// Higher starts first among ready tasks
+    pub priority: i32,
+    pub retry: Retry,
+    pub run: TaskFn,
+}
+
+/// When to run a failed task again
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Retry {
+    pub retries: u32,
+    pub backoff_ms: f64,
+    /// Each delay is this many times the previous one
+    pub factor: f64,
+    pub max_backoff_ms: f64,
+}
+
+impl Retry {
+    pub const NONE: Retry = Retry {
+        retries: 0,
+        backoff_ms: 0.0,
+        factor: 1.0,
+        max_backoff_ms: 0.0,
+    };
+
+    /// Delay before retry number `retry`, counting from 1
+    fn delay(&self, retry: u32) -> Duration {
+        let ms = self.backoff_ms * self.factor.powi(retry.saturating_sub(1) as i32);
+        Duration::from_secs_f64(ms.min(self.max_backoff_ms) / 1000.0)
+    }
+}
+
+/// Whether a build goes on after a task fails
+#[napi(string_enum)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FailureMode {
+    /// Start no new tasks after the first failure
+    FailFast,
+    /// Run every task that does not depend on a failed one
+    ContinueOnError,
+}
+
+/// How many tasks may run at once
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Limits {
+    pub total: usize,
+    pub js: usize,
+}
+
+/// Ways to steer a build while it runs
+pub(crate) struct Controls {
+    pub cancel: Arc<CancelToken>,
+    /// Requests to raise a task, by index, and its dependencies to a priority
+    pub raise: mpsc::UnboundedReceiver<(usize, i32)>,
+}
+
+/// What gets told about a build as it runs
+pub(crate) struct Observers {
+    pub hooks: StageHooks,
+    pub events: Arc<EventLog>,
+    pub timeline: Arc<Timeline>,
+}
+
+/// How a task ended
+#[napi(string_enum)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TaskStatus {
+    Succeeded,
+    Failed,
+    /// Not run because a dependency did not succeed
+    Skipped,
+    /// Not run, or stopped early, because the build was cancelled
+    Cancelled,
+}
+
+/// Result and timing of one task
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[napi(object)]
+pub struct TaskResult {
+    pub id: String,
+    pub stage: BuildStage,
+    pub status: TaskStatus,
+    pub output: Option<String>,
+    pub error: Option<String>,
+    /// When the task started, in ms since the build started; absent if skipped
+    pub start_ms: Option<f64>,
+    /// Time between its dependencies finishing and the task starting
+    pub queued_ms: Option<f64>,
+    /// Including retries, and the backoff between them
+    pub duration_ms: f64,
+    /// Number of times the task was run; 0 if it never started
+    pub attempts: u32,
+}
+
+/// A failed task and the tasks that were not run because of it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[napi(object)]
+pub struct TaskFailure {
+    pub id: String,
+    /// Error of each attempt, first to last
+    pub errors: Vec<String>,
+    /// Tasks skipped because they depend on this one, directly or through
+    /// other skipped tasks, or because the build stopped at this failure
+    pub skipped: Vec<String>,
+}
+
+/// Outcome of a whole build
+#[napi(string_enum)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BuildStatus {
+    Succeeded,
+    /// At least one task failed
+    Failed,
+    /// Stopped by `cancel` before every task had run
+    Cancelled,
+    /// Every task succeeded, but the determinism check found tasks whose
+    /// output differed between runs
+    Nondeterministic,
+}
+
+/// Results of a build, with tasks in the order they were submitted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[napi(object)]
+pub struct BuildReport {
+    pub build_id: String,
+    pub status: BuildStatus,
+    pub duration_ms: f64,
+    pub succeeded: u32,
+    pub failed: u32,
+    pub skipped: u32,
+    pub cancelled: u32,
+    /// Sum of `queuedMs` over all tasks
+    pub total_queued_ms: f64,
+    pub max_queued_ms: f64,
+    /// Failed tasks, in the order they were submitted
+    pub failures: Vec<TaskFailure>,
+    /// Why the build failed other than through a task, e.g. a stage hook
+    /// that threw
+    pub error: Option<String>,
+    /// Stages the build went through, in order
+    pub stages: Vec<StageTiming>,
+    /// Only set if `checkDeterminism` was on and the check ran
+    pub determinism: Option<DeterminismReport>,
+    pub tasks: Vec<TaskResult>,
+}
+
+impl BuildReport {
+    fn new(
+        build_id: String,
+        tasks: Vec<TaskResult>,
+        failures: Vec<TaskFailure>,
+        stages: Vec<StageTiming>,
+        determinism: Option<DeterminismReport>,
+        duration_ms: f64,
+        was_cancelled: bool,
+    ) -> Self {
+        let count = |status| tasks.iter().filter(|task| task.status == status).count() as u32;
+        let (succeeded, failed, skipped, cancelled) = (
+            count(TaskStatus::Succeeded),
+            count(TaskStatus::Failed),
+            count(TaskStatus::Skipped),
+            count(TaskStatus::Cancelled),
+        );
+        let queued = || tasks.iter().filter_map(|task| task.queued_ms);
+        let (total_queued_ms, max_queued_ms) = (queued().sum(), queued().fold(0.0, f64::max));
+        let status = if was_cancelled {
+            BuildStatus::Cancelled
+        } else if failed > 0 {
+            BuildStatus::Failed
+        } else if determinism.as_ref().is_some_and(|report| !report.deterministic) {
+            BuildStatus::Nondeterministic
+        } else {
+            BuildStatus::Succeeded
+        };
+        Self {
+            build_id,
+            status,
+            duration_ms,
+            succeeded,
+            failed,
+            skipped,
+            cancelled,
+            total_queued_ms,
+            max_queued_ms,
+            failures,
+            error: None,
+            stages,
+            determinism,
+            tasks,
+        }
+    }
+
+    /// Mark the build failed for a reason other than its tasks
+    fn fail(&mut self, error: String) {
+        self.status = BuildStatus::Failed;
+        self.error = Some(error);
+    }
+}
+
+fn elapsed_ms(since: Instant) -> f64 {
+    since.elapsed().as_secs_f64() * 1000.0
+}
+
+/// Tasks whose dependencies are done, highest priority first. Ties go to the
+/// task submitted first, so runs are reproducible.
+struct ReadyQueue {
+    heap: BinaryHeap<(i32, Reverse<usize>)>,
+    priorities: Vec<i32>,
+    /// When each task became ready, in ms since the build started
+    ready_ms: Vec<Option<f64>>,
+}
+
+impl ReadyQueue {
+    fn new(priorities: Vec<i32>) -> Self {
+        let ready_ms = vec![None; priorities.len()];
+        Self {
+            heap: BinaryHeap::new(),
+            priorities,
+            ready_ms,
+        }
+    }
+
+    fn push(&mut self, i: usize, now_ms: f64) {
+        self.ready_ms[i] = Some(now_ms);
+        self.requeue(i);
+    }
+
+    /// Put back a task that was popped but could not start yet
+    fn requeue(&mut self, i: usize) {
+        self.heap.push((self.priorities[i], Reverse(i)));
+    }
+
+    fn pop(&mut self) -> Option<usize> {
+        self.heap.pop().map(|(_, Reverse(i))| i)
+    }
+
+    /// Raise `task` and everything it depends on to at least `priority`
+    fn raise(&mut self, task: usize, priority: i32, dependencies: &[Vec<usize>]) {
+        let mut visited = vec![false; self.priorities.len()];
+        let mut stack = vec![task];
+        while let Some(i) = stack.pop() {
+            if std::mem::replace(&mut visited[i], true) {
+                continue;
+            }
+            self.priorities[i] = self.priorities[i].max(priority);
+            stack.extend(&dependencies[i]);
+        }
+        let queued: Vec<usize> = self.heap.drain().map(|(_, Reverse(i))| i).collect();
+        for i in queued {
+            self.requeue(i);
+        }
+    }
+}
+
+/// Edges of a task graph, as indices into its tasks
+struct Links {
+    dependencies: Vec<Vec<usize>>,
+    dependents: Vec<Vec<usize>>,
+    /// Every task after its dependencies, earliest submitted first among
+    /// tasks that could go next
+    order: Vec<usize>,
+}
+
+/// Check that ids are unique, dependencies exist and there are no cycles
+fn link(tasks: &[Task]) -> std::result::Result<Links, String> {
+    let mut index = HashMap::with_capacity(tasks.len());
+    for (i, task) in tasks.iter().enumerate() {
+        if index.insert(task.id.as_str(), i).is_some() {
+            return Err(format!("Duplicate task id {:?}", task.id));
+        }
+    }
+
+    let mut dependencies = vec![Vec::new(); tasks.len()];
+    let mut dependents = vec![Vec::new(); tasks.len()];
+    for (i, task) in tasks.iter().enumerate() {
+        for dependency in &task.dependencies {
+            let &d = index
+                .get(dependency.as_str())
+                .ok_or_else(|| format!("Task {:?} depends on unknown task {:?}", task.id, dependency))?;
+            if tasks[d].stage > task.stage {
+                return Err(format!(
+                    "Task {:?} in stage {:?} depends on task {:?} in later stage {:?}",
+                    task.id, task.stage, dependency, tasks[d].stage
+                ));
+            }
+            dependencies[i].push(d);
+            dependents[d].push(i);
+        }
+    }
+
+    // Kahn's algorithm: whatever never becomes ready is on a cycle
+    let mut remaining: Vec<usize> = dependencies.iter().map(Vec::len).collect();
+    let mut ready: BinaryHeap<Reverse<usize>> = (0..tasks.len()).filter(|&i| remaining[i] == 0).map(Reverse).collect();
+    let mut order = Vec::with_capacity(tasks.len());
+    while let Some(Reverse(i)) = ready.pop() {
+        order.push(i);
+        for &dependent in &dependents[i] {
+            remaining[dependent] -= 1;
+            if remaining[dependent] == 0 {
+                ready.push(Reverse(dependent));
+            }
+        }
+    }
+    if order.len() < tasks.len() {
+        let cycle: Vec<&str> = (0..tasks.len())
+            .filter(|&i| remaining[i] > 0)
+            .map(|i| tasks[i].id.as_str())
+            .collect();
+        return Err(format!("Tasks form a dependency cycle: {}", cycle.join(", ")));
+    }
+
+    Ok(Links {
+        dependencies,
+        dependents,
+        order,
+    })
+}
+
+/// When one attempt at a task ran, and on which thread it started
+struct AttemptSpan {
+    start: Instant,
+    end: Instant,
+    thread: String,
+}
+
+/// Outcome of a task after any retries
+pub(crate) struct Attempts {
+    pub outcome: TaskOutcome,
+    /// Error of each failed attempt
+    errors: Vec<String>,
+    spans: Vec<AttemptSpan>,
+}
+
+/// A task that has returned
+struct Finished {
+    index: usize,
+    /// Worker slot it ran in
+    slot: usize,
+    attempts: Attempts,
+    start_ms: f64,
+    duration_ms: f64,
+}
+
+/// Run `task` until it succeeds, is cancelled or has no retries left. A
+/// cancel that lands while waiting to retry ends it as cancelled, not failed.
+pub(crate) async fn run_with_retries(run: TaskFn, context: TaskContext, retry: Retry) -> Attempts {
+    let mut errors = Vec::new();
+    let mut spans = Vec::new();
+    loop {
+        let attempt = TaskContext {
+            attempt: errors.len() as u32 + 1,
+            ..context.clone()
+        };
+        let start = Instant::now();
+        // A nested task so a panic fails this attempt instead of the build
+        let run = run.clone();
+        let (outcome, thread) = tokio::spawn(async move {
+            let thread = std::thread::current().name().unwrap_or_default().to_string();
+            (run(attempt).await, thread)
+        })
+        .await
+        .unwrap_or_else(|e| (Err(TaskError::Failed(format!("Task panicked: {}", e))), String::new()));
+        spans.push(AttemptSpan {
+            start,
+            end: Instant::now(),
+            thread,
+        });
+
+        let error = match outcome {
+            Err(TaskError::Failed(error)) => error,
+            outcome => return Attempts { outcome, errors, spans },
+        };
+        errors.push(error.clone());
+
+        let retries = errors.len() as u32;
+        if retries > retry.retries {
+            return Attempts {
+                outcome: Err(TaskError::Failed(error)),
+                errors,
+                spans,
+            };
+        }
+        let cancelled = |errors, spans| Attempts {
+            outcome: Err(TaskError::Cancelled),
+            errors,
+            spans,
+        };
+        if context.cancel.is_cancelled() {
+            return cancelled(errors, spans);
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(retry.delay(retries)) => {}
+            _ = context.cancel.cancelled() => return cancelled(errors, spans),
+        }
+    }
+}
+
+/// How a task ended in the build itself, for the determinism check
+fn run_outcome(result: &TaskResult, prefix: &str) -> RunOutcome {
+    match (result.status, &result.output) {
+        (TaskStatus::Succeeded, Some(output)) => RunOutcome::Produced(determinism::stable_id(output, prefix)),
+        (TaskStatus::Failed, _) => RunOutcome::Failed,
+        _ => RunOutcome::NotRun,
+    }
+}
+
+/// Stop a build whose stage hook failed, keeping the first error
+fn stop_at_hook(hook_error: &mut Option<String>, cancel: &CancelToken, error: String) {
+    hook_error.get_or_insert(error);
+    cancel.cancel();
+}
+
+/// Run `tasks` stage by stage and in dependency order within `limits` until
+/// done or cancelled, rehearsing the Plan and Execute stages first if
+/// `check_determinism` is set. Fails only if the graph is invalid; a stage
+/// hook that fails stops the build, which still reaches Emit and reports
+/// Failed. Must be called from within a Tokio runtime; tasks are spawned onto it.
+pub(crate) async fn execute(
+    build_id: String,
+    tasks: Vec<Task>,
+    limits: Limits,
+    failure_mode: FailureMode,
+    check_determinism: bool,
+    controls: Controls,
+    observers: Observers,
+) -> std::result::Result<BuildReport, String> {
+    let Controls { cancel, mut raise } = controls;
+    let Observers {
+        hooks,
+        events,
+        timeline,
+    } = observers;
+    let Links {
+        dependencies,
+        dependents,
+        order,
+    } = link(&tasks).map_err(|e| format!("Invalid task graph: {}", e))?;
+    let started = Instant::now();
+    let mut stages = StageMachine::new(build_id.clone(), hooks, events.clone(), timeline.clone(), started);
+    let has_determinism_check =
+        check_determinism || tasks.iter().any(|task| task.stage == BuildStage::DeterminismCheck);
+
+    let ids: Vec<String> = tasks.iter().map(|task| task.id.clone()).collect();
+    let task_stages: Vec<BuildStage> = tasks.iter().map(|task| task.stage).collect();
+    let lanes: Vec<Lane> = tasks.iter().map(|task| task.lane).collect();
+    // Stable ids of a task's output start with its module, or its id
+    let prefixes: Vec<String> = tasks
+        .iter()
+        .map(|task| task.module.clone().unwrap_or_else(|| task.id.clone()))
+        .collect();
+    let modules: Vec<Option<String>> = tasks.iter().map(|task| task.module.clone()).collect();
+    let mut ready = ReadyQueue::new(tasks.iter().map(|task| task.priority).collect());
+    let runs: Vec<(TaskFn, Retry)> = tasks.into_iter().map(|task| (task.run, task.retry)).collect();
+    let mut results: Vec<Option<TaskResult>> = vec![None; ids.len()];
+    let mut errors: Vec<Vec<String>> = vec![Vec::new(); ids.len()];
+    // The failed task each skipped task was skipped because of
+    let mut blame: Vec<Option<usize>> = vec![None; ids.len()];
+    // The failure a fail-fast build stopped at
+    let mut stopped_at = None;
+    // The stage hook failure the build stopped at
+    let mut hook_error = None;
+    let mut remaining: Vec<usize> = dependencies.iter().map(Vec::len).collect();
+
+    for i in (0..ids.len()).filter(|&i| remaining[i] == 0) {
+        ready.push(i, 0.0);
+    }
+    let mut running = JoinSet::new();
+    // Lowest free slot first, so the timeline uses as few tracks as it can
+    let mut free_slots: BinaryHeap<Reverse<usize>> = (0..limits.total).map(Reverse).collect();
+    let mut running_js = 0;
+    // Ready JS tasks held back by the JS limit
+    let mut deferred = Vec::new();
+    // Ready tasks of stages not yet entered
+    let mut later = Vec::new();
+    // How each task ended in the rehearsal, once it has run
+    let mut rehearsal: Option<Vec<RunOutcome>> = None;
+    if let Err(e) = stages.enter(BuildStage::Init).await {
+        stop_at_hook(&mut hook_error, &cancel, e);
+    }
+
+    loop {
+        while running.len() < limits.total && !cancel.is_cancelled() {
+            let Some(i) = ready.pop() else {
+                break;
+            };
+            if Some(task_stages[i]) > stages.current() {
+                later.push(i);
+                continue;
+            }
+            if lanes[i] == Lane::Js && running_js >= limits.js {
+                deferred.push(i);
+                continue;
+            }
+
+            let failed_dependency = dependencies[i].iter().find(|&&d| {
+                results[d].as_ref().map(|result| result.status) != Some(TaskStatus::Succeeded)
+            });
+            if let Some(&d) = failed_dependency {
+                blame[i] = blame[d].or(Some(d));
+                results[i] = Some(TaskResult {
+                    id: ids[i].clone(),
+                    stage: task_stages[i],
+                    status: TaskStatus::Skipped,
+                    output: None,
+                    error: Some(format!("Dependency {:?} did not succeed", ids[d])),
+                    start_ms: None,
+                    queued_ms: None,
+                    duration_ms: 0.0,
+                    attempts: 0,
+                });
+                let now_ms = elapsed_ms(started);
+                for &dependent in &dependents[i] {
+                    remaining[dependent] -= 1;
+                    if remaining[dependent] == 0 {
+                        ready.push(dependent, now_ms);
+                    }
+                }
+                continue;
+            }
+
+            let context = TaskContext {
+                id: ids[i].clone(),
+                attempt: 1,
+                inputs: dependencies[i]
+                    .iter()
+                    .map(|&d| {
+                        let output = results[d].as_ref().and_then(|result| result.output.clone());
+                        (ids[d].clone(), output.unwrap_or_default())
+                    })
+                    .collect(),
+                cancel: cancel.clone(),
+            };
+            let (run, retry) = runs[i].clone();
+            if lanes[i] == Lane::Js {
+                running_js += 1;
+            }
+            let Reverse(slot) = free_slots.pop().expect("a slot is free while below the limit");
+            let start_ms = elapsed_ms(started);
+            running.spawn(async move {
+                let task_started = Instant::now();
+                let attempts = run_with_retries(run, context, retry).await;
+                Finished {
+                    index: i,
+                    slot,
+                    attempts,
+                    start_ms,
+                    duration_ms: elapsed_ms(task_started),
+                }
+            });
+        }
+
+        if running.is_empty() {
+            // Everything this stage can run has finished
+            if check_determinism
+                && stages.current() == Some(BuildStage::DeterminismCheck)
+                && rehearsal.is_none()
+                && !cancel.is_cancelled()
+            {
+                let rehearsed: Vec<usize> = (0..ids.len())
+                    .filter(|&i| matches!(task_stages[i], BuildStage::Plan | BuildStage::Execute))
+                    .collect();
+                let mut position = vec![None; ids.len()];
+                for (p, &i) in rehearsed.iter().enumerate() {
+                    position[i] = Some(p);
+                }
+                let tasks: Vec<RehearsedTask> = rehearsed
+                    .iter()
+                    .map(|&i| RehearsedTask {
+                        id: ids[i].clone(),
+                        prefix: prefixes[i].clone(),
+                        stage: task_stages[i],
+                        lane: lanes[i],
+                        priority: ready.priorities[i],
+                        retry: runs[i].1,
+                        run: runs[i].0.clone(),
+                        inputs: dependencies[i]
+                            .iter()
+                            .map(|&d| match position[d] {
+                                Some(p) => Input::Rehearsed(p),
+                                None => Input::Fixed {
+                                    id: ids[d].clone(),
+                                    output: results[d]
+                                        .as_ref()
+                                        .filter(|result| result.status == TaskStatus::Succeeded)
+                                        .and_then(|result| result.output.clone()),
+                                },
+                            })
+                            .collect(),
+                    })
+                    .collect();
+                let outcomes = determinism::rehearse(&tasks, limits, &cancel).await;
+                let mut by_task = vec![RunOutcome::NotRun; ids.len()];
+                for (i, outcome) in rehearsed.into_iter().zip(outcomes) {
+                    by_task[i] = outcome;
+                }
+                rehearsal = Some(by_task);
+            }
+            // A cancel that lands after every task finished has nothing left to stop
+            let finished = results.iter().all(Option::is_some);
+            match stages.upcoming(has_determinism_check) {
+                Some(next) if hook_error.is_none() && (!cancel.is_cancelled() || finished) => {
+                    if let Err(e) = stages.enter(next).await {
+                        stop_at_hook(&mut hook_error, &cancel, e);
+                    }
+                    for i in later.drain(..) {
+                        ready.requeue(i);
+                    }
+                    continue;
+                }
+                _ => break,
+            }
+        }
+
+        let joined = tokio::select! {
+            Some((task, priority)) = raise.recv() => {
+                ready.raise(task, priority, &dependencies);
+                continue;
+            }
+            joined = running.join_next() => joined,
+        };
+        let Some(joined) = joined else {
+            break;
+        };
+        let Finished {
+            index: i,
+            slot,
+            attempts:
+                Attempts {
+                    outcome,
+                    errors: task_errors,
+                    spans,
+                },
+            start_ms,
+            duration_ms,
+        } = joined.expect("scheduler tasks neither panic nor get aborted");
+        free_slots.push(Reverse(slot));
+        if lanes[i] == Lane::Js {
+            running_js -= 1;
+        }
+        for i in deferred.drain(..) {
+            ready.requeue(i);
+        }
+        let attempts = spans.len() as u32;
+        errors[i] = task_errors;
+        let (status, output, error) = match outcome {
+            Ok(output) => (TaskStatus::Succeeded, Some(output), None),
+            Err(TaskError::Failed(error)) => (TaskStatus::Failed, None, Some(error)),
+            Err(TaskError::Cancelled) => (TaskStatus::Cancelled, None, Some("Build was cancelled".to_string())),
+        };
+        if timeline.is_enabled() {
+            for (attempt, span) in spans.into_iter().enumerate() {
+                // Every attempt with an error failed; only the last can have none
+                let (status, error) = match errors[i].get(attempt) {
+                    Some(attempt_error) => (TaskStatus::Failed, Some(attempt_error)),
+                    None => (status, error.as_ref()),
+                };
+                timeline.record(
+                    Track::Worker(build_id.clone(), slot),
+                    "task",
+                    ids[i].clone(),
+                    span.start,
+                    Some(span.end),
+                    serde_json::json!({
+                        "build": build_id,
+                        "stage": task_stages[i],
+                        "lane": format!("{:?}", lanes[i]),
+                        "attempt": attempt + 1,
+                        "thread": span.thread,
+                        "status": status,
+                        "error": error,
+                    }),
+                );
+            }
+        }
+        if let Some(error) = error.as_ref().filter(|_| status == TaskStatus::Failed) {
+            events.emit(
+                task_stages[i],
+                EventLevel::Warn,
+                format!("Task {} failed after {} attempt(s): {}", ids[i], attempts, error),
+                Some(duration_ms),
+            );
+        }
+        if status == TaskStatus::Failed && failure_mode == FailureMode::FailFast && stopped_at.is_none() {
+            stopped_at = Some(i);
+            cancel.cancel();
+        }
+        results[i] = Some(TaskResult {
+            id: ids[i].clone(),
+            stage: task_stages[i],
+            status,
+            output,
+            error,
+            start_ms: Some(start_ms),
+            queued_ms: ready.ready_ms[i].map(|ready_ms| start_ms - ready_ms),
+            duration_ms,
+            attempts,
+        });
+        let now_ms = elapsed_ms(started);
+        for &dependent in &dependents[i] {
+            remaining[dependent] -= 1;
+            if remaining[dependent] == 0 {
+                ready.push(dependent, now_ms);
+            }
+        }
+    }
+
+    // Only a stopped build leaves tasks that never started
+    let results: Vec<TaskResult> = results
+        .into_iter()
+        .enumerate()
+        .map(|(i, result)| {
+            result.unwrap_or_else(|| {
+                let (status, error) = match (stopped_at, &hook_error) {
+                    (Some(failed), _) => {
+                        blame[i] = Some(failed);
+                        (TaskStatus::Skipped, format!("Build stopped after {:?} failed", ids[failed]))
+                    }
+                    (None, Some(hook_error)) => (TaskStatus::Skipped, format!("Build stopped: {}", hook_error)),
+                    (None, None) => (TaskStatus::Cancelled, "Build was cancelled".to_string()),
+                };
+                TaskResult {
+                    id: ids[i].clone(),
+                    stage: task_stages[i],
+                    status,
+                    output: None,
+                    error: Some(error),
+                    start_ms: None,
+                    queued_ms: None,
+                    duration_ms: 0.0,
+                    attempts: 0,
+                }
+            })
+        })
+        .collect();
+
+    let failures = results
+        .iter()
+        .enumerate()
+        .filter(|(_, result)| result.status == TaskStatus::Failed)
+        .map(|(i, result)| TaskFailure {
+            id: result.id.clone(),
+            errors: std::mem::take(&mut errors[i]),
+            skipped: (0..ids.len())
+                .filter(|&j| blame[j] == Some(i))
+                .map(|j| ids[j].clone())
+                .collect(),
+        })
+        .collect();
+    if hook_error.is_some() {
+        if let Err(e) = stages.skip_to_emit().await {
+            stop_at_hook(&mut hook_error, &cancel, e);
+        }
+    }
+    if let Err(e) = stages.leave().await {
+        stop_at_hook(&mut hook_error, &cancel, e);
+    }
+
+    // Plan tasks ran for real before the rehearsal, Execute tasks after it
+    let determinism = rehearsal.map(|mut rehearsal| {
+        let report = determinism::report(order.iter().map(|&i| {
+            let real = run_outcome(&results[i], &prefixes[i]);
+            let rehearsed = std::mem::replace(&mut rehearsal[i], RunOutcome::NotRun);
+            let (first, second) = match task_stages[i] {
+                BuildStage::Plan => (real, rehearsed),
+                _ => (rehearsed, real),
+            };
+            Comparison {
+                task_id: ids[i].clone(),
+                module: modules[i].clone(),
+                stage: task_stages[i],
+                first,
+                second,
+            }
+        }));
+        match &report.first_divergence {
+            Some(mismatch) => events.emit(
+                BuildStage::DeterminismCheck,
+                EventLevel::Error,
+                format!(
+                    "Build {} is not deterministic: {} task(s) differed between runs, first {} (module {})",
+                    build_id,
+                    report.mismatches.len(),
+                    mismatch.task_id,
+                    mismatch.module.as_deref().unwrap_or("unknown"),
+                ),
+                None,
+            ),
+            None => events.emit(
+                BuildStage::DeterminismCheck,
+                EventLevel::Info,
+                format!("Build {} is deterministic: {} task(s) matched", build_id, report.checked),
+                None,
+            ),
+        }
+        report
+    });
+    let was_cancelled = cancel.is_cancelled()
+        && stopped_at.is_none()
+        && hook_error.is_none()
+        && results.iter().any(|result| result.status == TaskStatus::Cancelled);
+    let mut report = BuildReport::new(
+        build_id,
+        results,
+        failures,
+        stages.into_timings(),
+        determinism,
+        elapsed_ms(started),
+        was_cancelled,
+    );
+    if let Some(error) = hook_error {
+        report.fail(error);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Mutex;
+
+    fn task(id: &str, dependencies: &[&str], run: TaskFn) -> Task {
+        Task {
+            id: id.to_string(),
+            dependencies: dependencies.iter().map(|d| d.to_string()).collect(),
+            stage: BuildStage::Execute,
+            lane: Lane::Native,
+            module: None,
+            priority: 0,
+            retry: Retry::NONE,
+            run,
+        }
+    }
+
+    fn succeed() -> TaskFn {
+        Arc::new(|context| Box::pin(async move { Ok(context.id) }))
+    }
+
+    fn fail() -> TaskFn {
+        Arc::new(|context| Box::pin(async move { Err(TaskError::Failed(format!("{} broke", context.id))) }))
+    }
+
+    fn sleep_then_succeed(ms: u64) -> TaskFn {
+        Arc::new(move |context| {
+            Box::pin(async move {
+                tokio::time::sleep(Duration::from_millis(ms)).await;
+                Ok(context.id)
+            })
+        })
+    }
+
+    async fn run(tasks: Vec<Task>, limits: Limits, failure_mode: FailureMode) -> BuildReport {
+        run_checked(tasks, limits, failure_mode, false).await
+    }
+
+    async fn run_checked(tasks: Vec<Task>, limits: Limits, failure_mode: FailureMode, check: bool) -> BuildReport {
+        let (_raise, raise) = mpsc::unbounded_channel();
+        let controls = Controls {
+            cancel: Arc::default(),
+            raise,
+        };
+        let observers = Observers {
+            hooks: StageHooks::default(),
+            events: Arc::new(EventLog::new(100)),
+            timeline: Arc::new(Timeline::new()),
+        };
+        execute("build-1".to_string(), tasks, limits, failure_mode, check, controls, observers)
+            .await
+            .unwrap()
+    }
+
+    const LIMITS: Limits = Limits { total: 4, js: 4 };
+
+    fn statuses(report: &BuildReport) -> Vec<(&str, TaskStatus)> {
+        report.tasks.iter().map(|task| (task.id.as_str(), task.status)).collect()
+    }
+
+    #[test]
+    fn rejects_cycles_unknown_dependencies_and_later_stages() {
+        let error = |tasks: Vec<Task>| link(&tasks).err().unwrap();
+
+        let cycle = error(vec![
+            task("a", &["c"], succeed()),
+            task("b", &["a"], succeed()),
+            task("c", &["b"], succeed()),
+            task("d", &[], succeed()),
+        ]);
+        assert_eq!(cycle, "Tasks form a dependency cycle: a, b, c");
+
+        let unknown = error(vec![task("a", &["missing"], succeed())]);
+        assert_eq!(unknown, "Task \"a\" depends on unknown task \"missing\"");
+
+        let duplicate = error(vec![task("a", &[], succeed()), task("a", &[], succeed())]);
+        assert_eq!(duplicate, "Duplicate task id \"a\"");
+
+        let mut plan = task("plan", &["execute"], succeed());
+        plan.stage = BuildStage::Plan;
+        let later = error(vec![task("execute", &[], succeed()), plan]);
+        assert!(later.contains("depends on task \"execute\" in later stage Execute"), "{}", later);
+    }
+
+    #[tokio::test]
+    async fn skips_everything_downstream_of_a_failure() {
+        let report = run(
+            vec![
+                task("a", &[], fail()),
+                task("b", &["a"], succeed()),
+                task("c", &["b"], succeed()),
+                task("d", &[], succeed()),
+            ],
+            LIMITS,
+            FailureMode::ContinueOnError,
+        )
+        .await;
+
+        assert_eq!(report.status, BuildStatus::Failed);
+        assert_eq!(
+            statuses(&report),
+            [
+                ("a", TaskStatus::Failed),
+                ("b", TaskStatus::Skipped),
+                ("c", TaskStatus::Skipped),
+                ("d", TaskStatus::Succeeded),
+            ]
+        );
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(report.failures[0].errors, ["a broke"]);
+        assert_eq!(report.failures[0].skipped, ["b", "c"]);
+        assert_eq!(report.tasks[2].error.as_deref(), Some("Dependency \"b\" did not succeed"));
+    }
+
+    #[tokio::test]
+    async fn fail_fast_blames_the_first_failure_for_tasks_it_never_started() {
+        let report = run(
+            vec![
+                task("a", &[], fail()),
+                task("b", &[], succeed()),
+                task("c", &["b"], succeed()),
+            ],
+            Limits { total: 1, js: 1 },
+            FailureMode::FailFast,
+        )
+        .await;
+
+        assert_eq!(report.status, BuildStatus::Failed);
+        assert_eq!(
+            statuses(&report),
+            [("a", TaskStatus::Failed), ("b", TaskStatus::Skipped), ("c", TaskStatus::Skipped)]
+        );
+        assert_eq!(report.failures[0].skipped, ["b", "c"]);
+        assert_eq!(report.tasks[1].error.as_deref(), Some("Build stopped after \"a\" failed"));
+    }
+
+    #[tokio::test]
+    async fn caps_js_tasks_below_the_overall_limit() {
+        let running = Arc::new(AtomicUsize::new(0));
+        let most = Arc::new(AtomicUsize::new(0));
+        let tracked: TaskFn = {
+            let (running, most) = (running.clone(), most.clone());
+            Arc::new(move |context| {
+                let (running, most) = (running.clone(), most.clone());
+                Box::pin(async move {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    most.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    Ok(context.id)
+                })
+            })
+        };
+        let tasks = (0..6)
+            .map(|i| Task {
+                lane: Lane::Js,
+                ..task(&format!("js{}", i), &[], tracked.clone())
+            })
+            .collect();
+
+        let report = run(tasks, Limits { total: 4, js: 2 }, FailureMode::FailFast).await;
+        assert_eq!(report.succeeded, 6);
+        assert_eq!(most.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn raising_a_task_raises_its_queued_dependencies() {
+        // 2 depends on 1, which depends on 0; 3 is unrelated
+        let dependencies = vec![vec![], vec![0], vec![1], vec![]];
+        let mut ready = ReadyQueue::new(vec![0, 0, 0, 5]);
+        ready.push(0, 0.0);
+        ready.push(3, 0.0);
+
+        ready.raise(2, 10, &dependencies);
+        assert_eq!(ready.priorities, [10, 10, 10, 5]);
+        assert_eq!(ready.pop(), Some(0));
+        assert_eq!(ready.pop(), Some(3));
+        assert_eq!(ready.pop(), None);
+
+        // Raising never lowers a priority
+        ready.raise(3, 1, &dependencies);
+        assert_eq!(ready.priorities[3], 5);
+    }
+
+    #[test]
+    fn backoff_grows_by_its_factor_up_to_the_limit() {
+        let retry = Retry {
+            retries: 5,
+            backoff_ms: 100.0,
+            factor: 2.0,
+            max_backoff_ms: 300.0,
+        };
+        let delays: Vec<u128> = (1..=4).map(|retry_number| retry.delay(retry_number).as_millis()).collect();
+        assert_eq!(delays, [100, 200, 300, 300]);
+    }
+
+    #[tokio::test]
+    async fn retries_until_success_and_counts_attempts() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let flaky: TaskFn = {
+            let seen = seen.clone();
+            Arc::new(move |context| {
+                seen.lock().unwrap().push(context.attempt);
+                Box::pin(async move {
+                    match context.attempt {
+                        1 | 2 => Err(TaskError::Failed(format!("attempt {}", context.attempt))),
+                        _ => Ok("done".to_string()),
+                    }
+                })
+            })
+        };
+        let retry = Retry {
+            retries: 3,
+            backoff_ms: 10.0,
+            factor: 2.0,
+            max_backoff_ms: 15.0,
+        };
+        let report = run(
+            vec![
+                Task { retry, ..task("flaky", &[], flaky) },
+                Task { retry: Retry { retries: 1, ..retry }, ..task("broken", &[], fail()) },
+            ],
+            LIMITS,
+            FailureMode::ContinueOnError,
+        )
+        .await;
+
+        assert_eq!(*seen.lock().unwrap(), [1, 2, 3]);
+        let flaky = &report.tasks[0];
+        assert_eq!((flaky.status, flaky.attempts), (TaskStatus::Succeeded, 3));
+        // Waited 10ms, then 15ms rather than 20ms
+        assert!(flaky.duration_ms >= 25.0, "{}", flaky.duration_ms);
+
+        let broken = &report.tasks[1];
+        assert_eq!((broken.status, broken.attempts), (TaskStatus::Failed, 2));
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(report.failures[0].errors, ["broken broke", "broken broke"]);
+    }
+
+    /// Cancels the build from inside a task once `ms` have passed
+    fn cancel_after(ms: u64) -> TaskFn {
+        Arc::new(move |context| {
+            Box::pin(async move {
+                tokio::time::sleep(Duration::from_millis(ms)).await;
+                context.cancel.cancel();
+                Ok(context.id)
+            })
+        })
+    }
+
+    #[tokio::test]
+    async fn a_cancel_during_backoff_cancels_the_task() {
+        let retry = Retry {
+            retries: 3,
+            backoff_ms: 10_000.0,
+            factor: 1.0,
+            max_backoff_ms: 10_000.0,
+        };
+        let started = Instant::now();
+        let report = run(
+            vec![Task { retry, ..task("flaky", &[], fail()) }, task("cancel", &[], cancel_after(20))],
+            LIMITS,
+            FailureMode::ContinueOnError,
+        )
+        .await;
+
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(report.status, BuildStatus::Cancelled);
+        let flaky = &report.tasks[0];
+        assert_eq!((flaky.status, flaky.attempts), (TaskStatus::Cancelled, 1));
+        assert!(report.failures.is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_cancel_after_the_last_task_changes_nothing() {
+        let report = run(
+            vec![task("a", &[], succeed()), task("last", &["a"], cancel_after(0))],
+            LIMITS,
+            FailureMode::ContinueOnError,
+        )
+        .await;
+
+        assert_eq!(report.status, BuildStatus::Succeeded);
+        assert_eq!(report.stages.last().map(|timing| timing.stage), Some(BuildStage::Emit));
+    }
+
+    #[tokio::test]
+    async fn runs_stages_in_order_and_waits_for_earlier_ones() {
+        let mut graph = task("graph", &[], sleep_then_succeed(20));
+        graph.stage = BuildStage::Graph;
+        let mut plan = task("plan", &[], sleep_then_succeed(20));
+        plan.stage = BuildStage::Plan;
+        let execute_task = task("execute", &[], succeed());
+
+        // Submitted last-stage first, to show submission order does not matter
+        let report = run(vec![execute_task, plan, graph], LIMITS, FailureMode::FailFast).await;
+        assert_eq!(report.status, BuildStatus::Succeeded);
+
+        let stages: Vec<BuildStage> = report.stages.iter().map(|timing| timing.stage).collect();
+        assert_eq!(
+            stages,
+            [BuildStage::Init, BuildStage::Graph, BuildStage::Plan, BuildStage::Execute, BuildStage::Emit]
+        );
+        let ends = |task: &TaskResult| task.start_ms.unwrap() + task.duration_ms;
+        let (execute_task, plan, graph) = (&report.tasks[0], &report.tasks[1], &report.tasks[2]);
+        assert!(plan.start_ms.unwrap() >= ends(graph));
+        assert!(execute_task.start_ms.unwrap() >= ends(plan));
+    }
+
+    #[tokio::test]
+    async fn the_rehearsal_finds_output_that_depends_on_run_order() {
+        // Each pair of runs tells its first task apart from its second
+        let runs = Arc::new(AtomicUsize::new(0));
+        let order_dependent = || -> TaskFn {
+            let runs = runs.clone();
+            Arc::new(move |_| {
+                let first = runs.fetch_add(1, Ordering::SeqCst) % 2 == 0;
+                Box::pin(async move { Ok(if first { "ran first" } else { "ran second" }.to_string()) })
+            })
+        };
+        let mut tasks = vec![
+            task("first", &[], order_dependent()),
+            task("second", &[], order_dependent()),
+            task("steady", &[], succeed()),
+        ];
+        tasks[0].module = Some("src/first.ts".to_string());
+        tasks[1].module = Some("src/second.ts".to_string());
+
+        let report = run_checked(tasks, Limits { total: 1, js: 1 }, FailureMode::ContinueOnError, true).await;
+
+        assert_eq!(report.status, BuildStatus::Nondeterministic);
+        let determinism = report.determinism.unwrap();
+        assert!(!determinism.deterministic);
+        assert_eq!(determinism.checked, 3);
+        let divergence = determinism.first_divergence.unwrap();
+        assert_eq!(divergence.task_id, "first");
+        assert_eq!(divergence.module.as_deref(), Some("src/first.ts"));
+        assert_eq!(divergence.stage, BuildStage::Execute);
+        assert_ne!(divergence.first, divergence.second);
+        let mismatched: Vec<&str> = determinism.mismatches.iter().map(|m| m.task_id.as_str()).collect();
+        assert_eq!(mismatched, ["first", "second"]);
+    }
+}
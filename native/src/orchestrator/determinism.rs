@@ -0,0 +1,236 @@
+// Determinism verification for Nexxo v2.0
+//
+// With `checkDeterminism` on, a build enters its DeterminismCheck stage and,
+// once that stage's own tasks are done, rehearses the Plan and Execute
+// stages: every task of those stages runs a second time, Plan tasks after
+// their real run and Execute tasks before it. The rehearsal starts ready
+// tasks in the opposite order to the scheduler, lowest priority and last
+// submitted first, so output that depends on the order tasks run in shows
+// up as a difference.
+//
+// Outputs are compared by their stable ids (see `generateStableId`), keyed
+// by the task's module. Mismatches are listed in dependency order, so the
+// first one is where the builds diverged; the ones after it may only differ
+// because they build on it. Tasks of other stages are not run again: Emit
+// tasks, which write the results out, run once as usual, and rehearsed tasks
+// get the outputs earlier stages produced in the real run.
+//
+// The rehearsal is invisible to everything but the report: `prioritize`
+// only reorders the real run, and rehearsed tasks record no timeline spans,
+// so in a trace the rehearsal shows up as time spent in DeterminismCheck.
+
+use napi_derive::napi;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::sync::Arc;
+use tokio::task::JoinSet;
+use xxhash_rust::xxh3::xxh3_64;
+
+use super::dag::{run_with_retries, CancelToken, Lane, Limits, Retry, TaskContext, TaskError, TaskFn};
+use super::stage::BuildStage;
+
+/// `{prefix}:{xxh3 of content}`, the same for the same content on every machine
+pub(crate) fn stable_id(content: &str, prefix: &str) -> String {
+    format!("{}:{:016x}", prefix, xxh3_64(content.as_bytes()))
+}
+
+/// A task whose output differed between the two runs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[napi(object)]
+pub struct DeterminismMismatch {
+    pub task_id: String,
+    /// `TaskSpec.module`, if the task has one
+    pub module: Option<String>,
+    pub stage: BuildStage,
+    /// Stable id of the output of the earlier run; absent if it failed
+    pub first: Option<String>,
+    /// Stable id of the output of the later run; absent if it failed
+    pub second: Option<String>,
+}
+
+/// Outcome of a build's determinism check
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[napi(object)]
+pub struct DeterminismReport {
+    /// Whether every task run twice produced the same output both times
+    pub deterministic: bool,
+    /// Tasks run in both the rehearsal and the build itself
+    pub checked: u32,
+    /// The earliest mismatch in dependency order, where the runs diverged
+    pub first_divergence: Option<DeterminismMismatch>,
+    /// Every mismatch, in dependency order
+    pub mismatches: Vec<DeterminismMismatch>,
+}
+
+/// How a task ended in one run, as far as comparing runs goes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum RunOutcome {
+    /// Not started, skipped or cancelled; nothing to compare
+    NotRun,
+    Failed,
+    /// Stable id of the output
+    Produced(String),
+}
+
+impl RunOutcome {
+    fn stable_id(&self) -> Option<String> {
+        match self {
+            RunOutcome::Produced(id) => Some(id.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Where a rehearsed task gets one of its inputs from
+pub(crate) enum Input {
+    /// A task of an earlier stage, from the real run; `None` if it produced nothing
+    Fixed { id: String, output: Option<String> },
+    /// Another rehearsed task, by position
+    Rehearsed(usize),
+}
+
+/// A Plan or Execute task to run again
+pub(crate) struct RehearsedTask {
+    pub id: String,
+    /// Stable ids of its output start with this
+    pub prefix: String,
+    pub stage: BuildStage,
+    pub lane: Lane,
+    pub priority: i32,
+    pub retry: Retry,
+    pub run: TaskFn,
+    /// In the order the dependencies were declared
+    pub inputs: Vec<Input>,
+}
+
+/// Run `tasks` stage by stage and in dependency order, within `limits`,
+/// until done or cancelled
+pub(crate) async fn rehearse(tasks: &[RehearsedTask], limits: Limits, cancel: &Arc<CancelToken>) -> Vec<RunOutcome> {
+    let mut outcomes = vec![RunOutcome::NotRun; tasks.len()];
+    let mut outputs: Vec<Option<String>> = vec![None; tasks.len()];
+    let mut dependents = vec![Vec::new(); tasks.len()];
+    let mut remaining = vec![0; tasks.len()];
+    for (i, task) in tasks.iter().enumerate() {
+        for input in &task.inputs {
+            if let Input::Rehearsed(d) = *input {
+                dependents[d].push(i);
+                remaining[i] += 1;
+            }
+        }
+    }
+
+    for stage in [BuildStage::Plan, BuildStage::Execute] {
+        // The reverse of the scheduler's order
+        let mut ready: BinaryHeap<Reverse<(i32, Reverse<usize>)>> = (0..tasks.len())
+            .filter(|&i| tasks[i].stage == stage && remaining[i] == 0)
+            .map(|i| Reverse((tasks[i].priority, Reverse(i))))
+            .collect();
+        let mut release = |i: usize, ready: &mut BinaryHeap<Reverse<(i32, Reverse<usize>)>>| {
+            for &dependent in &dependents[i] {
+                remaining[dependent] -= 1;
+                if remaining[dependent] == 0 && tasks[dependent].stage == stage {
+                    ready.push(Reverse((tasks[dependent].priority, Reverse(dependent))));
+                }
+            }
+        };
+        let mut running = JoinSet::new();
+        let mut running_js = 0;
+        let mut deferred = Vec::new();
+
+        loop {
+            while running.len() < limits.total && !cancel.is_cancelled() {
+                let Some(Reverse((_, Reverse(i)))) = ready.pop() else {
+                    break;
+                };
+                let task = &tasks[i];
+                if task.lane == Lane::Js && running_js >= limits.js {
+                    deferred.push(i);
+                    continue;
+                }
+                let inputs: Option<Vec<(String, String)>> = task
+                    .inputs
+                    .iter()
+                    .map(|input| match input {
+                        Input::Fixed { id, output } => output.clone().map(|output| (id.clone(), output)),
+                        Input::Rehearsed(d) => outputs[*d].clone().map(|output| (tasks[*d].id.clone(), output)),
+                    })
+                    .collect();
+                // Like the scheduler, skip tasks whose dependencies did not succeed
+                let Some(inputs) = inputs else {
+                    release(i, &mut ready);
+                    continue;
+                };
+
+                let context = TaskContext {
+                    id: task.id.clone(),
+                    attempt: 1,
+                    inputs,
+                    cancel: cancel.clone(),
+                };
+                if task.lane == Lane::Js {
+                    running_js += 1;
+                }
+                let (run, retry) = (task.run.clone(), task.retry);
+                running.spawn(async move { (i, run_with_retries(run, context, retry).await.outcome) });
+            }
+
+            let Some(joined) = running.join_next().await else {
+                break;
+            };
+            let (i, outcome) = joined.expect("rehearsal tasks neither panic nor get aborted");
+            if tasks[i].lane == Lane::Js {
+                running_js -= 1;
+            }
+            for d in deferred.drain(..) {
+                ready.push(Reverse((tasks[d].priority, Reverse(d))));
+            }
+            match outcome {
+                Ok(output) => {
+                    outcomes[i] = RunOutcome::Produced(stable_id(&output, &tasks[i].prefix));
+                    outputs[i] = Some(output);
+                }
+                Err(TaskError::Failed(_)) => outcomes[i] = RunOutcome::Failed,
+                Err(TaskError::Cancelled) => {}
+            }
+            release(i, &mut ready);
+        }
+    }
+    outcomes
+}
+
+/// A task run twice, with how each run ended
+pub(crate) struct Comparison {
+    pub task_id: String,
+    pub module: Option<String>,
+    pub stage: BuildStage,
+    pub first: RunOutcome,
+    pub second: RunOutcome,
+}
+
+/// Compare runs of tasks given in dependency order
+pub(crate) fn report(comparisons: impl IntoIterator<Item = Comparison>) -> DeterminismReport {
+    let mut checked = 0;
+    let mut mismatches = Vec::new();
+    for comparison in comparisons {
+        if comparison.first == RunOutcome::NotRun || comparison.second == RunOutcome::NotRun {
+            continue;
+        }
+        checked += 1;
+        if comparison.first != comparison.second {
+            mismatches.push(DeterminismMismatch {
+                task_id: comparison.task_id,
+                module: comparison.module,
+                stage: comparison.stage,
+                first: comparison.first.stable_id(),
+                second: comparison.second.stable_id(),
+            });
+        }
+    }
+    DeterminismReport {
+        deterministic: mismatches.is_empty(),
+        checked,
+        first_divergence: mismatches.first().cloned(),
+        mismatches,
+    }
+}
@@ -0,0 +1,245 @@
+// Build event history and subscriptions for Nexxo v2.0
+//
+// Events are kept in a ring buffer of fixed size, so a dev server that runs
+// for days keeps only the most recent ones. Subscribers are called with each
+// matching event as it is recorded; the calls are queued on the JS thread
+// and never wait for the subscriber to return.
+
+use napi::threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode};
+use napi::Status;
+use napi_derive::napi;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use super::stage::BuildStage;
+use super::BuildEvent;
+
+/// Events kept when no history size is given
+pub(crate) const DEFAULT_EVENT_HISTORY: u32 = 1000;
+
+/// Severity of a build event, least severe first
+#[napi(string_enum)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum EventLevel {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+/// Which events to receive or return
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[napi(object)]
+pub struct EventFilter {
+    /// Only events of these stages (default: all)
+    pub stages: Option<Vec<BuildStage>>,
+    /// Only events of this level or above (default: Debug)
+    pub min_level: Option<EventLevel>,
+}
+
+impl EventFilter {
+    fn matches(&self, event: &BuildEvent) -> bool {
+        self.stages.as_ref().is_none_or(|stages| stages.contains(&event.stage))
+            && self.min_level.is_none_or(|level| event.level >= level)
+    }
+}
+
+/// JS function receiving events. Weak, so a subscription does not keep the
+/// process alive.
+pub type EventCallback = ThreadsafeFunction<BuildEvent, (), BuildEvent, Status, false, true>;
+
+struct Subscriber {
+    id: u32,
+    filter: EventFilter,
+    deliver: Box<dyn Fn(&BuildEvent) + Send>,
+}
+
+struct LogState {
+    history: VecDeque<BuildEvent>,
+    capacity: usize,
+    subscribers: Vec<Subscriber>,
+    next_id: u32,
+}
+
+/// Recent events of an orchestrator and who to tell about new ones
+pub(crate) struct EventLog {
+    state: Mutex<LogState>,
+}
+
+impl EventLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            state: Mutex::new(LogState {
+                history: VecDeque::with_capacity(capacity),
+                capacity,
+                subscribers: Vec::new(),
+                next_id: 1,
+            }),
+        }
+    }
+
+    /// Record an event stamped with the current time
+    pub fn emit(&self, stage: BuildStage, level: EventLevel, message: String, duration_ms: Option<f64>) {
+        self.push(BuildEvent {
+            stage,
+            level,
+            message,
+            timestamp: chrono::Utc::now().timestamp_millis(),
+            duration_ms,
+            metadata: None,
+        });
+    }
+
+    pub fn push(&self, event: BuildEvent) {
+        let mut state = self.state.lock().unwrap();
+        for subscriber in state.subscribers.iter().filter(|subscriber| subscriber.filter.matches(&event)) {
+            (subscriber.deliver)(&event);
+        }
+        if state.capacity == 0 {
+            return;
+        }
+        if state.history.len() == state.capacity {
+            state.history.pop_front();
+        }
+        state.history.push_back(event);
+    }
+
+    /// Stored events matching `filter`, oldest first
+    pub fn history(&self, filter: Option<&EventFilter>) -> Vec<BuildEvent> {
+        let state = self.state.lock().unwrap();
+        state
+            .history
+            .iter()
+            .filter(|event| filter.is_none_or(|filter| filter.matches(event)))
+            .cloned()
+            .collect()
+    }
+
+    pub fn clear(&self) {
+        self.state.lock().unwrap().history.clear();
+    }
+
+    pub fn subscribe(&self, callback: EventCallback, filter: Option<EventFilter>) -> u32 {
+        self.subscribe_with(
+            move |event| {
+                callback.call(event.clone(), ThreadsafeFunctionCallMode::NonBlocking);
+            },
+            filter,
+        )
+    }
+
+    /// Call `deliver` with each matching event, while the log is locked
+    fn subscribe_with(&self, deliver: impl Fn(&BuildEvent) + Send + 'static, filter: Option<EventFilter>) -> u32 {
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.subscribers.push(Subscriber {
+            id,
+            filter: filter.unwrap_or_default(),
+            deliver: Box::new(deliver),
+        });
+        id
+    }
+
+    pub fn unsubscribe(&self, id: u32) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let before = state.subscribers.len();
+        state.subscribers.retain(|subscriber| subscriber.id != id);
+        state.subscribers.len() < before
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn event(stage: BuildStage, level: EventLevel, message: &str) -> BuildEvent {
+        BuildEvent {
+            stage,
+            level,
+            message: message.to_string(),
+            timestamp: 0,
+            duration_ms: None,
+            metadata: None,
+        }
+    }
+
+    fn messages(events: &[BuildEvent]) -> Vec<&str> {
+        events.iter().map(|event| event.message.as_str()).collect()
+    }
+
+    /// Subscribe, collecting what the subscriber is given
+    fn collect(log: &EventLog, filter: Option<EventFilter>) -> (u32, Arc<Mutex<Vec<BuildEvent>>>) {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = received.clone();
+        let id = log.subscribe_with(move |event| sink.lock().unwrap().push(event.clone()), filter);
+        (id, received)
+    }
+
+    #[test]
+    fn keeps_only_the_most_recent_events() {
+        let log = EventLog::new(3);
+        for message in ["a", "b", "c", "d", "e"] {
+            log.push(event(BuildStage::Execute, EventLevel::Info, message));
+        }
+        assert_eq!(messages(&log.history(None)), ["c", "d", "e"]);
+
+        log.clear();
+        assert!(log.history(None).is_empty());
+    }
+
+    #[test]
+    fn without_history_still_notifies_subscribers() {
+        let log = EventLog::new(0);
+        let (_, received) = collect(&log, None);
+        log.push(event(BuildStage::Init, EventLevel::Info, "started"));
+
+        assert!(log.history(None).is_empty());
+        assert_eq!(messages(&received.lock().unwrap()), ["started"]);
+    }
+
+    #[test]
+    fn filters_by_stage_and_minimum_level() {
+        let log = EventLog::new(10);
+        let graph_warnings = EventFilter {
+            stages: Some(vec![BuildStage::Graph]),
+            min_level: Some(EventLevel::Warn),
+        };
+        let (_, received) = collect(&log, Some(graph_warnings.clone()));
+
+        log.push(event(BuildStage::Graph, EventLevel::Info, "graph info"));
+        log.push(event(BuildStage::Graph, EventLevel::Warn, "graph warn"));
+        log.push(event(BuildStage::Graph, EventLevel::Error, "graph error"));
+        log.push(event(BuildStage::Execute, EventLevel::Error, "execute error"));
+
+        assert_eq!(messages(&log.history(Some(&graph_warnings))), ["graph warn", "graph error"]);
+        assert_eq!(messages(&received.lock().unwrap()), ["graph warn", "graph error"]);
+
+        let stages_only = EventFilter {
+            stages: Some(vec![BuildStage::Execute, BuildStage::Init]),
+            min_level: None,
+        };
+        assert_eq!(messages(&log.history(Some(&stages_only))), ["execute error"]);
+        let levels_only = EventFilter {
+            stages: None,
+            min_level: Some(EventLevel::Error),
+        };
+        assert_eq!(messages(&log.history(Some(&levels_only))), ["graph error", "execute error"]);
+        assert_eq!(log.history(Some(&EventFilter::default())).len(), 4);
+    }
+
+    #[test]
+    fn unsubscribing_stops_delivery_once() {
+        let log = EventLog::new(10);
+        let (id, received) = collect(&log, None);
+        log.push(event(BuildStage::Plan, EventLevel::Info, "before"));
+
+        assert!(log.unsubscribe(id));
+        assert!(!log.unsubscribe(id));
+        assert!(!log.unsubscribe(id + 100));
+        log.push(event(BuildStage::Plan, EventLevel::Info, "after"));
+        assert_eq!(messages(&received.lock().unwrap()), ["before"]);
+    }
+}
@@ -0,0 +1,343 @@
+// OpenTelemetry export of builds for Nexxo v2.0
+//
+// When an exporter is set, every finished build is sent to an OTLP collector
+// as one trace, using the JSON encoding of OTLP/HTTP (`POST /v1/traces`):
+// - a root span for the build
+// - a span for each stage it went through, as children of the build
+// - a span for each task that started, as children of its stage
+//
+// Spans are built from the `BuildReport`, so exporting adds no work while
+// the build runs. Finished builds are queued and sent by a background
+// thread, so `run` never waits on the collector; when the queue is full a
+// build is dropped rather than delaying the next one. Resource attributes
+// identify the project, target and commit, so builds can be grouped and
+// compared in the collector's backend.
+
+use napi_derive::napi;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+use xxhash_rust::xxh3::{xxh3_128, xxh3_64_with_seed};
+
+use super::dag::{BuildReport, BuildStatus, TaskStatus};
+use super::events::{EventLevel, EventLog};
+use super::stage::BuildStage;
+
+const DEFAULT_SERVICE_NAME: &str = "nexxo";
+const DEFAULT_TIMEOUT_MS: u32 = 10_000;
+const TRACES_PATH: &str = "/v1/traces";
+
+/// Finished builds waiting to be sent before new ones are dropped
+const QUEUE_CAPACITY: usize = 64;
+
+/// OTLP span kind INTERNAL
+const SPAN_KIND_INTERNAL: u32 = 1;
+
+/// OTLP status codes
+const STATUS_OK: u32 = 1;
+const STATUS_ERROR: u32 = 2;
+
+/// Where and how to send build spans
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[napi(object)]
+pub struct OtlpExporterOptions {
+    /// Base URL of the collector's OTLP/HTTP receiver, e.g.
+    /// `http://localhost:4318`; `/v1/traces` is added unless already there
+    pub endpoint: String,
+    /// Extra headers sent with every request, e.g. for authentication
+    pub headers: Option<HashMap<String, String>>,
+    /// `service.name` resource attribute (default: `nexxo`)
+    pub service_name: Option<String>,
+    /// `nexxo.project` resource attribute
+    pub project: Option<String>,
+    /// `nexxo.target` resource attribute, e.g. `dev` or `prod`
+    pub target: Option<String>,
+    /// `vcs.ref.head.revision` resource attribute
+    pub git_sha: Option<String>,
+    /// Per-request timeout in milliseconds (default: 10000)
+    pub timeout_ms: Option<u32>,
+}
+
+/// Encodes builds and posts them to the collector
+struct Collector {
+    agent: ureq::Agent,
+    url: String,
+    headers: Vec<(String, String)>,
+    resource: Value,
+}
+
+/// A finished build waiting to be sent
+struct ExportJob {
+    report: BuildReport,
+    start_unix_nanos: i64,
+    /// Stage that export failures are logged under
+    stage: BuildStage,
+}
+
+/// Sends finished builds to an OTLP collector from a background thread
+pub(crate) struct OtlpExporter {
+    queue: Option<SyncSender<ExportJob>>,
+    /// Builds queued and not yet sent
+    pending: Arc<(Mutex<u64>, Condvar)>,
+    events: Arc<EventLog>,
+}
+
+fn attribute(key: &str, value: Value) -> Value {
+    let value = match value {
+        Value::String(s) => json!({ "stringValue": s }),
+        Value::Bool(b) => json!({ "boolValue": b }),
+        Value::Number(n) if n.is_i64() || n.is_u64() => json!({ "intValue": n.to_string() }),
+        Value::Number(n) => json!({ "doubleValue": n }),
+        other => json!({ "stringValue": other.to_string() }),
+    };
+    json!({ "key": key, "value": value })
+}
+
+/// OTLP timestamps are nanoseconds since the Unix epoch, as strings
+fn nanos(start_unix_nanos: i64, offset_ms: f64) -> String {
+    (start_unix_nanos + (offset_ms * 1_000_000.0) as i64).to_string()
+}
+
+impl OtlpExporter {
+    /// Start the export thread; failures are logged as Warn events in `events`
+    pub fn new(options: &OtlpExporterOptions, events: Arc<EventLog>) -> std::result::Result<Self, String> {
+        let collector = Collector::new(options)?;
+        let pending = Arc::new((Mutex::new(0u64), Condvar::new()));
+        let (sender, receiver) = mpsc::sync_channel::<ExportJob>(QUEUE_CAPACITY);
+        let worker_pending = pending.clone();
+        let worker_events = events.clone();
+        std::thread::Builder::new()
+            .name("nexxo-otlp-export".to_string())
+            .spawn(move || export_worker(collector, receiver, worker_pending, worker_events))
+            .map_err(|e| format!("Failed to spawn OTLP export thread: {}", e))?;
+        Ok(Self {
+            queue: Some(sender),
+            pending,
+            events,
+        })
+    }
+
+    /// Queue a finished build that started at `start_unix_nanos`; drops it
+    /// with a Warn event under `stage` if the queue is full
+    pub fn enqueue(&self, report: BuildReport, start_unix_nanos: i64, stage: BuildStage) {
+        let Some(queue) = &self.queue else {
+            return;
+        };
+        *self.pending.0.lock().unwrap() += 1;
+        let build_id = report.build_id.clone();
+        let job = ExportJob {
+            report,
+            start_unix_nanos,
+            stage,
+        };
+        if queue.try_send(job).is_err() {
+            self.events.emit(
+                stage,
+                EventLevel::Warn,
+                format!("Dropped OTLP export of build {}: too many builds waiting to be sent", build_id),
+                None,
+            );
+            finish_export(&self.pending);
+        }
+    }
+
+    /// Block until every queued build has been sent or the timeout elapses.
+    /// Returns true if the queue drained.
+    pub fn flush(&self, timeout: Duration) -> bool {
+        let (lock, drained) = &*self.pending;
+        let pending = lock.lock().unwrap();
+        let (pending, _) = drained
+            .wait_timeout_while(pending, timeout, |pending| *pending > 0)
+            .unwrap();
+        *pending == 0
+    }
+}
+
+impl Drop for OtlpExporter {
+    fn drop(&mut self) {
+        // Closing the channel lets the thread send what is queued and exit
+        // without anyone waiting on the network
+        self.queue.take();
+    }
+}
+
+fn export_worker(
+    collector: Collector,
+    receiver: Receiver<ExportJob>,
+    pending: Arc<(Mutex<u64>, Condvar)>,
+    events: Arc<EventLog>,
+) {
+    while let Ok(job) = receiver.recv() {
+        // Telemetry is best effort; the build itself succeeded or failed already
+        if let Err(e) = collector.export(&job.report, job.start_unix_nanos) {
+            events.emit(
+                job.stage,
+                EventLevel::Warn,
+                format!("Failed to export build {} to OTLP: {}", job.report.build_id, e),
+                None,
+            );
+        }
+        finish_export(&pending);
+    }
+}
+
+fn finish_export(pending: &(Mutex<u64>, Condvar)) {
+    let (lock, drained) = pending;
+    let mut count = lock.lock().unwrap();
+    *count = count.saturating_sub(1);
+    if *count == 0 {
+        drained.notify_all();
+    }
+}
+
+impl Collector {
+    fn new(options: &OtlpExporterOptions) -> std::result::Result<Self, String> {
+        if !options.endpoint.starts_with("http://") && !options.endpoint.starts_with("https://") {
+            return Err(format!("OTLP endpoint must be http(s): {}", options.endpoint));
+        }
+        let base = options.endpoint.trim_end_matches('/');
+        let url = if base.ends_with(TRACES_PATH) {
+            base.to_string()
+        } else {
+            format!("{}{}", base, TRACES_PATH)
+        };
+
+        let mut attributes = vec![attribute(
+            "service.name",
+            json!(options.service_name.as_deref().unwrap_or(DEFAULT_SERVICE_NAME)),
+        )];
+        for (key, value) in [
+            ("nexxo.project", &options.project),
+            ("nexxo.target", &options.target),
+            ("vcs.ref.head.revision", &options.git_sha),
+        ] {
+            if let Some(value) = value {
+                attributes.push(attribute(key, json!(value)));
+            }
+        }
+
+        let timeout = Duration::from_millis(options.timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS) as u64);
+        Ok(Self {
+            agent: ureq::AgentBuilder::new().timeout(timeout).build(),
+            url,
+            headers: options.headers.clone().unwrap_or_default().into_iter().collect(),
+            resource: json!({ "attributes": attributes }),
+        })
+    }
+
+    /// The OTLP request body for a build that started at `start_unix_nanos`
+    fn encode(&self, report: &BuildReport, start_unix_nanos: i64) -> Value {
+        let trace_id = format!(
+            "{:032x}",
+            xxh3_128(format!("{}:{}:{}", report.build_id, start_unix_nanos, std::process::id()).as_bytes())
+        );
+        let mut next_span = 0u64;
+        let mut span_id = || {
+            next_span += 1;
+            // Zero is not a valid span id
+            format!("{:016x}", xxh3_64_with_seed(trace_id.as_bytes(), next_span).max(1))
+        };
+        let span = |span_id: &str, parent: Option<&str>, name: &str, start_ms: f64, duration_ms: f64| {
+            let mut span = json!({
+                "traceId": trace_id,
+                "spanId": span_id,
+                "name": name,
+                "kind": SPAN_KIND_INTERNAL,
+                "startTimeUnixNano": nanos(start_unix_nanos, start_ms),
+                "endTimeUnixNano": nanos(start_unix_nanos, start_ms + duration_ms),
+            });
+            if let Some(parent) = parent {
+                span["parentSpanId"] = json!(parent);
+            }
+            span
+        };
+
+        let mut spans = Vec::with_capacity(1 + report.stages.len() + report.tasks.len());
+        let build_span_id = span_id();
+        let mut build = span(&build_span_id, None, "build", 0.0, report.duration_ms);
+        build["attributes"] = json!([
+            attribute("nexxo.build.id", json!(report.build_id)),
+            attribute("nexxo.build.status", json!(format!("{:?}", report.status))),
+            attribute("nexxo.build.succeeded", json!(report.succeeded)),
+            attribute("nexxo.build.failed", json!(report.failed)),
+            attribute("nexxo.build.skipped", json!(report.skipped)),
+            attribute("nexxo.build.cancelled", json!(report.cancelled)),
+        ]);
+        build["status"] = match report.status {
+            BuildStatus::Succeeded => json!({ "code": STATUS_OK }),
+            BuildStatus::Failed => {
+                let message = report.error.clone().unwrap_or_else(|| format!("{} task(s) failed", report.failed));
+                json!({ "code": STATUS_ERROR, "message": message })
+            }
+            BuildStatus::Cancelled => json!({ "code": STATUS_ERROR, "message": "Build was cancelled" }),
+            BuildStatus::Nondeterministic => json!({ "code": STATUS_ERROR, "message": "Determinism check failed" }),
+        };
+        spans.push(build);
+
+        let mut stage_span_ids = HashMap::new();
+        for timing in &report.stages {
+            let id = span_id();
+            let mut stage = span(
+                &id,
+                Some(&build_span_id),
+                &format!("{:?}", timing.stage),
+                timing.start_ms,
+                timing.duration_ms,
+            );
+            stage["attributes"] = json!([attribute("nexxo.stage", json!(format!("{:?}", timing.stage)))]);
+            spans.push(stage);
+            stage_span_ids.insert(timing.stage, id);
+        }
+
+        for task in &report.tasks {
+            let Some(start_ms) = task.start_ms else {
+                continue;
+            };
+            let parent = stage_span_ids.get(&task.stage).unwrap_or(&build_span_id);
+            let mut attributes = vec![
+                attribute("nexxo.task.id", json!(task.id)),
+                attribute("nexxo.task.status", json!(format!("{:?}", task.status))),
+                attribute("nexxo.task.attempts", json!(task.attempts)),
+            ];
+            if let Some(queued_ms) = task.queued_ms {
+                attributes.push(attribute("nexxo.task.queued_ms", json!(queued_ms)));
+            }
+            let mut span = span(&span_id(), Some(parent), &task.id, start_ms, task.duration_ms);
+            span["attributes"] = json!(attributes);
+            span["status"] = match task.status {
+                TaskStatus::Succeeded => json!({ "code": STATUS_OK }),
+                _ => json!({ "code": STATUS_ERROR, "message": task.error.clone().unwrap_or_default() }),
+            };
+            spans.push(span);
+        }
+
+        json!({
+            "resourceSpans": [{
+                "resource": self.resource,
+                "scopeSpans": [{
+                    "scope": { "name": "nexxo.orchestrator", "version": env!("CARGO_PKG_VERSION") },
+                    "spans": spans,
+                }],
+            }],
+        })
+    }
+
+    /// Send a build to the collector. Blocks until it answers.
+    fn export(&self, report: &BuildReport, start_unix_nanos: i64) -> std::result::Result<(), String> {
+        let body = self.encode(report, start_unix_nanos).to_string();
+        let mut request = self
+            .agent
+            .post(&self.url)
+            .set("Content-Type", "application/json");
+        for (name, value) in &self.headers {
+            request = request.set(name, value);
+        }
+        request
+            .send_string(&body)
+            .map(|_| ())
+            .map_err(|e| format!("POST {} failed: {}", self.url, e))
+    }
+}
@@ -0,0 +1,276 @@
+// Build lifecycle for Nexxo v2.0
+//
+// Every build moves through the same stages, in order: Init, Graph, Plan,
+// DeterminismCheck, Execute and Emit. DeterminismCheck is optional and only
+// entered when the build has tasks in it or checks its determinism; every
+// other stage is entered even when empty, so hooks see the same lifecycle
+// for every build. A stage ends
+// once all of its tasks have finished, so a task never starts before every
+// task of an earlier stage is done.
+//
+// Hooks registered with `onStageEnter`/`onStageLeave` are awaited before the
+// build moves on, which lets plugins do their own work at each step. A hook
+// that throws stops the build: no more tasks start, and it goes straight to
+// Emit so Emit hooks still see it end. Stage changes are also logged as events.
+
+use napi::bindgen_prelude::*;
+use napi::threadsafe_function::ThreadsafeFunction;
+use napi_derive::napi;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Instant;
+
+use super::events::{EventLevel, EventLog};
+use super::timeline::{Timeline, Track};
+
+/// Build stage for orchestration
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[napi(string_enum)]
+pub enum BuildStage {
+    Init,
+    Graph,
+    Plan,
+    DeterminismCheck,
+    Execute,
+    Emit,
+}
+
+impl BuildStage {
+    /// The stage that normally follows this one
+    fn next(self) -> Option<Self> {
+        match self {
+            BuildStage::Init => Some(BuildStage::Graph),
+            BuildStage::Graph => Some(BuildStage::Plan),
+            BuildStage::Plan => Some(BuildStage::DeterminismCheck),
+            BuildStage::DeterminismCheck => Some(BuildStage::Execute),
+            BuildStage::Execute => Some(BuildStage::Emit),
+            BuildStage::Emit => None,
+        }
+    }
+
+    /// Parse a stage name as `logEvent` accepts it: in any case, with or
+    /// without `-`, `_` or spaces between words
+    pub(crate) fn parse(name: &str) -> Option<Self> {
+        let name: String = name
+            .chars()
+            .filter(|c| !matches!(c, '-' | '_' | ' '))
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+        match name.as_str() {
+            "init" => Some(BuildStage::Init),
+            "graph" => Some(BuildStage::Graph),
+            "plan" => Some(BuildStage::Plan),
+            "determinismcheck" => Some(BuildStage::DeterminismCheck),
+            "execute" => Some(BuildStage::Execute),
+            "emit" => Some(BuildStage::Emit),
+            _ => None,
+        }
+    }
+
+    /// Whether a build in stage `from` may move on to `to`
+    fn can_transition(from: Option<Self>, to: Self) -> bool {
+        match from {
+            None => to == BuildStage::Init,
+            // DeterminismCheck is optional
+            Some(BuildStage::Plan) => matches!(to, BuildStage::DeterminismCheck | BuildStage::Execute),
+            Some(from) => from.next() == Some(to),
+        }
+    }
+}
+
+/// Argument passed to stage hooks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[napi(object)]
+pub struct StageEvent {
+    pub build_id: String,
+    pub stage: BuildStage,
+    /// Time spent in the stage; only set when leaving it
+    pub duration_ms: Option<f64>,
+}
+
+/// When a stage of a build started and how long it took
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[napi(object)]
+pub struct StageTiming {
+    pub stage: BuildStage,
+    /// In ms since the build started
+    pub start_ms: f64,
+    pub duration_ms: f64,
+}
+
+/// JS function called on stage changes. Weak, so registering one does not
+/// keep the process alive.
+pub type StageHook = ThreadsafeFunction<StageEvent, Either<Promise<()>, ()>, StageEvent, Status, false, true>;
+
+/// Hooks registered on an orchestrator
+#[derive(Default, Clone)]
+pub(crate) struct StageHooks {
+    pub enter: Vec<Arc<StageHook>>,
+    pub leave: Vec<Arc<StageHook>>,
+}
+
+/// Call `hooks` one after another, awaiting each
+async fn call_hooks(hooks: &[Arc<StageHook>], event: &StageEvent, action: &str) -> std::result::Result<(), String> {
+    for hook in hooks {
+        let failed = |e: Error| format!("Hook for {} stage {:?} failed: {}", action, event.stage, e.reason);
+        if let Either::A(promise) = hook.call_async(event.clone()).await.map_err(failed)? {
+            promise.await.map_err(failed)?;
+        }
+    }
+    Ok(())
+}
+
+/// Stages of one build, with their timing
+pub(crate) struct StageMachine {
+    build_id: String,
+    hooks: StageHooks,
+    events: Arc<EventLog>,
+    timeline: Arc<Timeline>,
+    started: Instant,
+    current: Option<(BuildStage, Instant)>,
+    timings: Vec<StageTiming>,
+}
+
+impl StageMachine {
+    pub fn new(
+        build_id: String,
+        hooks: StageHooks,
+        events: Arc<EventLog>,
+        timeline: Arc<Timeline>,
+        started: Instant,
+    ) -> Self {
+        Self {
+            build_id,
+            hooks,
+            events,
+            timeline,
+            started,
+            current: None,
+            timings: Vec::new(),
+        }
+    }
+
+    pub fn current(&self) -> Option<BuildStage> {
+        self.current.map(|(stage, _)| stage)
+    }
+
+    /// The stage to enter after the current one, or `None` after Emit
+    pub fn upcoming(&self, include_determinism_check: bool) -> Option<BuildStage> {
+        match self.current() {
+            None => Some(BuildStage::Init),
+            Some(BuildStage::Plan) if !include_determinism_check => Some(BuildStage::Execute),
+            Some(stage) => stage.next(),
+        }
+    }
+
+    /// Leave the current stage, if any, and enter `stage`
+    pub async fn enter(&mut self, stage: BuildStage) -> std::result::Result<(), String> {
+        if !BuildStage::can_transition(self.current(), stage) {
+            return Err(format!("Invalid stage transition from {:?} to {:?}", self.current(), stage));
+        }
+        self.leave().await?;
+        self.start(stage).await
+    }
+
+    /// Leave the current stage, if any, and go straight to Emit, for a build
+    /// that stopped early. Every hook runs even if an earlier one fails.
+    pub async fn skip_to_emit(&mut self) -> std::result::Result<(), String> {
+        if self.current() == Some(BuildStage::Emit) {
+            return Ok(());
+        }
+        let left = self.leave().await;
+        let entered = self.start(BuildStage::Emit).await;
+        left.and(entered)
+    }
+
+    /// Enter `stage` without checking where the build came from
+    async fn start(&mut self, stage: BuildStage) -> std::result::Result<(), String> {
+        self.current = Some((stage, Instant::now()));
+        self.events.emit(
+            stage,
+            EventLevel::Debug,
+            format!("Build {} entered stage {:?}", self.build_id, stage),
+            None,
+        );
+        let event = StageEvent {
+            build_id: self.build_id.clone(),
+            stage,
+            duration_ms: None,
+        };
+        call_hooks(&self.hooks.enter, &event, "entering").await
+    }
+
+    /// Leave the current stage, if any, recording its timing
+    pub async fn leave(&mut self) -> std::result::Result<(), String> {
+        let Some((stage, entered)) = self.current.take() else {
+            return Ok(());
+        };
+        let left = Instant::now();
+        let duration_ms = left.duration_since(entered).as_secs_f64() * 1000.0;
+        self.timeline.record(
+            Track::Stages(self.build_id.clone()),
+            "stage",
+            format!("{:?}", stage),
+            entered,
+            Some(left),
+            serde_json::json!({ "build": self.build_id }),
+        );
+        self.timings.push(StageTiming {
+            stage,
+            start_ms: entered.duration_since(self.started).as_secs_f64() * 1000.0,
+            duration_ms,
+        });
+        self.events.emit(
+            stage,
+            EventLevel::Info,
+            format!("Build {} finished stage {:?}", self.build_id, stage),
+            Some(duration_ms),
+        );
+        let event = StageEvent {
+            build_id: self.build_id.clone(),
+            stage,
+            duration_ms: Some(duration_ms),
+        };
+        call_hooks(&self.hooks.leave, &event, "leaving").await
+    }
+
+    pub fn into_timings(self) -> Vec<StageTiming> {
+        self.timings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_stage_names_in_any_case() {
+        assert_eq!(BuildStage::parse("Execute"), Some(BuildStage::Execute));
+        assert_eq!(BuildStage::parse("graph"), Some(BuildStage::Graph));
+        assert_eq!(BuildStage::parse("determinism-check"), Some(BuildStage::DeterminismCheck));
+        assert_eq!(BuildStage::parse("DETERMINISM_CHECK"), Some(BuildStage::DeterminismCheck));
+        assert_eq!(BuildStage::parse("bundle"), None);
+    }
+
+    #[tokio::test]
+    async fn skips_to_emit_from_any_stage() {
+        let mut stages = StageMachine::new(
+            "build-1".to_string(),
+            StageHooks::default(),
+            Arc::new(EventLog::new(100)),
+            Arc::new(Timeline::new()),
+            Instant::now(),
+        );
+        stages.enter(BuildStage::Init).await.unwrap();
+        stages.enter(BuildStage::Graph).await.unwrap();
+        assert!(stages.enter(BuildStage::Execute).await.is_err());
+
+        stages.skip_to_emit().await.unwrap();
+        assert_eq!(stages.current(), Some(BuildStage::Emit));
+        stages.skip_to_emit().await.unwrap();
+        stages.leave().await.unwrap();
+
+        let visited: Vec<BuildStage> = stages.into_timings().iter().map(|timing| timing.stage).collect();
+        assert_eq!(visited, [BuildStage::Init, BuildStage::Graph, BuildStage::Emit]);
+    }
+}
@@ -0,0 +1,207 @@
+// Task specifications submitted from JS for Nexxo v2.0
+//
+// JS describes tasks as plain objects; each one either names built-in native
+// work that the orchestrator runs on its own runtime, or passes a callback
+// that is called on the JS thread and may return a Promise. Outputs chain
+// through the graph: a hashing task folds in the outputs of its dependencies,
+// so the final hash of a task changes whenever anything upstream of it changes.
+
+use napi::bindgen_prelude::*;
+use napi::threadsafe_function::ThreadsafeFunction;
+use napi_derive::napi;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use xxhash_rust::xxh3::Xxh3;
+
+use super::dag::{Lane, Retry, Task, TaskContext, TaskError, TaskFn, TaskOutcome};
+use super::stage::BuildStage;
+
+/// Delay before the first retry when none is given
+const DEFAULT_BACKOFF_MS: u32 = 100;
+
+/// Growth of the delay between retries when none is given
+const DEFAULT_BACKOFF_FACTOR: f64 = 2.0;
+
+/// Upper bound for the delay between retries when none is given
+const DEFAULT_MAX_BACKOFF_MS: u32 = 30_000;
+
+/// Built-in work a task can run
+#[napi(string_enum)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NativeTaskKind {
+    /// Does nothing; groups dependencies under one name
+    Noop,
+    /// Hash `input` together with the outputs of the dependencies
+    Hash,
+    /// Hash the contents of the file at `input` together with the outputs of the dependencies
+    HashFile,
+}
+
+/// Argument passed to a task callback
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[napi(object)]
+pub struct TaskInvocation {
+    pub id: String,
+    /// Outputs of the task's dependencies, by task id
+    pub inputs: HashMap<String, String>,
+    /// Value of `TaskSpec.input`
+    pub input: Option<String>,
+    /// 1 for the first run, 2 for the first retry and so on
+    pub attempt: u32,
+}
+
+/// What a task callback may return: its output, or a Promise of it
+type CallbackReturn = Either<Promise<Option<String>>, Option<String>>;
+
+/// JS function run as a task
+pub type TaskCallback = ThreadsafeFunction<TaskInvocation, CallbackReturn, TaskInvocation, Status, false>;
+
+/// How often, and how soon, to run a failed task again
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[napi(object)]
+pub struct RetryPolicy {
+    /// Runs after the first failed one (default: 0)
+    pub retries: Option<u32>,
+    /// Delay before the first retry (default: 100)
+    pub backoff_ms: Option<u32>,
+    /// Each further delay is this many times the previous one (default: 2)
+    pub backoff_factor: Option<f64>,
+    /// Upper bound for the delay (default: 30000)
+    pub max_backoff_ms: Option<u32>,
+}
+
+impl RetryPolicy {
+    pub(crate) fn to_retry(&self) -> Result<Retry> {
+        let factor = self.backoff_factor.unwrap_or(DEFAULT_BACKOFF_FACTOR);
+        if !factor.is_finite() || factor < 1.0 {
+            return Err(Error::from_reason(format!(
+                "backoffFactor must be a finite number of at least 1, got {}",
+                factor
+            )));
+        }
+        Ok(Retry {
+            retries: self.retries.unwrap_or(0),
+            backoff_ms: self.backoff_ms.unwrap_or(DEFAULT_BACKOFF_MS) as f64,
+            factor,
+            max_backoff_ms: self.max_backoff_ms.unwrap_or(DEFAULT_MAX_BACKOFF_MS) as f64,
+        })
+    }
+}
+
+/// A task submitted to `BuildOrchestrator.run`
+#[napi(object, object_to_js = false)]
+pub struct TaskSpec {
+    /// Unique within a build
+    pub id: String,
+    /// Ids of tasks that must succeed before this one starts
+    pub dependencies: Option<Vec<String>>,
+    /// Stage the task runs in (default: Execute). Dependencies must be in
+    /// the same stage or an earlier one.
+    pub stage: Option<BuildStage>,
+    /// Work to run (default: Noop). Not allowed together with `callback`.
+    pub kind: Option<NativeTaskKind>,
+    /// Input for the work, e.g. the text to hash or the path of the file
+    pub input: Option<String>,
+    /// Module the task's output belongs to, e.g. `src/App.tsx`. Names the
+    /// task's output in determinism reports and stable ids.
+    pub module: Option<String>,
+    /// Higher starts first when more tasks are ready than can run (default: 0)
+    pub priority: Option<i32>,
+    /// Retries if the task fails (default: `RunOptions.retry`)
+    pub retry: Option<RetryPolicy>,
+    /// JS function to run instead of native work. It may return the task's
+    /// output or a Promise of it; throwing or rejecting fails the task.
+    #[napi(ts_type = "(invocation: TaskInvocation) => string | void | null | Promise<string | void | null>")]
+    pub callback: Option<TaskCallback>,
+}
+
+impl TaskSpec {
+    pub(crate) fn into_task(self, default_retry: Retry) -> Result<Task> {
+        let retry = match &self.retry {
+            Some(policy) => policy.to_retry()?,
+            None => default_retry,
+        };
+        let input = self.input;
+        let (lane, run): (Lane, TaskFn) = match (self.kind, self.callback) {
+            (Some(kind), Some(_)) => {
+                return Err(Error::from_reason(format!(
+                    "Task {:?} has both a kind ({:?}) and a callback",
+                    self.id, kind
+                )));
+            }
+            (_, Some(callback)) => {
+                let callback = Arc::new(callback);
+                (
+                    Lane::Js,
+                    Arc::new(move |context| Box::pin(run_callback(callback.clone(), input.clone(), context))),
+                )
+            }
+            (kind, None) => {
+                let kind = kind.unwrap_or(NativeTaskKind::Noop);
+                let input = input.unwrap_or_default();
+                (
+                    Lane::Native,
+                    Arc::new(move |context| Box::pin(run_native(kind, input.clone(), context))),
+                )
+            }
+        };
+        Ok(Task {
+            id: self.id,
+            dependencies: self.dependencies.unwrap_or_default(),
+            stage: self.stage.unwrap_or(BuildStage::Execute),
+            lane,
+            module: self.module,
+            priority: self.priority.unwrap_or(0),
+            retry,
+            run,
+        })
+    }
+}
+
+/// xxh3 of `content` and each dependency output, as 16 hex digits
+fn hash_with_inputs(content: &[u8], context: &TaskContext) -> String {
+    let mut hasher = Xxh3::new();
+    hasher.update(content);
+    for (_, output) in &context.inputs {
+        // Length-prefixed so ("ab", "c") and ("a", "bc") differ
+        hasher.update(&(output.len() as u64).to_le_bytes());
+        hasher.update(output.as_bytes());
+    }
+    format!("{:016x}", hasher.digest())
+}
+
+async fn run_native(kind: NativeTaskKind, input: String, context: TaskContext) -> TaskOutcome {
+    match kind {
+        NativeTaskKind::Noop => Ok(String::new()),
+        NativeTaskKind::Hash => Ok(hash_with_inputs(input.as_bytes(), &context)),
+        NativeTaskKind::HashFile => {
+            let cancel = context.cancel.clone();
+            let content = tokio::select! {
+                content = tokio::fs::read(&input) => {
+                    content.map_err(|e| format!("Failed to read {}: {}", input, e))?
+                }
+                _ = cancel.cancelled() => return Err(TaskError::Cancelled),
+            };
+            // Hashing large files would hold up the runtime's other tasks
+            let hash = tokio::task::spawn_blocking(move || hash_with_inputs(&content, &context))
+                .await
+                .map_err(|e| format!("Failed to hash {}: {}", input, e))?;
+            Ok(hash)
+        }
+    }
+}
+
+async fn run_callback(callback: Arc<TaskCallback>, input: Option<String>, context: TaskContext) -> TaskOutcome {
+    let invocation = TaskInvocation {
+        id: context.id,
+        inputs: context.inputs.into_iter().collect(),
+        input,
+        attempt: context.attempt,
+    };
+    let output = match callback.call_async(invocation).await.map_err(|e| e.reason.clone())? {
+        Either::A(promise) => promise.await.map_err(|e| e.reason.clone())?,
+        Either::B(output) => output,
+    };
+    Ok(output.unwrap_or_default())
+}
@@ -0,0 +1,342 @@
+// Build timelines in Chrome Trace Event format for Nexxo v2.0
+//
+// With tracing on, the orchestrator records a span for every task attempt,
+// every stage of a build and every cache lookup. The export loads in
+// chrome://tracing and Perfetto, where each build gets its own process so
+// builds running at the same time never share a track:
+// - Build {id}: one track with the build's stages, then one track per
+//   worker slot, i.e. per task that can run at once, so spans on a track
+//   never overlap; the OS thread is in the span's args
+// - Cache: lookups made through a cache passed to `traceCache`, or reported
+//   with `traceCacheLookup`, with hits and misses on separate tracks
+//
+// Spans are kept in memory, at most `MAX_SPANS` of them, until cleared.
+
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::cache::LookupOutcome;
+
+/// Spans kept before the oldest are discarded
+const MAX_SPANS: usize = 100_000;
+
+const CACHE_PID: usize = 1;
+/// Builds are numbered from here in the order their first span was recorded
+const FIRST_BUILD_PID: usize = 2;
+
+/// Where a span is drawn
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Track {
+    /// Stages of the build with this id
+    Stages(String),
+    /// Worker slot of the scheduler of the build with this id
+    Worker(String, usize),
+    CacheHits,
+    CacheMisses,
+}
+
+impl Track {
+    fn build(&self) -> Option<&str> {
+        match self {
+            Track::Stages(build) | Track::Worker(build, _) => Some(build),
+            Track::CacheHits | Track::CacheMisses => None,
+        }
+    }
+
+    /// Process and thread ids in the trace, given the ids of the builds in
+    /// the order they get their processes
+    fn ids(&self, builds: &[&str]) -> (usize, usize) {
+        let build_pid = |build: &str| FIRST_BUILD_PID + builds.iter().position(|id| *id == build).unwrap_or(0);
+        match self {
+            Track::Stages(build) => (build_pid(build), 0),
+            Track::Worker(build, slot) => (build_pid(build), slot + 1),
+            Track::CacheHits => (CACHE_PID, 0),
+            Track::CacheMisses => (CACHE_PID, 1),
+        }
+    }
+
+    fn name(&self) -> String {
+        match self {
+            Track::Stages(_) => "Stages".to_string(),
+            Track::Worker(_, slot) => format!("Worker {}", slot),
+            Track::CacheHits => "Hits".to_string(),
+            Track::CacheMisses => "Misses".to_string(),
+        }
+    }
+}
+
+struct Span {
+    track: Track,
+    name: String,
+    category: &'static str,
+    start: Instant,
+    /// `None` for an instant event
+    end: Option<Instant>,
+    args: Value,
+}
+
+#[derive(Default)]
+struct TimelineState {
+    spans: VecDeque<Span>,
+    discarded: u64,
+}
+
+/// Spans recorded by an orchestrator
+pub(crate) struct Timeline {
+    enabled: AtomicBool,
+    /// Trace timestamps count from here
+    epoch: Instant,
+    state: Mutex<TimelineState>,
+}
+
+impl Timeline {
+    pub fn new() -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+            epoch: Instant::now(),
+            state: Mutex::new(TimelineState::default()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Record a span from `start` to `end`, or an instant event if `end` is `None`
+    pub fn record(
+        &self,
+        track: Track,
+        category: &'static str,
+        name: String,
+        start: Instant,
+        end: Option<Instant>,
+        args: Value,
+    ) {
+        if !self.is_enabled() {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        if state.spans.len() == MAX_SPANS {
+            state.spans.pop_front();
+            state.discarded += 1;
+        }
+        state.spans.push_back(Span {
+            track,
+            name,
+            category,
+            start,
+            end,
+            args,
+        });
+    }
+
+    /// Record a cache lookup that ran from `start` to `end`, or an instant
+    /// event if its duration is unknown
+    pub fn record_lookup(&self, key: &str, outcome: LookupOutcome, start: Instant, end: Option<Instant>) {
+        let track = match outcome {
+            LookupOutcome::LocalHit | LookupOutcome::RemoteHit => Track::CacheHits,
+            LookupOutcome::Miss => Track::CacheMisses,
+        };
+        self.record(track, "cache", format!("{:?}", outcome), start, end, json!({ "key": key }));
+    }
+
+    pub fn clear(&self) {
+        *self.state.lock().unwrap() = TimelineState::default();
+    }
+
+    /// Microseconds since the epoch, as Chrome traces expect
+    fn micros(&self, at: Instant) -> f64 {
+        at.saturating_duration_since(self.epoch).as_secs_f64() * 1_000_000.0
+    }
+
+    /// The recorded spans as a Chrome Trace Event JSON document
+    pub fn export(&self) -> Value {
+        let state = self.state.lock().unwrap();
+        let mut events = Vec::with_capacity(state.spans.len() + 8);
+
+        // Name and order the processes and threads that have spans, builds
+        // first in the order they started and the cache last
+        let mut builds: Vec<&str> = Vec::new();
+        for build in state.spans.iter().filter_map(|span| span.track.build()) {
+            if !builds.contains(&build) {
+                builds.push(build);
+            }
+        }
+        let processes = builds
+            .iter()
+            .enumerate()
+            .map(|(index, build)| (FIRST_BUILD_PID + index, format!("Build {}", build)))
+            .chain(
+                state.spans.iter().any(|span| span.track.build().is_none()).then(|| (CACHE_PID, "Cache".to_string())),
+            );
+        for (sort_index, (pid, name)) in processes.enumerate() {
+            events.push(json!({ "name": "process_name", "ph": "M", "pid": pid, "args": { "name": name } }));
+            events.push(json!({ "name": "process_sort_index", "ph": "M", "pid": pid, "args": { "sort_index": sort_index } }));
+        }
+        let tracks: BTreeMap<(usize, usize), &Track> =
+            state.spans.iter().map(|span| (span.track.ids(&builds), &span.track)).collect();
+        for ((pid, tid), track) in tracks {
+            events.push(json!({ "name": "thread_name", "ph": "M", "pid": pid, "tid": tid, "args": { "name": track.name() } }));
+        }
+
+        for span in &state.spans {
+            let (pid, tid) = span.track.ids(&builds);
+            let mut event = json!({
+                "name": span.name,
+                "cat": span.category,
+                "pid": pid,
+                "tid": tid,
+                "ts": self.micros(span.start),
+                "args": span.args,
+            });
+            match span.end {
+                Some(end) => {
+                    event["ph"] = json!("X");
+                    event["dur"] = json!(end.saturating_duration_since(span.start).as_secs_f64() * 1_000_000.0);
+                }
+                None => {
+                    event["ph"] = json!("i");
+                    event["s"] = json!("t");
+                }
+            }
+            events.push(event);
+        }
+
+        json!({
+            "traceEvents": events,
+            "displayTimeUnit": "ms",
+            "otherData": { "producer": "nexxo", "discardedSpans": state.discarded },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn enabled() -> Timeline {
+        let timeline = Timeline::new();
+        timeline.set_enabled(true);
+        timeline
+    }
+
+    fn events(timeline: &Timeline) -> Vec<Value> {
+        timeline.export()["traceEvents"].as_array().unwrap().clone()
+    }
+
+    /// Metadata events named `name`, as (pid, tid, args.name or args.sort_index)
+    fn metadata(events: &[Value], name: &str) -> Vec<(Value, Value, Value)> {
+        events
+            .iter()
+            .filter(|event| event["ph"] == "M" && event["name"] == name)
+            .map(|event| {
+                let args = &event["args"];
+                let value = if args["name"].is_null() { args["sort_index"].clone() } else { args["name"].clone() };
+                (event["pid"].clone(), event["tid"].clone(), value)
+            })
+            .collect()
+    }
+
+    fn span<'a>(events: &'a [Value], name: &str) -> &'a Value {
+        events.iter().find(|event| event["name"] == name && event["ph"] != "M").unwrap()
+    }
+
+    #[test]
+    fn records_nothing_while_disabled() {
+        let timeline = Timeline::new();
+        timeline.record(Track::Stages("a".into()), "stage", "Init".into(), Instant::now(), None, json!({}));
+        assert!(events(&timeline).is_empty());
+    }
+
+    #[test]
+    fn exports_complete_and_instant_events() {
+        let timeline = enabled();
+        let start = timeline.epoch + Duration::from_millis(2);
+        let end = start + Duration::from_micros(1500);
+        timeline.record(Track::Worker("a".into(), 0), "task", "compile".into(), start, Some(end), json!({ "attempt": 1 }));
+        timeline.record_lookup("graph:g", LookupOutcome::Miss, start, None);
+
+        let events = events(&timeline);
+        let task = span(&events, "compile");
+        assert_eq!(task["ph"], "X");
+        assert_eq!(task["cat"], "task");
+        assert_eq!(task["ts"].as_f64().unwrap().round(), 2000.0);
+        assert_eq!(task["dur"].as_f64().unwrap().round(), 1500.0);
+        assert_eq!(task["args"], json!({ "attempt": 1 }));
+
+        let lookup = span(&events, "Miss");
+        assert_eq!((&lookup["ph"], &lookup["s"], &lookup["cat"]), (&json!("i"), &json!("t"), &json!("cache")));
+        assert!(lookup["dur"].is_null());
+        assert_eq!(lookup["args"], json!({ "key": "graph:g" }));
+        assert_eq!((&lookup["pid"], &lookup["tid"]), (&json!(CACHE_PID), &json!(1)));
+
+        let trace = timeline.export();
+        assert_eq!(trace["displayTimeUnit"], "ms");
+        assert_eq!(trace["otherData"]["discardedSpans"], 0);
+    }
+
+    #[test]
+    fn gives_each_build_its_own_process() {
+        let timeline = enabled();
+        let now = Instant::now();
+        for build in ["a", "b"] {
+            timeline.record(Track::Stages(build.into()), "stage", format!("Init {}", build), now, Some(now), json!({}));
+            timeline.record(Track::Worker(build.into(), 0), "task", format!("task {}", build), now, Some(now), json!({}));
+        }
+        timeline.record_lookup("graph:g", LookupOutcome::LocalHit, now, Some(now));
+
+        let events = events(&timeline);
+        assert_eq!(
+            metadata(&events, "process_name"),
+            [
+                (json!(2), Value::Null, json!("Build a")),
+                (json!(3), Value::Null, json!("Build b")),
+                (json!(1), Value::Null, json!("Cache")),
+            ]
+        );
+        assert_eq!(
+            metadata(&events, "process_sort_index"),
+            [
+                (json!(2), Value::Null, json!(0)),
+                (json!(3), Value::Null, json!(1)),
+                (json!(1), Value::Null, json!(2)),
+            ]
+        );
+        assert_eq!(
+            metadata(&events, "thread_name"),
+            [
+                (json!(1), json!(0), json!("Hits")),
+                (json!(2), json!(0), json!("Stages")),
+                (json!(2), json!(1), json!("Worker 0")),
+                (json!(3), json!(0), json!("Stages")),
+                (json!(3), json!(1), json!("Worker 0")),
+            ]
+        );
+
+        // The same worker slot of two builds lands on two tracks
+        let (a, b) = (span(&events, "task a"), span(&events, "task b"));
+        assert_eq!((&a["pid"], &a["tid"]), (&json!(2), &json!(1)));
+        assert_eq!((&b["pid"], &b["tid"]), (&json!(3), &json!(1)));
+    }
+
+    #[test]
+    fn discards_the_oldest_spans_past_the_limit() {
+        let timeline = enabled();
+        let now = Instant::now();
+        for _ in 0..MAX_SPANS + 2 {
+            timeline.record(Track::CacheHits, "cache", "LocalHit".into(), now, None, json!({}));
+        }
+        let trace = timeline.export();
+        assert_eq!(trace["otherData"]["discardedSpans"], 2);
+        timeline.clear();
+        assert!(events(&timeline).is_empty());
+    }
+}
@@ -0,0 +1,127 @@
+// Plain-directory storage backend
+//
+// Each entry is one file, named by the XXH3 hash of its key and sharded into
+// 256 subdirectories. A file holds the key (so hash collisions read as
+// misses and prefix scans can recover keys) followed by the value:
+//
+//   key length   u32 LE
+//   key
+//   value
+//
+// Writes go to a temporary file that is renamed into place, so readers never
+// see a partial entry.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use xxhash_rust::xxh3::xxh3_64;
+
+use crate::Storage;
+
+/// One file per entry under a root directory
+pub struct DirectoryStorage {
+    root: PathBuf,
+    temp_counter: AtomicU64,
+}
+
+impl DirectoryStorage {
+    pub fn open(root: &Path) -> io::Result<Self> {
+        fs::create_dir_all(root)?;
+        Ok(Self {
+            root: root.to_path_buf(),
+            temp_counter: AtomicU64::new(0),
+        })
+    }
+
+    fn entry_path(&self, key: &[u8]) -> PathBuf {
+        let hash = format!("{:016x}", xxh3_64(key));
+        self.root.join(&hash[..2]).join(&hash[2..])
+    }
+
+    /// Split a file's contents into key and value
+    fn decode(contents: &[u8]) -> Option<(&[u8], &[u8])> {
+        let key_len = u32::from_le_bytes(contents.get(..4)?.try_into().ok()?) as usize;
+        let key = contents.get(4..4 + key_len)?;
+        Some((key, &contents[4 + key_len..]))
+    }
+
+    fn shards(&self) -> io::Result<Vec<PathBuf>> {
+        let mut shards = Vec::new();
+        for entry in fs::read_dir(&self.root)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                shards.push(entry.path());
+            }
+        }
+        Ok(shards)
+    }
+}
+
+impl Storage for DirectoryStorage {
+    fn name(&self) -> &'static str {
+        "directory"
+    }
+
+    fn get(&self, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        let contents = match fs::read(self.entry_path(key)) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        Ok(match Self::decode(&contents) {
+            Some((stored_key, value)) if stored_key == key => Some(value.to_vec()),
+            _ => None,
+        })
+    }
+
+    fn put(&self, key: &[u8], value: &[u8]) -> io::Result<()> {
+        let path = self.entry_path(key);
+        let shard = path.parent().expect("entry paths are inside a shard");
+        fs::create_dir_all(shard)?;
+
+        let temp = shard.join(format!(
+            ".tmp-{}-{}",
+            std::process::id(),
+            self.temp_counter.fetch_add(1, Ordering::Relaxed)
+        ));
+        let mut file = fs::File::create(&temp)?;
+        file.write_all(&(key.len() as u32).to_le_bytes())?;
+        file.write_all(key)?;
+        file.write_all(value)?;
+        drop(file);
+        fs::rename(&temp, &path)
+    }
+
+    fn delete(&self, key: &[u8]) -> io::Result<()> {
+        match fs::remove_file(self.entry_path(key)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn scan_prefix(&self, prefix: &[u8], visit: &mut dyn FnMut(&[u8], &[u8]) -> bool) -> io::Result<()> {
+        for shard in self.shards()? {
+            for entry in fs::read_dir(shard)? {
+                let entry = entry?;
+                if entry.file_name().to_string_lossy().starts_with(".tmp-") {
+                    continue;
+                }
+                let contents = fs::read(entry.path())?;
+                if let Some((key, value)) = Self::decode(&contents) {
+                    if key.starts_with(prefix) && !visit(key, value) {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn clear(&self) -> io::Result<()> {
+        for shard in self.shards()? {
+            fs::remove_dir_all(shard)?;
+        }
+        Ok(())
+    }
+}
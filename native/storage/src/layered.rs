@@ -0,0 +1,114 @@
+// Two-level storage: a fast L1 in front of a persistent L2
+
+use std::collections::BTreeSet;
+use std::io;
+use std::sync::{Mutex, MutexGuard};
+
+use xxhash_rust::xxh3::xxh3_64;
+
+use crate::{BatchOp, Storage};
+
+/// Number of key locks; keys share a lock when their hashes collide
+const LOCK_SHARDS: usize = 64;
+
+/// Reads try L1 first and fill it from L2 on a miss; writes go to both.
+/// L2 is the source of truth, so it is written first.
+///
+/// A fill and a write of the same key hold that key's lock, so a value read
+/// from L2 can't land in L1 after a delete or overwrite has gone through.
+pub struct LayeredStorage<L1: Storage, L2: Storage> {
+    l1: L1,
+    l2: L2,
+    locks: Vec<Mutex<()>>,
+}
+
+impl<L1: Storage, L2: Storage> LayeredStorage<L1, L2> {
+    pub fn new(l1: L1, l2: L2) -> Self {
+        let locks = (0..LOCK_SHARDS).map(|_| Mutex::new(())).collect();
+        Self { l1, l2, locks }
+    }
+
+    fn shard(key: &[u8]) -> usize {
+        (xxh3_64(key) % LOCK_SHARDS as u64) as usize
+    }
+
+    /// Lock the given shards in ascending order, so two batches can't deadlock
+    fn lock_shards(&self, shards: impl IntoIterator<Item = usize>) -> Vec<MutexGuard<'_, ()>> {
+        let shards: BTreeSet<usize> = shards.into_iter().collect();
+        shards.into_iter().map(|shard| self.locks[shard].lock().unwrap()).collect()
+    }
+
+    pub fn l1(&self) -> &L1 {
+        &self.l1
+    }
+
+    pub fn l2(&self) -> &L2 {
+        &self.l2
+    }
+}
+
+impl<L1: Storage, L2: Storage> Storage for LayeredStorage<L1, L2> {
+    fn name(&self) -> &'static str {
+        "layered"
+    }
+
+    fn get(&self, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        if let Some(value) = self.l1.get(key)? {
+            return Ok(Some(value));
+        }
+        let _guard = self.locks[Self::shard(key)].lock().unwrap();
+        // Another reader may have filled it while we waited
+        if let Some(value) = self.l1.get(key)? {
+            return Ok(Some(value));
+        }
+        let value = self.l2.get(key)?;
+        if let Some(value) = &value {
+            self.l1.put(key, value)?;
+        }
+        Ok(value)
+    }
+
+    fn put(&self, key: &[u8], value: &[u8]) -> io::Result<()> {
+        let _guard = self.locks[Self::shard(key)].lock().unwrap();
+        self.l2.put(key, value)?;
+        self.l1.put(key, value)
+    }
+
+    fn delete(&self, key: &[u8]) -> io::Result<()> {
+        let _guard = self.locks[Self::shard(key)].lock().unwrap();
+        self.l2.delete(key)?;
+        self.l1.delete(key)
+    }
+
+    fn write(&self, batch: Vec<BatchOp>) -> io::Result<()> {
+        let _guards = self.lock_shards(batch.iter().map(|op| match op {
+            BatchOp::Put(key, _) | BatchOp::Delete(key) => Self::shard(key),
+        }));
+        self.l2.write(batch.clone())?;
+        self.l1.write(batch)
+    }
+
+    fn scan_prefix(&self, prefix: &[u8], visit: &mut dyn FnMut(&[u8], &[u8]) -> bool) -> io::Result<()> {
+        // L1 only ever holds a subset of L2
+        self.l2.scan_prefix(prefix, visit)
+    }
+
+    fn scan_from(
+        &self,
+        prefix: &[u8],
+        start: &[u8],
+        visit: &mut dyn FnMut(&[u8], &[u8]) -> bool,
+    ) -> io::Result<()> {
+        self.l2.scan_from(prefix, start, visit)
+    }
+
+    fn clear(&self) -> io::Result<()> {
+        let _guards = self.lock_shards(0..LOCK_SHARDS);
+        self.l2.clear()?;
+        self.l1.clear()
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        self.l2.flush()
+    }
+}
@@ -0,0 +1,206 @@
+// Cache storage backends for Nexxo v2.0
+//
+// One key-value trait shared by both native crates, so a cache can pick its
+// backing store without code changes:
+// - `MemoryStorage`: process-local, for tests and ephemeral CI runs
+// - `DirectoryStorage`: one file per entry, no native dependencies
+// - `RocksStorage`: RocksDB, behind the `rocksdb` feature
+// - `LayeredStorage`: a fast L1 in front of a persistent L2
+//
+// `from_env` selects a backend from `NEXXO_CACHE_BACKEND` / `NEXXO_CACHE_DIR`.
+
+mod directory;
+mod layered;
+mod memory;
+#[cfg(feature = "rocksdb")]
+mod rocks;
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub use directory::DirectoryStorage;
+pub use layered::LayeredStorage;
+pub use memory::MemoryStorage;
+#[cfg(feature = "rocksdb")]
+pub use rocks::RocksStorage;
+
+/// Environment variable naming the backend (`memory`, `directory` or `rocksdb`)
+pub const BACKEND_ENV: &str = "NEXXO_CACHE_BACKEND";
+
+/// Environment variable overriding where persistent backends store data
+pub const DIR_ENV: &str = "NEXXO_CACHE_DIR";
+
+/// One change in a `Storage::write` batch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BatchOp {
+    Put(Vec<u8>, Vec<u8>),
+    Delete(Vec<u8>),
+}
+
+/// A byte-oriented key-value store
+pub trait Storage: Send + Sync {
+    /// Short backend name for diagnostics
+    fn name(&self) -> &'static str;
+
+    fn get(&self, key: &[u8]) -> io::Result<Option<Vec<u8>>>;
+
+    /// Look up many keys, returning values in the order of `keys`
+    fn multi_get(&self, keys: &[&[u8]]) -> io::Result<Vec<Option<Vec<u8>>>> {
+        keys.iter().map(|key| self.get(key)).collect()
+    }
+
+    fn put(&self, key: &[u8], value: &[u8]) -> io::Result<()>;
+
+    fn delete(&self, key: &[u8]) -> io::Result<()>;
+
+    /// Apply a batch of changes. Atomic for the memory and RocksDB backends;
+    /// the directory backend applies changes one file at a time.
+    fn write(&self, batch: Vec<BatchOp>) -> io::Result<()> {
+        for op in batch {
+            match op {
+                BatchOp::Put(key, value) => self.put(&key, &value)?,
+                BatchOp::Delete(key) => self.delete(&key)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Visit every entry whose key starts with `prefix`, in no particular
+    /// order for the directory backend. Stops early when `visit` returns false.
+    fn scan_prefix(&self, prefix: &[u8], visit: &mut dyn FnMut(&[u8], &[u8]) -> bool) -> io::Result<()>;
+
+    /// Like `scan_prefix`, visiting keys from `start` on in ascending order
+    /// on every backend. The memory and RocksDB backends seek straight to
+    /// `start`; by default the matching entries are collected and sorted.
+    fn scan_from(
+        &self,
+        prefix: &[u8],
+        start: &[u8],
+        visit: &mut dyn FnMut(&[u8], &[u8]) -> bool,
+    ) -> io::Result<()> {
+        let mut entries = Vec::new();
+        self.scan_prefix(prefix, &mut |key, value| {
+            if key >= start {
+                entries.push((key.to_vec(), value.to_vec()));
+            }
+            true
+        })?;
+        entries.sort();
+        for (key, value) in entries {
+            if !visit(&key, &value) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove every entry
+    fn clear(&self) -> io::Result<()>;
+
+    /// Make completed writes durable
+    fn flush(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<S: Storage + ?Sized> Storage for Box<S> {
+    fn name(&self) -> &'static str {
+        (**self).name()
+    }
+
+    fn get(&self, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        (**self).get(key)
+    }
+
+    fn multi_get(&self, keys: &[&[u8]]) -> io::Result<Vec<Option<Vec<u8>>>> {
+        (**self).multi_get(keys)
+    }
+
+    fn put(&self, key: &[u8], value: &[u8]) -> io::Result<()> {
+        (**self).put(key, value)
+    }
+
+    fn delete(&self, key: &[u8]) -> io::Result<()> {
+        (**self).delete(key)
+    }
+
+    fn write(&self, batch: Vec<BatchOp>) -> io::Result<()> {
+        (**self).write(batch)
+    }
+
+    fn scan_prefix(&self, prefix: &[u8], visit: &mut dyn FnMut(&[u8], &[u8]) -> bool) -> io::Result<()> {
+        (**self).scan_prefix(prefix, visit)
+    }
+
+    fn scan_from(
+        &self,
+        prefix: &[u8],
+        start: &[u8],
+        visit: &mut dyn FnMut(&[u8], &[u8]) -> bool,
+    ) -> io::Result<()> {
+        (**self).scan_from(prefix, start, visit)
+    }
+
+    fn clear(&self) -> io::Result<()> {
+        (**self).clear()
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        (**self).flush()
+    }
+}
+
+/// Available backends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackendKind {
+    Memory,
+    Directory,
+    RocksDb,
+}
+
+impl BackendKind {
+    pub fn parse(name: &str) -> io::Result<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "memory" | "mem" => Ok(BackendKind::Memory),
+            "directory" | "dir" | "fs" => Ok(BackendKind::Directory),
+            "rocksdb" | "rocks" => Ok(BackendKind::RocksDb),
+            other => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unknown cache backend {:?} (expected memory, directory or rocksdb)", other),
+            )),
+        }
+    }
+}
+
+/// Open a backend of the given kind. `path` is ignored by the memory backend.
+pub fn open(kind: BackendKind, path: &Path) -> io::Result<Box<dyn Storage>> {
+    match kind {
+        BackendKind::Memory => Ok(Box::new(MemoryStorage::new())),
+        BackendKind::Directory => Ok(Box::new(DirectoryStorage::open(path)?)),
+        #[cfg(feature = "rocksdb")]
+        BackendKind::RocksDb => Ok(Box::new(RocksStorage::open(path)?)),
+        #[cfg(not(feature = "rocksdb"))]
+        BackendKind::RocksDb => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "This build was compiled without the RocksDB cache backend",
+        )),
+    }
+}
+
+/// Backend named by `NEXXO_CACHE_BACKEND`, or `default_kind` if it is unset
+pub fn kind_from_env(default_kind: BackendKind) -> io::Result<BackendKind> {
+    match std::env::var(BACKEND_ENV) {
+        Ok(name) if !name.trim().is_empty() => BackendKind::parse(&name),
+        _ => Ok(default_kind),
+    }
+}
+
+/// Open the backend named by `NEXXO_CACHE_BACKEND` (default: `default_kind`)
+/// at `NEXXO_CACHE_DIR` (default: `default_path`)
+pub fn from_env(default_kind: BackendKind, default_path: &Path) -> io::Result<Box<dyn Storage>> {
+    let kind = kind_from_env(default_kind)?;
+    let path = std::env::var_os(DIR_ENV)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| default_path.to_path_buf());
+    open(kind, &path)
+}
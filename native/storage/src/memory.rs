@@ -0,0 +1,89 @@
+// In-memory storage backend
+
+use std::collections::BTreeMap;
+use std::io;
+use std::ops::Bound;
+use std::sync::RwLock;
+
+use crate::{BatchOp, Storage};
+
+/// Ordered in-memory map; nothing survives the process
+#[derive(Default)]
+pub struct MemoryStorage {
+    entries: RwLock<BTreeMap<Vec<u8>, Vec<u8>>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    fn get(&self, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.entries.read().unwrap().get(key).cloned())
+    }
+
+    fn put(&self, key: &[u8], value: &[u8]) -> io::Result<()> {
+        self.entries.write().unwrap().insert(key.to_vec(), value.to_vec());
+        Ok(())
+    }
+
+    fn delete(&self, key: &[u8]) -> io::Result<()> {
+        self.entries.write().unwrap().remove(key);
+        Ok(())
+    }
+
+    fn write(&self, batch: Vec<BatchOp>) -> io::Result<()> {
+        // One lock for the whole batch keeps it atomic for readers
+        let mut entries = self.entries.write().unwrap();
+        for op in batch {
+            match op {
+                BatchOp::Put(key, value) => {
+                    entries.insert(key, value);
+                }
+                BatchOp::Delete(key) => {
+                    entries.remove(&key);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn scan_prefix(&self, prefix: &[u8], visit: &mut dyn FnMut(&[u8], &[u8]) -> bool) -> io::Result<()> {
+        self.scan_from(prefix, prefix, visit)
+    }
+
+    fn scan_from(
+        &self,
+        prefix: &[u8],
+        start: &[u8],
+        visit: &mut dyn FnMut(&[u8], &[u8]) -> bool,
+    ) -> io::Result<()> {
+        let entries = self.entries.read().unwrap();
+        let range = entries.range::<[u8], _>((Bound::Included(start.max(prefix)), Bound::Unbounded));
+        for (key, value) in range {
+            if !key.starts_with(prefix) || !visit(key, value) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn clear(&self) -> io::Result<()> {
+        self.entries.write().unwrap().clear();
+        Ok(())
+    }
+}
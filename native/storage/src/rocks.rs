@@ -0,0 +1,143 @@
+// RocksDB storage backend
+
+use rocksdb::{Direction, IteratorMode, Options, WriteBatch, DB};
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::{BatchOp, Storage};
+
+/// RocksDB database, or one column family of it
+pub struct RocksStorage {
+    db: Arc<DB>,
+    column_family: Option<String>,
+}
+
+impl RocksStorage {
+    /// Open (or create) a database of its own at `path`
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut opts = Options::default();
+        opts.create_if_missing(true);
+        opts.set_compression_type(rocksdb::DBCompressionType::Lz4);
+        let db = DB::open(&opts, path).map_err(io::Error::other)?;
+        Ok(Self {
+            db: Arc::new(db),
+            column_family: None,
+        })
+    }
+
+    /// Share an already open database, optionally restricted to one column family
+    pub fn with_db(db: Arc<DB>, column_family: Option<String>) -> io::Result<Self> {
+        if let Some(name) = &column_family {
+            if db.cf_handle(name).is_none() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("Column family {} does not exist", name),
+                ));
+            }
+        }
+        Ok(Self { db, column_family })
+    }
+
+    fn cf(&self) -> Option<&rocksdb::ColumnFamily> {
+        self.column_family
+            .as_ref()
+            .map(|name| self.db.cf_handle(name).expect("checked when the storage was created"))
+    }
+}
+
+impl Storage for RocksStorage {
+    fn name(&self) -> &'static str {
+        "rocksdb"
+    }
+
+    fn get(&self, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        match self.cf() {
+            Some(cf) => self.db.get_cf(cf, key),
+            None => self.db.get(key),
+        }
+        .map_err(io::Error::other)
+    }
+
+    fn multi_get(&self, keys: &[&[u8]]) -> io::Result<Vec<Option<Vec<u8>>>> {
+        let results = match self.cf() {
+            Some(cf) => self.db.multi_get_cf(keys.iter().map(|key| (cf, key))),
+            None => self.db.multi_get(keys),
+        };
+        results
+            .into_iter()
+            .map(|result| result.map_err(io::Error::other))
+            .collect()
+    }
+
+    fn put(&self, key: &[u8], value: &[u8]) -> io::Result<()> {
+        match self.cf() {
+            Some(cf) => self.db.put_cf(cf, key, value),
+            None => self.db.put(key, value),
+        }
+        .map_err(io::Error::other)
+    }
+
+    fn delete(&self, key: &[u8]) -> io::Result<()> {
+        match self.cf() {
+            Some(cf) => self.db.delete_cf(cf, key),
+            None => self.db.delete(key),
+        }
+        .map_err(io::Error::other)
+    }
+
+    fn write(&self, batch: Vec<BatchOp>) -> io::Result<()> {
+        let cf = self.cf();
+        let mut write_batch = WriteBatch::default();
+        for op in &batch {
+            match (op, cf) {
+                (BatchOp::Put(key, value), Some(cf)) => write_batch.put_cf(cf, key, value),
+                (BatchOp::Put(key, value), None) => write_batch.put(key, value),
+                (BatchOp::Delete(key), Some(cf)) => write_batch.delete_cf(cf, key),
+                (BatchOp::Delete(key), None) => write_batch.delete(key),
+            }
+        }
+        self.db.write(write_batch).map_err(io::Error::other)
+    }
+
+    fn scan_prefix(&self, prefix: &[u8], visit: &mut dyn FnMut(&[u8], &[u8]) -> bool) -> io::Result<()> {
+        self.scan_from(prefix, prefix, visit)
+    }
+
+    fn scan_from(
+        &self,
+        prefix: &[u8],
+        start: &[u8],
+        visit: &mut dyn FnMut(&[u8], &[u8]) -> bool,
+    ) -> io::Result<()> {
+        let mode = IteratorMode::From(start.max(prefix), Direction::Forward);
+        let iter = match self.cf() {
+            Some(cf) => self.db.iterator_cf(cf, mode),
+            None => self.db.iterator(mode),
+        };
+        for item in iter {
+            let (key, value) = item.map_err(io::Error::other)?;
+            if !key.starts_with(prefix) || !visit(&key, &value) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn clear(&self) -> io::Result<()> {
+        let mut batch = Vec::new();
+        self.scan_prefix(&[], &mut |key, _| {
+            batch.push(BatchOp::Delete(key.to_vec()));
+            true
+        })?;
+        self.write(batch)
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        match self.cf() {
+            Some(cf) => self.db.flush_cf(cf),
+            None => self.db.flush(),
+        }
+        .map_err(io::Error::other)
+    }
+}
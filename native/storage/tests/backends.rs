@@ -0,0 +1,210 @@
+// Round trips through every storage backend
+
+use nexxo_storage::{BackendKind, BatchOp, DirectoryStorage, LayeredStorage, MemoryStorage, Storage};
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+
+fn temp_dir(name: &str) -> PathBuf {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    let dir = std::env::temp_dir().join(format!(
+        "nexxo-storage-{}-{}-{}",
+        name,
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+fn scan(storage: &dyn Storage, prefix: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut found = Vec::new();
+    storage
+        .scan_prefix(prefix, &mut |key, value| {
+            found.push((key.to_vec(), value.to_vec()));
+            true
+        })
+        .unwrap();
+    found.sort();
+    found
+}
+
+/// The behaviour every backend shares
+fn round_trip(storage: &dyn Storage) {
+    assert_eq!(storage.get(b"input:a").unwrap(), None);
+
+    storage.put(b"input:a", b"1").unwrap();
+    storage.put(b"input:b", b"").unwrap();
+    storage.put(b"graph:a", &[0, 255, 10]).unwrap();
+    assert_eq!(storage.get(b"input:a").unwrap().as_deref(), Some(&b"1"[..]));
+    assert_eq!(storage.get(b"input:b").unwrap().as_deref(), Some(&b""[..]));
+
+    storage.put(b"input:a", b"2").unwrap();
+    assert_eq!(
+        storage.multi_get(&[b"graph:a", b"missing", b"input:a"]).unwrap(),
+        [Some(vec![0, 255, 10]), None, Some(b"2".to_vec())]
+    );
+
+    assert_eq!(
+        scan(storage, b"input:"),
+        [(b"input:a".to_vec(), b"2".to_vec()), (b"input:b".to_vec(), Vec::new())]
+    );
+    assert_eq!(scan(storage, b"").len(), 3);
+
+    let mut from_a = Vec::new();
+    storage
+        .scan_from(b"input:", b"input:a", &mut |key, _| {
+            from_a.push(key.to_vec());
+            true
+        })
+        .unwrap();
+    assert_eq!(from_a, [b"input:a".to_vec(), b"input:b".to_vec()]);
+
+    let mut first = Vec::new();
+    storage
+        .scan_from(b"input:", b"input:a~", &mut |key, _| {
+            first.push(key.to_vec());
+            false
+        })
+        .unwrap();
+    assert_eq!(first, [b"input:b".to_vec()]);
+
+    let mut visited = 0;
+    storage
+        .scan_prefix(b"", &mut |_, _| {
+            visited += 1;
+            false
+        })
+        .unwrap();
+    assert_eq!(visited, 1);
+
+    storage
+        .write(vec![
+            BatchOp::Delete(b"input:a".to_vec()),
+            BatchOp::Put(b"plan:dev:1".to_vec(), b"p".to_vec()),
+            BatchOp::Delete(b"never-written".to_vec()),
+        ])
+        .unwrap();
+    assert_eq!(storage.get(b"input:a").unwrap(), None);
+    assert_eq!(storage.get(b"plan:dev:1").unwrap().as_deref(), Some(&b"p"[..]));
+
+    storage.delete(b"input:b").unwrap();
+    storage.delete(b"input:b").unwrap();
+    assert_eq!(storage.get(b"input:b").unwrap(), None);
+    storage.flush().unwrap();
+
+    storage.clear().unwrap();
+    assert!(scan(storage, b"").is_empty());
+    assert_eq!(storage.get(b"graph:a").unwrap(), None);
+}
+
+#[test]
+fn memory_round_trip() {
+    let storage = MemoryStorage::new();
+    round_trip(&storage);
+    assert!(storage.is_empty());
+}
+
+#[test]
+fn directory_round_trip_and_persistence() {
+    let dir = temp_dir("directory");
+    round_trip(&DirectoryStorage::open(&dir).unwrap());
+
+    DirectoryStorage::open(&dir).unwrap().put(b"input:kept", b"value").unwrap();
+    let reopened = DirectoryStorage::open(&dir).unwrap();
+    assert_eq!(reopened.get(b"input:kept").unwrap().as_deref(), Some(&b"value"[..]));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn layered_round_trip_fills_l1_from_l2() {
+    round_trip(&LayeredStorage::new(MemoryStorage::new(), MemoryStorage::new()));
+
+    let layered = LayeredStorage::new(MemoryStorage::new(), MemoryStorage::new());
+    layered.l2().put(b"graph:a", b"g").unwrap();
+    assert!(layered.l1().is_empty());
+    assert_eq!(layered.get(b"graph:a").unwrap().as_deref(), Some(&b"g"[..]));
+    assert_eq!(layered.l1().get(b"graph:a").unwrap().as_deref(), Some(&b"g"[..]));
+}
+
+/// An L2 whose reads stall long enough for a write to slip in
+#[derive(Default)]
+struct SlowReads {
+    inner: MemoryStorage,
+    reading: AtomicBool,
+}
+
+impl Storage for SlowReads {
+    fn name(&self) -> &'static str {
+        "slow"
+    }
+
+    fn get(&self, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        let value = self.inner.get(key)?;
+        self.reading.store(true, Ordering::SeqCst);
+        std::thread::sleep(Duration::from_millis(50));
+        Ok(value)
+    }
+
+    fn put(&self, key: &[u8], value: &[u8]) -> io::Result<()> {
+        self.inner.put(key, value)
+    }
+
+    fn delete(&self, key: &[u8]) -> io::Result<()> {
+        self.inner.delete(key)
+    }
+
+    fn scan_prefix(&self, prefix: &[u8], visit: &mut dyn FnMut(&[u8], &[u8]) -> bool) -> io::Result<()> {
+        self.inner.scan_prefix(prefix, visit)
+    }
+
+    fn clear(&self) -> io::Result<()> {
+        self.inner.clear()
+    }
+}
+
+#[test]
+fn layered_fill_never_resurrects_a_deleted_key() {
+    let layered = LayeredStorage::new(MemoryStorage::new(), SlowReads::default());
+    layered.l2().inner.put(b"graph:a", b"stale").unwrap();
+
+    std::thread::scope(|scope| {
+        let reader = scope.spawn(|| layered.get(b"graph:a").unwrap());
+        while !layered.l2().reading.load(Ordering::SeqCst) {
+            std::thread::yield_now();
+        }
+        // Lands while the reader holds the value it read from L2
+        layered.delete(b"graph:a").unwrap();
+        assert_eq!(reader.join().unwrap().as_deref(), Some(&b"stale"[..]));
+    });
+
+    assert_eq!(layered.l1().get(b"graph:a").unwrap(), None);
+    assert_eq!(layered.get(b"graph:a").unwrap(), None);
+}
+
+#[cfg(feature = "rocksdb")]
+#[test]
+fn rocksdb_round_trip() {
+    let dir = temp_dir("rocksdb");
+    round_trip(&nexxo_storage::RocksStorage::open(&dir).unwrap());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn parses_backend_names() {
+    assert_eq!(BackendKind::parse(" Memory ").unwrap(), BackendKind::Memory);
+    assert_eq!(BackendKind::parse("fs").unwrap(), BackendKind::Directory);
+    assert_eq!(BackendKind::parse("rocks").unwrap(), BackendKind::RocksDb);
+    assert!(BackendKind::parse("redis").is_err());
+}
+
+#[test]
+fn opens_backends_by_kind() {
+    let dir = temp_dir("open");
+    assert_eq!(nexxo_storage::open(BackendKind::Memory, &dir).unwrap().name(), "memory");
+    assert_eq!(nexxo_storage::open(BackendKind::Directory, &dir).unwrap().name(), "directory");
+    #[cfg(not(feature = "rocksdb"))]
+    assert!(nexxo_storage::open(BackendKind::RocksDb, &dir).is_err());
+    std::fs::remove_dir_all(dir).unwrap();
+}
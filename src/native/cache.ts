@@ -5,11 +5,73 @@ import { createRequire } from 'module';
 const require = createRequire(import.meta.url);
 const nativeModule = require('../../nexxo_native.node');
 
-const { BuildCache: NativeBuildCache } = nativeModule;
+const { BuildCache: NativeBuildCache, CacheKeyBuilder, decodeCacheKey, fingerprintConfig } = nativeModule;
 
-import type { BuildCache as NativeBuildCacheType, CacheStats } from '../../nexxo_native.node';
+/**
+ * Unambiguous cache keys that fold in config, environment and Nexxo version,
+ * and the helpers to fingerprint configs and split keys back into their parts
+ */
+export { CacheKeyBuilder, decodeCacheKey, fingerprintConfig };
 
-export { CacheStats };
+// Types of the native crate that builds nexxo_native.node
+import type {
+    BuildCache as NativeBuildCacheType,
+    BuildCacheOptions,
+    CacheAccessMode,
+    CacheCompression,
+    CacheKeyOptions,
+    CacheLookup,
+    CacheRecovery,
+    CacheSnapshot,
+    CacheStats,
+    CacheTransaction,
+    CheckpointInfo,
+    CompactProgress,
+    CompressionOptions,
+    DecodedCacheKey,
+    EntryMetadata,
+    ExplainReport,
+    ExportReport,
+    ImportReport,
+    KeyDifference,
+    LookupOutcome,
+    MissExplanation,
+    MissReason,
+    NamespaceStats,
+    RemoteCacheOptions,
+    RemoteCacheStats,
+    TargetStats,
+    VerifyReport,
+} from '../../native/index.js';
+
+export {
+    BuildCacheOptions,
+    CacheAccessMode,
+    CacheCompression,
+    CacheKeyOptions,
+    CacheLookup,
+    CacheRecovery,
+    CacheSnapshot,
+    CacheStats,
+    CacheTransaction,
+    CheckpointInfo,
+    CompactProgress,
+    CompressionOptions,
+    DecodedCacheKey,
+    EntryMetadata,
+    ExplainReport,
+    ExportReport,
+    ImportReport,
+    KeyDifference,
+    LookupOutcome,
+    MissExplanation,
+    MissReason,
+    NamespaceStats,
+    RemoteCacheOptions,
+    RemoteCacheStats,
+    TargetStats,
+    VerifyReport,
+};
 
 /**
  * RocksDB-based persistent build cache
@@ -19,6 +81,7 @@ export { CacheStats };
  * - Automatic compaction and cleanup
  * - Multi-target support (dev/prod/lib)
  * - Cache warming and invalidation
+ * - Optional remote HTTP tier (Bazel or Turborepo protocol)
  * 
  * @example
  * ```ts
@@ -38,17 +101,46 @@ export { CacheStats };
 export class BuildCache {
     private cache: NativeBuildCacheType;
 
-    constructor(cachePath: string) {
-        this.cache = new NativeBuildCache(cachePath);
+    constructor(cachePath: string, options?: BuildCacheOptions) {
+        this.cache = new NativeBuildCache(cachePath, options);
     }
 
     /**
-     * Get a value from the cache
+     * Get a value from the local cache, never reading through to the remote cache
      */
     get(key: string): string | null {
         return this.cache.get(key) || null;
     }
 
+    /**
+     * Get a value off the JS thread, reading through to the remote cache on a local miss
+     */
+    async getAsync(key: string): Promise<string | null> {
+        return (await this.cache.getAsync(key)) || null;
+    }
+
+    /**
+     * Get many values from the local cache at once, in the order of `keys`
+     */
+    multiGet(keys: string[]): Array<string | null> {
+        return this.cache.multiGet(keys).map((value) => value ?? null);
+    }
+
+    /**
+     * Async version of `multiGet` that reads through to the remote cache on local misses
+     */
+    async multiGetAsync(keys: string[]): Promise<Array<string | null>> {
+        return (await this.cache.multiGetAsync(keys)).map((value) => value ?? null);
+    }
+
+    /**
+     * Take a consistent, read-only view of the cache for the rest of a build.
+     * Throws unless the cache uses the RocksDB backend.
+     */
+    snapshot(): CacheSnapshot {
+        return this.cache.snapshot();
+    }
+
     /**
      * Set a value in the cache
      */
@@ -56,6 +148,13 @@ export class BuildCache {
         this.cache.set(key, value);
     }
 
+    /**
+     * Set a value off the JS thread
+     */
+    async setAsync(key: string, value: string): Promise<void> {
+        await this.cache.setAsync(key, value);
+    }
+
     /**
      * Delete a value from the cache
      */
@@ -70,6 +169,62 @@ export class BuildCache {
         return this.cache.has(key);
     }
 
+    /**
+     * Get the metadata stored with an entry, without counting a hit or refreshing its access time
+     */
+    getEntryMetadata(key: string): EntryMetadata | null {
+        return this.cache.getEntryMetadata(key);
+    }
+
+    /**
+     * Entries dropped on open because another schema or producer version wrote them
+     */
+    get invalidatedEntries(): number {
+        return this.cache.invalidatedEntries;
+    }
+
+    /**
+     * How the cache recovered from a database it could not open, if it had to
+     */
+    get recovery(): CacheRecovery | null {
+        return this.cache.recovery;
+    }
+
+    /**
+     * Turn lookup tracing on or off. Lookups already traced are kept.
+     */
+    setTracing(enabled: boolean): void {
+        this.cache.setTracing(enabled);
+    }
+
+    /**
+     * Whether lookups are being traced
+     */
+    get tracing(): boolean {
+        return this.cache.tracing;
+    }
+
+    /**
+     * Traced lookups with an explanation for each miss, optionally only those for `key`
+     */
+    explain(key?: string): ExplainReport {
+        return this.cache.explain(key);
+    }
+
+    /**
+     * Forget all traced lookups
+     */
+    clearTrace(): void {
+        this.cache.clearTrace();
+    }
+
+    /**
+     * The native cache, for native APIs that take one such as `traceCache`
+     */
+    get native(): NativeBuildCacheType {
+        return this.cache;
+    }
+
     /**
      * Batch set multiple key-value pairs
      */
@@ -77,6 +232,20 @@ export class BuildCache {
         this.cache.batchSet(entries);
     }
 
+    /**
+     * Batch set multiple key-value pairs off the JS thread
+     */
+    async batchSetAsync(entries: Record<string, string>): Promise<void> {
+        await this.cache.batchSetAsync(entries);
+    }
+
+    /**
+     * Start staging writes for a build; nothing is visible until the transaction is committed
+     */
+    beginTransaction(): CacheTransaction {
+        return this.cache.beginTransaction();
+    }
+
     /**
      * Clear all entries for a specific target (dev/prod/lib)
      */
@@ -92,12 +261,79 @@ export class BuildCache {
     }
 
     /**
-     * Get cache statistics
+     * Clear all cache entries off the JS thread, resolving to the number removed
+     */
+    async clearAllAsync(): Promise<number> {
+        return this.cache.clearAllAsync();
+    }
+
+    /**
+     * Get cache statistics, broken down by namespace and target.
+     * On the RocksDB backend entry counts and sizes are estimates, so this never scans
+     * the database; the other backends count by scanning every entry.
      */
     getStats(): CacheStats {
         return this.cache.getStats();
     }
 
+    /**
+     * Wait for queued remote uploads; false if some were still pending at the timeout
+     */
+    flushRemote(timeoutMs?: number): boolean {
+        return this.cache.flushRemote(timeoutMs);
+    }
+
+    /**
+     * Get remote cache statistics, if a remote cache is configured
+     */
+    getRemoteStats(): RemoteCacheStats | null {
+        return this.cache.getRemoteStats();
+    }
+
+    /**
+     * Export the selected namespaces (all when omitted) to a portable archive
+     */
+    export(path: string, namespaces?: string[]): ExportReport {
+        return this.cache.export(path, namespaces);
+    }
+
+    /**
+     * Merge an archive into the cache, replacing conflicting local values only with `overwrite`
+     */
+    import(path: string, overwrite?: boolean): ImportReport {
+        return this.cache.import(path, overwrite);
+    }
+
+    /**
+     * Check every entry's checksum, removing the corrupt ones
+     */
+    verify(): VerifyReport {
+        return this.cache.verify();
+    }
+
+    /**
+     * Take a checkpoint of the cache in `dir`, which must not exist yet.
+     * A relative `dir` is placed under `{cachePath}.checkpoints`.
+     */
+    checkpoint(dir: string): CheckpointInfo {
+        return this.cache.checkpoint(dir);
+    }
+
+    /**
+     * Checkpoints under `{cachePath}.checkpoints`, oldest first
+     */
+    listCheckpoints(): CheckpointInfo[] {
+        return this.cache.listCheckpoints();
+    }
+
+    /**
+     * Replace the contents of the cache with a checkpoint taken by `checkpoint`.
+     * Fails while snapshots, open transactions or async calls are using the cache.
+     */
+    restore(dir: string): void {
+        this.cache.restore(dir);
+    }
+
     /**
      * Compact the database to reclaim space
      */
@@ -105,6 +341,27 @@ export class BuildCache {
         this.cache.compact();
     }
 
+    /**
+     * Compact off the JS thread, reporting progress once per namespace
+     */
+    async compactAsync(onProgress?: (progress: CompactProgress) => void): Promise<void> {
+        await this.cache.compactAsync(onProgress);
+    }
+
+    /**
+     * Mode the cache was opened in; `Auto` resolves to Primary or Secondary
+     */
+    get accessMode(): CacheAccessMode {
+        return this.cache.accessMode;
+    }
+
+    /**
+     * Catch up with the primary's writes (Secondary instances only)
+     */
+    catchUp(): void {
+        this.cache.catchUp();
+    }
+
     /**
      * Close the cache (cleanup)
      */
@@ -114,7 +371,8 @@ export class BuildCache {
 }
 
 /**
- * Create cache key for input fingerprint
+ * Create cache key for input fingerprint.
+ * Prefer `CacheKeyBuilder`, whose keys stay unambiguous when paths contain `:`.
  */
 export function createInputKey(filePath: string, contentHash: string): string {
     return `input:${filePath}:${contentHash}`;
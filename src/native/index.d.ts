@@ -10,8 +10,30 @@ export interface CacheStats {
     sizeBytes: number;
 }
 
+export declare const enum BuildStage {
+    Init = 'Init',
+    Graph = 'Graph',
+    Plan = 'Plan',
+    DeterminismCheck = 'DeterminismCheck',
+    Execute = 'Execute',
+    Emit = 'Emit',
+}
+
+export declare const enum EventLevel {
+    Debug = 'Debug',
+    Info = 'Info',
+    Warn = 'Warn',
+    Error = 'Error',
+}
+
+export interface EventFilter {
+    stages?: BuildStage[];
+    minLevel?: EventLevel;
+}
+
 export interface BuildEvent {
-    stage: string;
+    stage: BuildStage;
+    level: EventLevel;
     message: string;
     timestamp: number;
     durationMs?: number;
@@ -42,8 +64,8 @@ export class BuildCache {
 
 export class BuildOrchestrator {
     constructor(parallelism?: number);
-    logEvent(stage: string, message: string, durationMs?: number): Promise<void>;
-    getEvents(): Promise<BuildEvent[]>;
+    logEvent(stage: BuildStage | string, message: string, durationMs?: number, level?: EventLevel): Promise<void>;
+    getEvents(filter?: EventFilter): Promise<BuildEvent[]>;
     clearEvents(): Promise<void>;
     executeParallel(taskCount: number): Promise<OrchestratorStats>;
     processParallelSync(items: string[]): string[];
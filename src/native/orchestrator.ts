@@ -7,17 +7,49 @@ const nativeModule = require('../../nexxo_native.node');
 
 const {
     BuildOrchestrator: NativeBuildOrchestrator,
+    EventLevel: EventLevelValues,
+    NativeTaskKind: NativeTaskKindValues,
     getOptimalParallelism,
     benchmarkParallelism
 } = nativeModule;
 
+// Types of the native crate that builds nexxo_native.node
 import type {
     BuildOrchestrator as NativeBuildOrchestratorType,
     BuildEvent,
-    OrchestratorStats
-} from '../../nexxo_native.node';
+    OrchestratorStats,
+    BuildReport,
+    BuildStage,
+    DeterminismMismatch,
+    DeterminismReport,
+    EventFilter,
+    EventLevel as EventLevelType,
+    LookupOutcome,
+    NativeTaskKind as NativeTaskKindType,
+    OtlpExporterOptions,
+    RetryPolicy,
+    RunOptions,
+    StageEvent,
+    StageTiming,
+    TaskFailure,
+    TaskInvocation,
+    TaskSpec
+} from '../../native/index.js';
+import type { BuildCache } from './cache.js';
 
-export { BuildEvent, OrchestratorStats };
+export { BuildEvent, OrchestratorStats, BuildReport, BuildStage, DeterminismMismatch, DeterminismReport, EventFilter, OtlpExporterOptions, RetryPolicy, RunOptions, StageEvent, StageTiming, TaskFailure, TaskInvocation, TaskSpec };
+
+/**
+ * What a native task does, for `TaskSpec.kind`
+ */
+export const NativeTaskKind: typeof NativeTaskKindType = NativeTaskKindValues;
+export type NativeTaskKind = NativeTaskKindType;
+
+/**
+ * Severity of a build event, for `logEvent` and `EventFilter.minLevel`
+ */
+export const EventLevel: typeof EventLevelType = EventLevelValues;
+export type EventLevel = EventLevelType;
 
 /**
  * Tokio-based parallel build orchestrator
@@ -32,36 +64,56 @@ export { BuildEvent, OrchestratorStats };
  * ```ts
  * const orchestrator = new BuildOrchestrator();
  * 
- * // Execute tasks in parallel
- * const stats = await orchestrator.executeParallel(100);
- * console.log(`Completed ${stats.completedTasks} tasks in ${stats.totalDurationMs}ms`);
+ * // Run native and JS tasks in dependency order
+ * const report = await orchestrator.run([
+ *   { id: 'a', kind: NativeTaskKind.HashFile, input: 'src/a.ts' },
+ *   { id: 'compile', dependencies: ['a'], callback: async ({ inputs }) => compile(inputs.a) },
+ *   { id: 'bundle', kind: NativeTaskKind.Hash, dependencies: ['compile'] },
+ * ], { maxJsConcurrency: 2 });
+ * console.log(`${report.succeeded} tasks succeeded in ${report.durationMs}ms`);
  * 
  * // Generate stable IDs
  * const id = orchestrator.generateStableId(fileContent, 'module');
  * 
- * // Get events
+ * // Get events, or be told about them as they happen
  * const events = await orchestrator.getEvents();
+ * orchestrator.subscribe((event) => console.log(event.message), { minLevel: EventLevel.Warn });
  * ```
  */
 export class BuildOrchestrator {
     private orchestrator: NativeBuildOrchestratorType;
 
-    constructor(parallelism?: number) {
-        this.orchestrator = new NativeBuildOrchestrator(parallelism);
+    constructor(parallelism?: number, eventHistory?: number) {
+        this.orchestrator = new NativeBuildOrchestrator(parallelism, eventHistory);
+    }
+
+    /**
+     * Log a build event (default level: Info). `stage` is a `BuildStage` or its
+     * name in any case, such as 'execute'; unknown stages are rejected.
+     */
+    async logEvent(stage: BuildStage | string, message: string, durationMs?: number, level?: EventLevel): Promise<void> {
+        await this.orchestrator.logEvent(stage, message, durationMs, level);
+    }
+
+    /**
+     * Get the most recent events, optionally filtered
+     */
+    async getEvents(filter?: EventFilter): Promise<BuildEvent[]> {
+        return await this.orchestrator.getEvents(filter);
     }
 
     /**
-     * Log a build event
+     * Receive events as they are logged; returns an id for `unsubscribe`
      */
-    async logEvent(stage: string, message: string, durationMs?: number): Promise<void> {
-        await this.orchestrator.logEvent(stage, message, durationMs);
+    subscribe(callback: (event: BuildEvent) => void, filter?: EventFilter): number {
+        return this.orchestrator.subscribe(callback, filter);
     }
 
     /**
-     * Get all logged events
+     * Stop a subscription
      */
-    async getEvents(): Promise<BuildEvent[]> {
-        return await this.orchestrator.getEvents();
+    unsubscribe(id: number): boolean {
+        return this.orchestrator.unsubscribe(id);
     }
 
     /**
@@ -72,7 +124,107 @@ export class BuildOrchestrator {
     }
 
     /**
-     * Execute tasks in parallel
+     * Run a graph of tasks in dependency order with maximum parallelism
+     */
+    async run(tasks: TaskSpec[], options?: RunOptions): Promise<BuildReport> {
+        return await this.orchestrator.run(tasks, options);
+    }
+
+    /**
+     * A fresh `build-{n}` id to pass as `RunOptions.buildId`, so the build can
+     * be cancelled or prioritized while `run` is pending
+     */
+    nextBuildId(): string {
+        return this.orchestrator.nextBuildId();
+    }
+
+    /**
+     * Cancel a running build; its `run` call resolves with status Cancelled
+     */
+    cancel(buildId: string): boolean {
+        return this.orchestrator.cancel(buildId);
+    }
+
+    /**
+     * Move a queued task and its dependencies ahead of other work, e.g. for a
+     * module the browser is waiting on
+     */
+    prioritize(buildId: string, taskId: string, priority: number): boolean {
+        return this.orchestrator.prioritize(buildId, taskId, priority);
+    }
+
+    /**
+     * Call `hook` whenever a build enters a stage; the build waits for it
+     */
+    onStageEnter(hook: (event: StageEvent) => void | Promise<void>): void {
+        this.orchestrator.onStageEnter(hook);
+    }
+
+    /**
+     * Call `hook` whenever a build leaves a stage; the build waits for it
+     */
+    onStageLeave(hook: (event: StageEvent) => void | Promise<void>): void {
+        this.orchestrator.onStageLeave(hook);
+    }
+
+    /**
+     * Remove all stage hooks
+     */
+    clearStageHooks(): void {
+        this.orchestrator.clearStageHooks();
+    }
+
+    /**
+     * Start or stop recording spans for `exportTrace`
+     */
+    setTracing(enabled: boolean): void {
+        this.orchestrator.setTracing(enabled);
+    }
+
+    /**
+     * Record a cache lookup on the timeline's cache tracks
+     */
+    traceCacheLookup(key: string, outcome: LookupOutcome, durationMs?: number): void {
+        this.orchestrator.traceCacheLookup(key, outcome, durationMs);
+    }
+
+    /**
+     * Draw every lookup through `cache` on the timeline's cache tracks, or stop with `enabled: false`
+     */
+    traceCache(cache: BuildCache, enabled?: boolean): void {
+        this.orchestrator.traceCache(cache.native, enabled);
+    }
+
+    /**
+     * Export recorded spans as Chrome Trace Event JSON, optionally to a file
+     */
+    exportTrace(path?: string): string {
+        return this.orchestrator.exportTrace(path);
+    }
+
+    /**
+     * Drop all recorded spans
+     */
+    clearTrace(): void {
+        this.orchestrator.clearTrace();
+    }
+
+    /**
+     * Send finished builds to an OpenTelemetry collector, or stop with `null`
+     */
+    setOtlpExporter(options: OtlpExporterOptions | null): void {
+        this.orchestrator.setOtlpExporter(options);
+    }
+
+    /**
+     * Wait for finished builds to reach the collector; false if some were still queued at the timeout
+     */
+    async flushOtlp(timeoutMs?: number): Promise<boolean> {
+        return await this.orchestrator.flushOtlp(timeoutMs);
+    }
+
+    /**
+     * Benchmark the runtime with `taskCount` independent 10ms tasks
      */
     async executeParallel(taskCount: number): Promise<OrchestratorStats> {
         return await this.orchestrator.executeParallel(taskCount);
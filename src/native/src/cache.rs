@@ -1,5 +1,7 @@
 use dashmap::DashMap;
+use nexxo_storage::{BackendKind, Storage};
 use serde::{Serialize, Deserialize};
+use std::path::Path;
 use std::sync::Arc;
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -9,34 +11,85 @@ pub struct CacheEntry {
     // Add metadata like dependencies, timestamps, etc.
 }
 
+/// In-memory asset cache, optionally layered as an L1 over persistent storage.
+/// Reads fall through to the persistent tier on a miss; writes go to both.
 pub struct AssetCache {
     store: Arc<DashMap<String, CacheEntry>>,
+    persistent: Option<Box<dyn Storage>>,
 }
 
 impl AssetCache {
     pub fn new() -> Self {
         AssetCache {
             store: Arc::new(DashMap::new()),
+            persistent: None,
+        }
+    }
+
+    /// Layer the in-memory cache over `persistent`
+    pub fn with_storage(persistent: Box<dyn Storage>) -> Self {
+        AssetCache {
+            store: Arc::new(DashMap::new()),
+            persistent: Some(persistent),
+        }
+    }
+
+    /// Pick the persistent tier from `NEXXO_CACHE_BACKEND` / `NEXXO_CACHE_DIR`.
+    /// Without `NEXXO_CACHE_BACKEND` the cache stays memory-only.
+    pub fn from_env(default_dir: &Path) -> std::io::Result<Self> {
+        if std::env::var_os(nexxo_storage::BACKEND_ENV).is_none() {
+            return Ok(Self::new());
+        }
+        match nexxo_storage::from_env(BackendKind::Memory, default_dir)? {
+            // A memory tier under a memory L1 only doubles the footprint
+            storage if storage.name() == "memory" => Ok(Self::new()),
+            storage => Ok(Self::with_storage(storage)),
         }
     }
 
     pub fn get(&self, key: &str) -> Option<CacheEntry> {
-        self.store.get(key).map(|v| v.clone())
+        if let Some(entry) = self.store.get(key) {
+            return Some(entry.clone());
+        }
+
+        let persistent = self.persistent.as_ref()?;
+        let bytes = persistent.get(key.as_bytes()).ok()??;
+        let entry: CacheEntry = bincode::deserialize(&bytes).ok()?;
+        self.store.insert(key.to_string(), entry.clone());
+        Some(entry)
     }
 
     pub fn insert(&self, key: String, entry: CacheEntry) {
+        if let Some(persistent) = &self.persistent {
+            // The persistent tier is best effort; the in-memory copy still serves this process
+            if let Ok(bytes) = bincode::serialize(&entry) {
+                let _ = persistent.put(key.as_bytes(), &bytes);
+            }
+        }
         self.store.insert(key, entry);
     }
-    
+
+    /// Number of entries held in memory
     pub fn len(&self) -> usize {
         self.store.len()
     }
-    
+
     pub fn clear(&self) {
         self.store.clear();
+        if let Some(persistent) = &self.persistent {
+            let _ = persistent.clear();
+        }
     }
 
     pub fn remove(&self, key: &str) {
         self.store.remove(key);
+        if let Some(persistent) = &self.persistent {
+            let _ = persistent.delete(key.as_bytes());
+        }
+    }
+
+    /// Name of the persistent backend, if any
+    pub fn backend(&self) -> Option<&'static str> {
+        self.persistent.as_ref().map(|storage| storage.name())
     }
 }
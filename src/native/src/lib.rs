@@ -4,6 +4,7 @@ use napi_derive::napi;
 use rayon::prelude::*;
 use std::sync::Arc;
 use std::fs;
+use std::path::Path;
 
 pub mod pipeline;
 pub mod cache;
@@ -26,16 +27,19 @@ pub struct NativeWorker {
 #[napi]
 impl NativeWorker {
   #[napi(constructor)]
-  pub fn new(pool_size: Option<u32>) -> Self {
+  pub fn new(pool_size: Option<u32>) -> napi::Result<Self> {
     let size = pool_size.unwrap_or(4);
+    // Memory-only unless NEXXO_CACHE_BACKEND selects a persistent tier
+    let cache = AssetCache::from_env(Path::new(".nexxo/asset-cache"))
+      .map_err(|e| napi::Error::from_reason(format!("Failed to open asset cache: {}", e)))?;
     
-    NativeWorker {
+    Ok(NativeWorker {
       pool_size: size,
-      cache: Arc::new(AssetCache::new()),
+      cache: Arc::new(cache),
       graph: Arc::new(DependencyGraph::new()),
       resolver: Arc::new(Resolver::new()),
       transformer: Arc::new(Transformer::new()),
-    }
+    })
   }
 
   #[napi]
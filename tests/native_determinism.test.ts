@@ -0,0 +1,58 @@
+/**
+ * Determinism Check Tests
+ * Runs BuildOrchestrator with `checkDeterminism` against JS tasks whose
+ * output does or does not depend on the order tasks run in
+ */
+
+import { describe, it, expect } from '@jest/globals';
+import { createRequire } from 'module';
+
+const require = createRequire(import.meta.url);
+const { BuildOrchestrator } = require('../nexxo_native.node');
+
+describe('BuildOrchestrator determinism check', () => {
+    it('reports the first task whose output depends on run order', async () => {
+        const orchestrator = new BuildOrchestrator(1);
+
+        // Each pair of runs tells its first task apart from its second
+        let runs = 0;
+        const orderDependent = () => (runs++ % 2 === 0 ? 'ran first' : 'ran second');
+
+        const report = await orchestrator.run(
+            [
+                { id: 'styles', module: 'src/styles.css', callback: orderDependent },
+                { id: 'app', module: 'src/App.tsx', callback: orderDependent },
+                { id: 'bundle', dependencies: ['styles', 'app'], kind: 'Hash', input: 'bundle' },
+            ],
+            { checkDeterminism: true, maxConcurrency: 1, maxJsConcurrency: 1 },
+        );
+
+        expect(report.status).toBe('Nondeterministic');
+        expect(report.determinism.deterministic).toBe(false);
+        expect(report.determinism.checked).toBe(3);
+        expect(report.determinism.firstDivergence).toMatchObject({
+            taskId: 'styles',
+            module: 'src/styles.css',
+            stage: 'Execute',
+        });
+        expect(report.determinism.firstDivergence.first).not.toBe(report.determinism.firstDivergence.second);
+        // The bundle only differs because it builds on them
+        expect(report.determinism.mismatches.map((mismatch: any) => mismatch.taskId)).toEqual(['styles', 'app', 'bundle']);
+    });
+
+    it('passes tasks whose output only depends on their inputs', async () => {
+        const orchestrator = new BuildOrchestrator(2);
+
+        const report = await orchestrator.run(
+            [
+                { id: 'styles', module: 'src/styles.css', callback: ({ id }: any) => `compiled ${id}` },
+                { id: 'bundle', dependencies: ['styles'], kind: 'Hash', input: 'bundle' },
+            ],
+            { checkDeterminism: true },
+        );
+
+        expect(report.status).toBe('Succeeded');
+        expect(report.determinism).toMatchObject({ deterministic: true, checked: 2, mismatches: [] });
+        expect(report.determinism.firstDivergence).toBeUndefined();
+    });
+});
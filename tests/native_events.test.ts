@@ -0,0 +1,63 @@
+/**
+ * Build Event Subscription Tests
+ * Subscribes to a BuildOrchestrator's events with filters and checks what
+ * each subscriber receives while `run` works through the build stages
+ */
+
+import { describe, it, expect } from '@jest/globals';
+import { createRequire } from 'module';
+
+const require = createRequire(import.meta.url);
+const { BuildOrchestrator } = require('../nexxo_native.node');
+
+// Subscribers are called from the JS event loop, after the events are logged
+const drain = () => new Promise((resolve) => setTimeout(resolve, 20));
+
+describe('BuildOrchestrator event subscriptions', () => {
+    it('delivers only the stages and levels a subscriber asked for during run()', async () => {
+        const orchestrator = new BuildOrchestrator(2);
+        const stageEvents: any[] = [];
+        const warnings: any[] = [];
+        const everything: any[] = [];
+        const stagesId = orchestrator.subscribe((event: any) => stageEvents.push(event), {
+            stages: ['Graph', 'Emit'],
+            minLevel: 'Info',
+        });
+        orchestrator.subscribe((event: any) => warnings.push(event), { minLevel: 'Warn' });
+        orchestrator.subscribe((event: any) => everything.push(event));
+
+        const report = await orchestrator.run([
+            { id: 'a', kind: 'Hash', input: 'a' },
+            { id: 'b', dependencies: ['a'], kind: 'Hash', input: 'b' },
+        ]);
+        await drain();
+
+        expect(report.status).toBe('Succeeded');
+        expect(stageEvents.map((event) => [event.stage, event.level])).toEqual([
+            ['Graph', 'Info'],
+            ['Emit', 'Info'],
+        ]);
+        expect(stageEvents[0].message).toContain('finished stage Graph');
+        expect(warnings).toEqual([]);
+        // Unfiltered, a subscriber also sees each stage being entered
+        expect(everything.map((event) => event.stage)).toEqual(
+            expect.arrayContaining(['Init', 'Graph', 'Plan', 'Execute', 'Emit']),
+        );
+        expect(everything.some((event) => event.level === 'Debug')).toBe(true);
+
+        expect(orchestrator.unsubscribe(stagesId)).toBe(true);
+        expect(orchestrator.unsubscribe(stagesId)).toBe(false);
+        await orchestrator.run([{ id: 'c', kind: 'Hash', input: 'c' }]);
+        await drain();
+        expect(stageEvents).toHaveLength(2);
+    });
+
+    it('returns stored events through the same filter', async () => {
+        const orchestrator = new BuildOrchestrator(1);
+        await orchestrator.run([{ id: 'a', kind: 'Hash', input: 'a' }]);
+        await orchestrator.logEvent('execute', 'custom warning', undefined, 'Warn');
+
+        const executeWarnings = await orchestrator.getEvents({ stages: ['Execute'], minLevel: 'Warn' });
+        expect(executeWarnings.map((event: any) => event.message)).toEqual(['custom warning']);
+    });
+});
@@ -0,0 +1,216 @@
+/**
+ * OTLP Export Tests
+ * Runs BuildOrchestrator against an in-process stand-in for an OpenTelemetry
+ * collector's OTLP/HTTP receiver
+ *
+ * Builds are sent off the JS thread after `run` resolves, so the stand-in can
+ * live on the main event loop and tests wait for it with `flushOtlp`.
+ */
+
+import { describe, it, expect, beforeAll, afterAll, beforeEach } from '@jest/globals';
+import { createRequire } from 'module';
+import http from 'http';
+import type { AddressInfo } from 'net';
+
+const require = createRequire(import.meta.url);
+const { BuildOrchestrator } = require('../nexxo_native.node');
+
+interface CollectedRequest {
+    method: string;
+    path: string;
+    contentType: string | null;
+    custom: string | null;
+    body: any;
+}
+
+function attributes(list: Array<{ key: string; value: Record<string, unknown> }>): Record<string, unknown> {
+    return Object.fromEntries(list.map(({ key, value }) => [key, Object.values(value)[0]]));
+}
+
+describe('BuildOrchestrator OTLP export', () => {
+    const requests: CollectedRequest[] = [];
+    let server: http.Server;
+    let endpoint: string;
+
+    beforeAll(async () => {
+        server = http.createServer((req, res) => {
+            const chunks: Buffer[] = [];
+            req.on('data', (chunk) => chunks.push(chunk));
+            req.on('end', () => {
+                requests.push({
+                    method: req.method!,
+                    path: req.url!,
+                    contentType: req.headers['content-type'] || null,
+                    custom: (req.headers['x-nexxo-ci'] as string) || null,
+                    body: JSON.parse(Buffer.concat(chunks).toString()),
+                });
+                res.setHeader('Content-Type', 'application/json');
+                res.end('{}');
+            });
+        });
+        await new Promise<void>((resolve) => server.listen(0, '127.0.0.1', resolve));
+        endpoint = `http://127.0.0.1:${(server.address() as AddressInfo).port}`;
+    });
+
+    afterAll(async () => {
+        await new Promise((resolve) => server.close(resolve));
+    });
+
+    beforeEach(() => {
+        requests.length = 0;
+    });
+
+    it('sends stages and tasks as one trace with resource attributes', async () => {
+        const orchestrator = new BuildOrchestrator(2);
+        orchestrator.setOtlpExporter({
+            endpoint,
+            headers: { 'x-nexxo-ci': '1' },
+            project: 'storefront',
+            target: 'prod',
+            gitSha: '0123abcd',
+        });
+
+        const report = await orchestrator.run(
+            [
+                { id: 'plan-routes', stage: 'Plan', kind: 'Hash', input: 'routes' },
+                { id: 'compile', dependencies: ['plan-routes'], kind: 'Hash', input: 'app' },
+                { id: 'emit', dependencies: ['compile'], stage: 'Emit' },
+            ],
+            { buildId: 'otlp-tree' },
+        );
+        expect(report.status).toBe('Succeeded');
+
+        expect(await orchestrator.flushOtlp()).toBe(true);
+        expect(requests).toHaveLength(1);
+        const [request] = requests;
+        expect(request.method).toBe('POST');
+        expect(request.path).toBe('/v1/traces');
+        expect(request.contentType).toBe('application/json');
+        expect(request.custom).toBe('1');
+
+        const [resourceSpans] = request.body.resourceSpans;
+        expect(attributes(resourceSpans.resource.attributes)).toMatchObject({
+            'service.name': 'nexxo',
+            'nexxo.project': 'storefront',
+            'nexxo.target': 'prod',
+            'vcs.ref.head.revision': '0123abcd',
+        });
+
+        const spans: any[] = resourceSpans.scopeSpans[0].spans;
+        const byName = new Map(spans.map((span) => [span.name, span]));
+        const build = byName.get('build');
+        expect(build.parentSpanId).toBeUndefined();
+        expect(attributes(build.attributes)['nexxo.build.id']).toBe('otlp-tree');
+        expect(build.status.code).toBe(1);
+
+        for (const timing of report.stages) {
+            expect(byName.get(timing.stage).parentSpanId).toBe(build.spanId);
+        }
+        expect(byName.get('plan-routes').parentSpanId).toBe(byName.get('Plan').spanId);
+        expect(byName.get('compile').parentSpanId).toBe(byName.get('Execute').spanId);
+        expect(byName.get('emit').parentSpanId).toBe(byName.get('Emit').spanId);
+
+        expect(new Set(spans.map((span) => span.traceId)).size).toBe(1);
+        expect(new Set(spans.map((span) => span.spanId)).size).toBe(spans.length);
+        for (const span of spans) {
+            expect(BigInt(span.endTimeUnixNano) >= BigInt(span.startTimeUnixNano)).toBe(true);
+        }
+    });
+
+    it('marks failed tasks and builds with an error status', async () => {
+        const orchestrator = new BuildOrchestrator(2);
+        orchestrator.setOtlpExporter({ endpoint: `${endpoint}/v1/traces` });
+
+        const report = await orchestrator.run([
+            { id: 'broken', kind: 'HashFile', input: '/definitely/not/here.ts' },
+            { id: 'after', dependencies: ['broken'] },
+        ]);
+        expect(report.status).toBe('Failed');
+
+        expect(await orchestrator.flushOtlp()).toBe(true);
+        expect(requests).toHaveLength(1);
+        expect(requests[0].path).toBe('/v1/traces');
+        const spans: any[] = requests[0].body.resourceSpans[0].scopeSpans[0].spans;
+        const byName = new Map(spans.map((span) => [span.name, span]));
+        expect(byName.get('build').status.code).toBe(2);
+        expect(byName.get('broken').status.code).toBe(2);
+        expect(byName.get('broken').status.message).toMatch(/not\/here/);
+        // Skipped tasks never started, so they have no span
+        expect(byName.has('after')).toBe(false);
+    });
+
+    it('marks cancelled builds with an error status', async () => {
+        const orchestrator = new BuildOrchestrator(2);
+        orchestrator.setOtlpExporter({ endpoint });
+
+        const report = await orchestrator.run(
+            [
+                { id: 'first', callback: () => void orchestrator.cancel('otlp-cancelled') },
+                { id: 'second', dependencies: ['first'] },
+            ],
+            { buildId: 'otlp-cancelled' },
+        );
+        expect(report.status).toBe('Cancelled');
+
+        expect(await orchestrator.flushOtlp()).toBe(true);
+        const spans: any[] = requests[0].body.resourceSpans[0].scopeSpans[0].spans;
+        const build = spans.find((span) => span.name === 'build');
+        expect(build.status).toEqual({ code: 2, message: 'Build was cancelled' });
+        expect(attributes(build.attributes)['nexxo.build.status']).toBe('Cancelled');
+    });
+
+    it('does not wait for the collector before resolving run', async () => {
+        const orchestrator = new BuildOrchestrator(2);
+        orchestrator.setOtlpExporter({ endpoint });
+
+        // The stand-in shares this event loop, so it cannot have answered yet
+        await orchestrator.run([{ id: 'a' }], { buildId: 'queued' });
+        expect(requests).toHaveLength(0);
+
+        expect(await orchestrator.flushOtlp()).toBe(true);
+        expect(requests).toHaveLength(1);
+    });
+
+    it('stops exporting once the exporter is removed', async () => {
+        const orchestrator = new BuildOrchestrator(2);
+        const buildIds = () =>
+            requests.map((request) => {
+                const build = request.body.resourceSpans[0].scopeSpans[0].spans.find((span: any) => span.name === 'build');
+                return attributes(build.attributes)['nexxo.build.id'];
+            });
+
+        orchestrator.setOtlpExporter({ endpoint });
+        await orchestrator.run([{ id: 'a' }], { buildId: 'before' });
+        expect(await orchestrator.flushOtlp()).toBe(true);
+        expect(buildIds()).toEqual(['before']);
+
+        orchestrator.setOtlpExporter(null);
+        await orchestrator.run([{ id: 'a' }], { buildId: 'while-removed' });
+        expect(await orchestrator.flushOtlp()).toBe(true);
+
+        // Only the builds run with an exporter set ever reach the collector
+        orchestrator.setOtlpExporter({ endpoint });
+        await orchestrator.run([{ id: 'a' }], { buildId: 'after' });
+        expect(await orchestrator.flushOtlp()).toBe(true);
+        expect(buildIds()).toEqual(['before', 'after']);
+    });
+
+    it('logs a warning instead of failing the build when the collector is down', async () => {
+        const orchestrator = new BuildOrchestrator(2);
+        orchestrator.setOtlpExporter({ endpoint: 'http://127.0.0.1:1', timeoutMs: 1000 });
+
+        const report = await orchestrator.run([{ id: 'a' }], { buildId: 'offline' });
+        expect(report.status).toBe('Succeeded');
+
+        expect(await orchestrator.flushOtlp()).toBe(true);
+        const warnings = await orchestrator.getEvents({ minLevel: 'Warn' });
+        expect(warnings.some((event: any) => /Failed to export build offline to OTLP/.test(event.message))).toBe(true);
+    });
+
+    it('rejects endpoints that are not http(s)', () => {
+        const orchestrator = new BuildOrchestrator(2);
+        expect(() => orchestrator.setOtlpExporter({ endpoint: 'grpc://localhost:4317' })).toThrow(
+            /OTLP endpoint must be http/,
+        );
+    });
+});
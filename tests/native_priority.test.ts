@@ -0,0 +1,58 @@
+/**
+ * Priority Scheduling Tests
+ * Raises a queued task of a running BuildOrchestrator build with `prioritize`
+ * and checks it jumps ahead of the tasks queued before it
+ */
+
+import { describe, it, expect } from '@jest/globals';
+import { createRequire } from 'module';
+
+const require = createRequire(import.meta.url);
+const { BuildOrchestrator } = require('../nexxo_native.node');
+
+async function until(condition: () => boolean): Promise<void> {
+    while (!condition()) {
+        await new Promise((resolve) => setTimeout(resolve, 5));
+    }
+}
+
+describe('BuildOrchestrator prioritize', () => {
+    it('starts a raised task before lower-priority tasks queued ahead of it', async () => {
+        const orchestrator = new BuildOrchestrator(1);
+        const buildId = orchestrator.nextBuildId();
+
+        const started: string[] = [];
+        let release!: () => void;
+        const blocked = new Promise<void>((resolve) => (release = resolve));
+        const record = async ({ id }: any) => {
+            started.push(id);
+            if (id === 'blocker') {
+                await blocked;
+            }
+            return id;
+        };
+
+        // Equal priorities start in submission order, so `urgent` would go last
+        const running = orchestrator.run(
+            ['blocker', 'first', 'second', 'urgent'].map((id) => ({ id, callback: record })),
+            { buildId, maxConcurrency: 1 },
+        );
+        await until(() => started.length === 1);
+        expect(orchestrator.prioritize(buildId, 'urgent', 10)).toBe(true);
+        // Keep the others queued a little longer, so their wait shows up
+        await new Promise((resolve) => setTimeout(resolve, 20));
+        release();
+        const report = await running;
+
+        expect(report.status).toBe('Succeeded');
+        expect(started).toEqual(['blocker', 'urgent', 'first', 'second']);
+
+        const queuedMs = Object.fromEntries(report.tasks.map((task: any) => [task.id, task.queuedMs]));
+        for (const id of ['urgent', 'first', 'second']) {
+            expect(queuedMs[id]).toBeGreaterThan(0);
+        }
+        expect(queuedMs.second).toBeGreaterThan(queuedMs.urgent);
+        expect(report.totalQueuedMs).toBeGreaterThan(0);
+        expect(orchestrator.prioritize(buildId, 'urgent', 20)).toBe(false);
+    });
+});
@@ -0,0 +1,279 @@
+/**
+ * Remote Cache Tier Tests
+ * Runs BuildCache against an in-process stand-in for a Bazel / Turborepo HTTP cache
+ *
+ * The stand-in server lives in a worker thread: `flushRemote` waits for
+ * uploads synchronously, so a server on the main event loop could never answer.
+ */
+
+import { describe, it, expect, beforeAll, afterAll } from '@jest/globals';
+import { createRequire } from 'module';
+import { Worker } from 'worker_threads';
+import fs from 'fs';
+import os from 'os';
+import path from 'path';
+
+const require = createRequire(import.meta.url);
+const { BuildCache } = require('../nexxo_native.node');
+
+const SERVER_SOURCE = `
+const http = require('http');
+const { parentPort, workerData } = require('worker_threads');
+
+const blobs = new Map();
+const requests = [];
+let inFlight = 0;
+let maxInFlight = 0;
+
+const server = http.createServer((req, res) => {
+    const url = new URL(req.url, 'http://localhost');
+    if (url.pathname === '/__requests') {
+        res.end(JSON.stringify({ requests, maxInFlight }));
+        return;
+    }
+    if (url.pathname === '/__reset') {
+        blobs.clear();
+        requests.length = 0;
+        maxInFlight = 0;
+        res.end('ok');
+        return;
+    }
+
+    const chunks = [];
+    req.on('data', (chunk) => chunks.push(chunk));
+    req.on('end', () => {
+        requests.push({
+            method: req.method,
+            path: url.pathname,
+            query: url.search,
+            authorization: req.headers['authorization'] || null,
+            custom: req.headers['x-nexxo-ci'] || null,
+        });
+        if (workerData.failAll) {
+            res.statusCode = 500;
+            res.end();
+            return;
+        }
+        if (req.method === 'PUT') {
+            inFlight++;
+            maxInFlight = Math.max(maxInFlight, inFlight);
+            setTimeout(() => {
+                inFlight--;
+                blobs.set(url.pathname, Buffer.concat(chunks));
+                res.statusCode = 200;
+                res.end();
+            }, workerData.putDelayMs || 0);
+            return;
+        }
+        const blob = blobs.get(url.pathname);
+        if (!blob) {
+            res.statusCode = 404;
+            res.end();
+            return;
+        }
+        res.end(blob);
+    });
+});
+
+server.listen(0, '127.0.0.1', () => parentPort.postMessage(server.address().port));
+parentPort.on('message', () => server.close(() => process.exit(0)));
+`;
+
+interface StandInServer {
+    url: string;
+    requests(): Promise<{ requests: Array<Record<string, string | null>>; maxInFlight: number }>;
+    reset(): Promise<void>;
+    stop(): Promise<void>;
+}
+
+async function startServer(options: { failAll?: boolean; putDelayMs?: number } = {}): Promise<StandInServer> {
+    const worker = new Worker(SERVER_SOURCE, { eval: true, workerData: options });
+    const port: number = await new Promise((resolve) => worker.once('message', resolve));
+    const url = `http://127.0.0.1:${port}`;
+    return {
+        url,
+        requests: async () => (await fetch(`${url}/__requests`)).json() as any,
+        reset: async () => {
+            await fetch(`${url}/__reset`);
+        },
+        stop: async () => {
+            worker.postMessage('stop');
+            await new Promise((resolve) => worker.once('exit', resolve));
+        },
+    };
+}
+
+function tempCacheDir(name: string): string {
+    return fs.mkdtempSync(path.join(os.tmpdir(), `nexxo-remote-${name}-`));
+}
+
+describe('BuildCache remote tier', () => {
+    let server: StandInServer;
+
+    beforeAll(async () => {
+        server = await startServer();
+    });
+
+    afterAll(async () => {
+        await server.stop();
+    });
+
+    it('uploads writes and reads them through from another machine (Bazel)', async () => {
+        await server.reset();
+        const remote = { url: server.url, token: 'secret', headers: { 'x-nexxo-ci': '1' } };
+
+        const producer = new BuildCache(tempCacheDir('producer'), { remote });
+        producer.set('input:src/App.tsx:abc123', 'compiled-app');
+        expect(producer.flushRemote(5000)).toBe(true);
+        expect(producer.getRemoteStats().uploads).toBe(1);
+
+        const consumer = new BuildCache(tempCacheDir('consumer'), { remote });
+        expect(await consumer.getAsync('input:src/App.tsx:abc123')).toBe('compiled-app');
+        expect(consumer.getRemoteStats().hits).toBe(1);
+
+        const { requests } = await server.requests();
+        const paths = requests.map((r) => `${r.method} ${(r.path as string).split('/')[1]}`);
+        expect(paths).toEqual(['PUT cas', 'PUT ac', 'GET ac', 'GET cas']);
+        expect(requests.every((r) => r.authorization === 'Bearer secret')).toBe(true);
+        expect(requests.every((r) => r.custom === '1')).toBe(true);
+    });
+
+    it('serves later reads from the local cache after a remote hit', async () => {
+        await server.reset();
+        const remote = { url: server.url };
+
+        const producer = new BuildCache(tempCacheDir('producer'), { remote });
+        producer.set('graph:deadbeef', 'graph-json');
+        producer.flushRemote(5000);
+
+        const consumer = new BuildCache(tempCacheDir('consumer'), { remote });
+        expect(await consumer.getAsync('graph:deadbeef')).toBe('graph-json');
+        expect(consumer.get('graph:deadbeef')).toBe('graph-json');
+        expect(consumer.getRemoteStats().hits).toBe(1);
+    });
+
+    it('speaks the Turborepo artifacts API', async () => {
+        await server.reset();
+        const remote = {
+            url: server.url,
+            protocol: 'Turborepo',
+            token: 'turbo-token',
+            teamId: 'team_123',
+            teamSlug: 'nexxo & co',
+        };
+
+        const producer = new BuildCache(tempCacheDir('turbo-producer'), { remote });
+        producer.set('artifact:prod:chunk-1', 'chunk-source');
+        producer.flushRemote(5000);
+
+        const consumer = new BuildCache(tempCacheDir('turbo-consumer'), { remote });
+        expect(await consumer.getAsync('artifact:prod:chunk-1')).toBe('chunk-source');
+
+        const { requests } = await server.requests();
+        expect(requests.map((r) => r.method)).toEqual(['PUT', 'GET']);
+        for (const request of requests) {
+            expect(request.path).toMatch(/^\/v8\/artifacts\/[0-9a-f]{64}$/);
+            expect(request.query).toBe('?teamId=team_123&slug=nexxo%20%26%20co');
+            expect(request.authorization).toBe('Bearer turbo-token');
+        }
+    });
+
+    it('reports a remote miss without touching local state', async () => {
+        await server.reset();
+        const cache = new BuildCache(tempCacheDir('miss'), { remote: { url: server.url } });
+
+        expect(await cache.getAsync('input:missing.ts:000')).toBeNull();
+        expect(cache.getRemoteStats().misses).toBe(1);
+        expect(cache.getStats().misses).toBe(1);
+    });
+
+    it('never uploads in read-only mode', async () => {
+        await server.reset();
+        const cache = new BuildCache(tempCacheDir('readonly'), { remote: { url: server.url, readOnly: true } });
+
+        cache.batchSet({ 'plan:dev:1': 'a', 'plan:dev:2': 'b' });
+        expect(cache.flushRemote(1000)).toBe(true);
+        expect(cache.get('plan:dev:1')).toBe('a');
+
+        const { requests } = await server.requests();
+        expect(requests).toHaveLength(0);
+    });
+
+    it('ignores remote entries written by another producer version', async () => {
+        await server.reset();
+        const remote = { url: server.url };
+
+        const producer = new BuildCache(tempCacheDir('old-producer'), { remote, producerVersion: 'nexxo@1.0.0' });
+        producer.set('input:src/App.tsx:abc123', 'old-output');
+        producer.flushRemote(5000);
+
+        const consumer = new BuildCache(tempCacheDir('new-consumer'), { remote, producerVersion: 'nexxo@2.0.0' });
+        expect(await consumer.getAsync('input:src/App.tsx:abc123')).toBeNull();
+    });
+
+    it('keeps synchronous lookups on this machine', async () => {
+        await server.reset();
+        const remote = { url: server.url };
+
+        const producer = new BuildCache(tempCacheDir('sync-producer'), { remote });
+        producer.set('plan:dev:sync', 'plan-json');
+        producer.flushRemote(5000);
+        await server.reset();
+
+        const consumer = new BuildCache(tempCacheDir('sync-consumer'), { remote });
+        expect(consumer.get('plan:dev:sync')).toBeNull();
+        expect(consumer.multiGet(['plan:dev:sync'])).toEqual([null]);
+
+        const { requests } = await server.requests();
+        expect(requests).toHaveLength(0);
+        expect(await consumer.multiGetAsync(['plan:dev:sync'])).toEqual(['plan-json']);
+    });
+
+    it('rejects non-HTTP remote URLs', () => {
+        expect(() => new BuildCache(tempCacheDir('bad-url'), { remote: { url: 'ftp://cache' } })).toThrow(
+            /Remote cache URL must be http/
+        );
+    });
+});
+
+describe('BuildCache remote tier limits and failures', () => {
+    it('caps concurrent uploads', async () => {
+        const server = await startServer({ putDelayMs: 50 });
+        try {
+            const cache = new BuildCache(tempCacheDir('concurrency'), {
+                remote: { url: server.url, protocol: 'Turborepo', maxConcurrentUploads: 2 },
+            });
+            const entries: Record<string, string> = {};
+            for (let i = 0; i < 10; i++) {
+                entries[`artifact:dev:${i}`] = `value-${i}`;
+            }
+            cache.batchSet(entries);
+            expect(cache.flushRemote(10_000)).toBe(true);
+
+            const { maxInFlight } = await server.requests();
+            expect(maxInFlight).toBeGreaterThan(0);
+            expect(maxInFlight).toBeLessThanOrEqual(2);
+            expect(cache.getRemoteStats().uploads).toBe(10);
+        } finally {
+            await server.stop();
+        }
+    });
+
+    it('treats server errors as misses and keeps the local cache working', async () => {
+        const server = await startServer({ failAll: true });
+        try {
+            const cache = new BuildCache(tempCacheDir('failing'), { remote: { url: server.url } });
+
+            cache.set('input:a.ts:1', 'local-value');
+            expect(cache.flushRemote(5000)).toBe(true);
+            expect(cache.get('input:a.ts:1')).toBe('local-value');
+            expect(await cache.getAsync('input:b.ts:2')).toBeNull();
+
+            const stats = cache.getRemoteStats();
+            expect(stats.uploadFailures).toBe(1);
+            expect(stats.downloadFailures).toBe(1);
+        } finally {
+            await server.stop();
+        }
+    });
+});